@@ -0,0 +1,2 @@
+ALTER TABLE grading_task ADD COLUMN trigger VARCHAR NOT NULL DEFAULT 'PUSH';
+ALTER TABLE grading_task ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+ALTER TABLE grading_task ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE grading_task ADD COLUMN last_error VARCHAR;
+ALTER TABLE grading_task ADD COLUMN not_before TIMESTAMPTZ;
@@ -0,0 +1,31 @@
+ALTER TABLE grading_task ADD COLUMN reserved_at TIMESTAMPTZ;
+ALTER TABLE grading_task ADD COLUMN ordered_at TIMESTAMPTZ;
+ALTER TABLE grading_task ADD COLUMN started_at TIMESTAMPTZ;
+
+CREATE TABLE IF NOT EXISTS grading_run (
+  id SERIAL PRIMARY KEY,
+  uuid UUID NOT NULL UNIQUE,
+  user_assignment_id integer NOT NULL,
+  user_provider_login VARCHAR NOT NULL,
+  repository VARCHAR NOT NULL,
+  grader_repository VARCHAR NOT NULL,
+  trigger VARCHAR NOT NULL,
+  last_status VARCHAR NOT NULL,
+  end_status VARCHAR NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  queued_at TIMESTAMPTZ NOT NULL,
+  reserved_at TIMESTAMPTZ,
+  ordered_at TIMESTAMPTZ,
+  started_at TIMESTAMPTZ,
+  ended_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  short_commit_id VARCHAR,
+  commit_url VARCHAR,
+  full_log_url VARCHAR,
+  error VARCHAR,
+  CONSTRAINT fk_grading_run_user_assignment_id
+        FOREIGN KEY(user_assignment_id)
+        REFERENCES user_assignment(id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS grading_run_ended_at_idx ON grading_run (ended_at DESC);
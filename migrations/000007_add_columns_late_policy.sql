@@ -0,0 +1,9 @@
+ALTER TABLE assignment ADD COLUMN late_grace_period_in_secs INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE assignment ADD COLUMN late_penalty_percentage_per_day INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE assignment ADD COLUMN late_hard_cutoff TIMESTAMPTZ;
+
+ALTER TABLE user_assignment ADD COLUMN raw_normalized_grade NUMERIC(4, 2) NOT NULL DEFAULT 0;
+ALTER TABLE user_assignment ADD COLUMN late_days INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE user_assignment ADD COLUMN penalty_percentage INTEGER NOT NULL DEFAULT 0;
+
+UPDATE user_assignment SET raw_normalized_grade = normalized_grade;
@@ -0,0 +1,2 @@
+ALTER TABLE assignment ADD COLUMN grade_aggregation VARCHAR NOT NULL DEFAULT 'BEST';
+ALTER TABLE assignment ADD COLUMN grade_aggregation_last_n INTEGER NOT NULL DEFAULT 3;
@@ -0,0 +1 @@
+ALTER TABLE grading_task ADD COLUMN commit_ref VARCHAR;
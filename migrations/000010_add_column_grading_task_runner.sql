@@ -0,0 +1 @@
+ALTER TABLE grading_task ADD COLUMN runner VARCHAR;
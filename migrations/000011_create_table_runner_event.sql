@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS runner_event (
+  id SERIAL PRIMARY KEY,
+  dedupe_key VARCHAR NOT NULL UNIQUE,
+  task_uuid VARCHAR NOT NULL,
+  status VARCHAR NOT NULL,
+  outcome VARCHAR NOT NULL,
+  received_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS runner_event_task_uuid_idx ON runner_event (task_uuid);
+
+ALTER TABLE assignment ADD COLUMN accept_grades_after_timeout BOOLEAN NOT NULL DEFAULT FALSE;
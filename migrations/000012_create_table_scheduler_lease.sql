@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS scheduler_lease (
+  name VARCHAR PRIMARY KEY,
+  holder VARCHAR NOT NULL,
+  acquired_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_tick_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS scheduled_job (
+  name VARCHAR PRIMARY KEY,
+  enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  run_requested BOOLEAN NOT NULL DEFAULT FALSE,
+  last_started_at TIMESTAMPTZ,
+  last_ended_at TIMESTAMPTZ,
+  last_status VARCHAR,
+  last_error VARCHAR
+);
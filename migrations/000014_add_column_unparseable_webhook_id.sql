@@ -0,0 +1 @@
+ALTER TABLE unparseable_webhook ADD COLUMN IF NOT EXISTS id SERIAL PRIMARY KEY;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+  delivery_id VARCHAR PRIMARY KEY,
+  event VARCHAR NOT NULL,
+  outcome VARCHAR NOT NULL,
+  error VARCHAR,
+  received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  processing_time_in_ms INTEGER,
+  redeliveries INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS webhook_delivery_received_at_idx ON webhook_delivery (received_at);
@@ -0,0 +1 @@
+ALTER TABLE "user" ADD COLUMN IF NOT EXISTS installation_suspended_at TIMESTAMPTZ;
@@ -0,0 +1 @@
+ALTER TABLE assignment ADD COLUMN IF NOT EXISTS push_filter JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+ALTER TABLE assignment ADD COLUMN IF NOT EXISTS grader_ref VARCHAR;
+ALTER TABLE assignment ADD COLUMN IF NOT EXISTS workflow_inputs JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS grade_override (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  assignment_id INTEGER NOT NULL,
+  kind VARCHAR NOT NULL,
+  value NUMERIC(4, 2),
+  reason VARCHAR NOT NULL,
+  teacher_id INTEGER,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_grade_override_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES "user"(id)
+        ON DELETE CASCADE,
+  CONSTRAINT fk_grade_override_assignment_id
+        FOREIGN KEY(assignment_id)
+        REFERENCES assignment(id)
+        ON DELETE CASCADE,
+  CONSTRAINT fk_grade_override_teacher_id
+        FOREIGN KEY(teacher_id)
+        REFERENCES "user"(id)
+        ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS grade_override_user_assignment_idx ON grade_override (user_id, assignment_id);
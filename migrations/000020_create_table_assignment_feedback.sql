@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS assignment_feedback (
+  id SERIAL PRIMARY KEY,
+  uuid UUID DEFAULT gen_random_uuid() NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL,
+  assignment_id INTEGER NOT NULL,
+  teacher_id INTEGER,
+  body TEXT NOT NULL,
+  short_commit_id VARCHAR,
+  published_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_assignment_feedback_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES "user"(id)
+        ON DELETE CASCADE,
+  CONSTRAINT fk_assignment_feedback_assignment_id
+        FOREIGN KEY(assignment_id)
+        REFERENCES assignment(id)
+        ON DELETE CASCADE,
+  CONSTRAINT fk_assignment_feedback_teacher_id
+        FOREIGN KEY(teacher_id)
+        REFERENCES "user"(id)
+        ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS assignment_feedback_user_assignment_idx ON assignment_feedback (user_id, assignment_id);
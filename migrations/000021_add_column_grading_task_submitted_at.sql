@@ -0,0 +1,2 @@
+ALTER TABLE grading_task ADD COLUMN IF NOT EXISTS submitted_at TIMESTAMPTZ;
+ALTER TABLE grading_run ADD COLUMN IF NOT EXISTS submitted_at TIMESTAMPTZ;
@@ -1,3 +1,5 @@
+use crate::grading::GradingBackendKind;
+use anyhow::{anyhow, Context};
 use shuttle_runtime::SecretStore;
 use validator::Validate;
 
@@ -17,12 +19,35 @@ pub struct Config {
     pub github_client_cache_size: usize,
     pub github_runner_app_id: u64,
     pub github_runner_app_private_key: String,
-    pub github_runner_repo_slug: String,
+    #[serde(default)]
+    pub github_runner_repo_slug: Option<String>,
     #[serde(default = "default_github_runner_workflow_id")]
     pub github_runner_workflow_id: String,
-    pub github_runner_installation_id: u64,
+    #[serde(default)]
+    pub github_runner_installation_id: Option<u64>,
+    /// JSON array of [`RunnerConfig`], superseding the single runner `GITHUB_RUNNER_*` parameters
+    #[serde(default)]
+    pub github_runners: Option<String>,
     #[serde(default)]
     pub github_runner_callback_url_override: Option<String>,
+    /// JSON array of [`SelfHostedRunnerConfig`], runners authenticating their callbacks with a shared secret
+    #[serde(default)]
+    pub self_hosted_runners: Option<String>,
+    #[serde(default = "default_runner_signature_tolerance_in_secs")]
+    #[validate(range(min = 1))]
+    pub runner_signature_tolerance_in_secs: i64,
+    /// Issuer of the OIDC tokens presented by GitHub hosted runners, its keys being served under `/.well-known/jwks`
+    #[serde(default = "default_github_oidc_issuer_url")]
+    pub github_oidc_issuer_url: String,
+    #[serde(default = "default_github_jwks_ttl_in_secs")]
+    #[validate(range(min = 1))]
+    pub github_jwks_ttl_in_secs: u64,
+    #[serde(default)]
+    pub grading_backend: GradingBackendKind,
+    #[serde(default)]
+    pub local_grader_command: Option<String>,
+    #[serde(default)]
+    pub local_grader_workdir: Option<String>,
     #[serde(default = "default_scheduler_interval_in_secs")]
     #[validate(range(min = 1))]
     pub scheduler_interval_in_secs: u64,
@@ -38,6 +63,15 @@ pub struct Config {
     #[serde(default = "default_max_parallel_gradings")]
     #[validate(range(min = 1))]
     pub max_parallel_gradings: i32,
+    #[serde(default = "default_deadline_boost_window_in_secs")]
+    #[validate(range(min = 0))]
+    pub deadline_boost_window_in_secs: i32,
+    #[serde(default = "default_grading_dispatch_max_attempts")]
+    #[validate(range(min = 1))]
+    pub grading_dispatch_max_attempts: i32,
+    #[serde(default = "default_grading_dispatch_backoff_in_secs")]
+    #[validate(range(min = 1))]
+    pub grading_dispatch_backoff_in_secs: i32,
     #[cfg(feature = "sentry")]
     pub sentry_dsn: String,
 }
@@ -50,6 +84,18 @@ fn default_github_runner_workflow_id() -> String {
     String::from("grade.yml")
 }
 
+fn default_github_oidc_issuer_url() -> String {
+    String::from("https://token.actions.githubusercontent.com")
+}
+
+const fn default_github_jwks_ttl_in_secs() -> u64 {
+    60 * 60
+}
+
+const fn default_runner_signature_tolerance_in_secs() -> i64 {
+    5 * 60
+}
+
 const fn default_scheduler_interval_in_secs() -> u64 {
     15
 }
@@ -70,6 +116,35 @@ const fn default_max_parallel_gradings() -> i32 {
     3
 }
 
+const fn default_deadline_boost_window_in_secs() -> i32 {
+    24 * 60 * 60
+}
+
+const fn default_grading_dispatch_max_attempts() -> i32 {
+    5
+}
+
+const fn default_grading_dispatch_backoff_in_secs() -> i32 {
+    30
+}
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RunnerConfig {
+    pub slug: String,
+    pub installation_id: u64,
+    #[serde(default = "default_github_runner_workflow_id")]
+    pub workflow_id: String,
+    #[serde(default = "default_max_parallel_gradings")]
+    pub max_parallel_gradings: i32,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SelfHostedRunnerConfig {
+    pub name: String,
+    /// Key of the HMAC-SHA256 signature of the callbacks
+    pub secret: String,
+}
+
 impl Config {
     #[must_use]
     pub fn runner_callback_base_url(&self) -> &str {
@@ -77,6 +152,53 @@ impl Config {
             .as_deref()
             .unwrap_or(&self.base_url)
     }
+
+    pub fn runners(&self) -> anyhow::Result<Vec<RunnerConfig>> {
+        let runners = if let Some(raw_runners) = &self.github_runners {
+            serde_json::from_str::<Vec<RunnerConfig>>(raw_runners)
+                .context("[config] Unparseable GITHUB_RUNNERS")?
+        } else {
+            vec![RunnerConfig {
+                slug: self.github_runner_repo_slug.clone().context(
+                    "[config] GITHUB_RUNNER_REPO_SLUG is required when GITHUB_RUNNERS is not set",
+                )?,
+                installation_id: self.github_runner_installation_id.context(
+                    "[config] GITHUB_RUNNER_INSTALLATION_ID is required when GITHUB_RUNNERS is not set",
+                )?,
+                workflow_id: self.github_runner_workflow_id.clone(),
+                max_parallel_gradings: self.max_parallel_gradings,
+            }]
+        };
+        if runners.is_empty() {
+            Err(anyhow!(
+                "[config] GITHUB_RUNNERS must declare at least one runner"
+            ))?;
+        }
+        if let Some(runner) = runners.iter().find(|r| r.max_parallel_gradings < 1) {
+            Err(anyhow!(
+                "[config] Runner {} must allow at least one parallel grading",
+                runner.slug
+            ))?;
+        }
+        Ok(runners)
+    }
+
+    pub fn self_hosted_runners(&self) -> anyhow::Result<Vec<SelfHostedRunnerConfig>> {
+        let runners = self
+            .self_hosted_runners
+            .as_deref()
+            .map(serde_json::from_str::<Vec<SelfHostedRunnerConfig>>)
+            .transpose()
+            .context("[config] Unparseable SELF_HOSTED_RUNNERS")?
+            .unwrap_or_default();
+        if let Some(runner) = runners.iter().find(|r| r.secret.is_empty()) {
+            Err(anyhow!(
+                "[config] Self-hosted runner {} must have a non-empty secret",
+                runner.name
+            ))?;
+        }
+        Ok(runners)
+    }
 }
 
 impl TryFrom<SecretStore> for Config {
@@ -1,5 +1,11 @@
+use crate::github::runner::{validate_grader_ref, validate_workflow_inputs};
+use crate::repository::grading_task::GradingTrigger;
+use crate::service::grade_aggregation::GradeAggregation;
+use crate::service::push_filter::PushFilter;
 use serde::{Deserialize, Serialize};
 use sqlx::types::Json;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
 use time::{OffsetDateTime, PrimitiveDateTime};
 
@@ -178,6 +184,70 @@ pub struct NewAssignment {
     pub hidden_by_teacher: bool,
     #[cfg_attr(feature = "automatic_test_feature", builder(default))]
     pub grader_cli_v2: bool,
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub late_grace_period_in_secs: i32,
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub late_penalty_percentage_per_day: i32,
+    #[serde(default, with = "entity_time_serde::option")]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub late_hard_cutoff: Option<OffsetDateTime>,
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub grade_aggregation: GradeAggregation,
+    #[serde(default = "default_grade_aggregation_last_n")]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default = "3"))]
+    pub grade_aggregation_last_n: i32,
+    /// Whether grades reported by the runner after the grading timed out are still recorded
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub accept_grades_after_timeout: bool,
+    /// Pushes triggering a grading, those to the default branch only by default
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub push_filter: PushFilter,
+    /// Branch, tag or SHA of the grader to run, its default branch if absent
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub grader_ref: Option<String>,
+    /// Extra inputs of the runner workflow, like the exercise variant handled by a shared grader
+    #[serde(default)]
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub workflow_inputs: BTreeMap<String, String>,
+}
+
+impl validator::Validate for NewAssignment {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        let mut errors = validator::ValidationErrors::new();
+        if let Some(Err(err)) = self.grader_ref.as_deref().map(validate_grader_ref) {
+            errors.add("grader_ref", err);
+        }
+        if let Err(err) = validate_workflow_inputs(&self.workflow_inputs) {
+            errors.add("workflow_inputs", err);
+        }
+        if let Err(err) = self.push_filter.validate() {
+            errors.add(
+                "push_filter",
+                validator::ValidationError::new("glob").with_message(Cow::Owned(err.to_string())),
+            );
+        }
+        if self.grade_aggregation_last_n < 1 {
+            errors.add(
+                "grade_aggregation_last_n",
+                validator::ValidationError::new("range"),
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+const fn default_grade_aggregation_last_n() -> i32 {
+    3
 }
 
 #[derive(sqlx::FromRow, Debug, Clone)]
@@ -196,6 +266,68 @@ pub struct Assignment {
     pub grader_run_url: String,
     pub hidden_by_teacher: bool,
     pub grader_cli_v2: bool,
+    pub late_grace_period_in_secs: i32,
+    pub late_penalty_percentage_per_day: i32,
+    pub late_hard_cutoff: Option<OffsetDateTime>,
+    pub grade_aggregation: String,
+    pub grade_aggregation_last_n: i32,
+    pub accept_grades_after_timeout: bool,
+    pub push_filter: Json<PushFilter>,
+    pub grader_ref: Option<String>,
+    pub workflow_inputs: Json<BTreeMap<String, String>>,
+}
+
+/// Late submission rules of an assignment
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct LatePolicy {
+    pub stop: OffsetDateTime,
+    pub late_grace_period_in_secs: i32,
+    pub late_penalty_percentage_per_day: i32,
+    pub late_hard_cutoff: Option<OffsetDateTime>,
+}
+
+/// What is needed to grade a user assignment
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct GradingRules {
+    #[sqlx(flatten)]
+    pub late_policy: LatePolicy,
+    pub grade_aggregation: String,
+    pub grade_aggregation_last_n: i32,
+    pub grades_history: Json<Vec<InstantGrade>>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct UserAssignmentGradesHistory {
+    pub id: i32,
+    pub grades_history: Json<Vec<InstantGrade>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Lateness {
+    pub late_days: i32,
+    pub penalty_percentage: i32,
+}
+
+impl LatePolicy {
+    /// Submissions within the grace period are not late,
+    /// beyond it every started day after `stop` adds the per-day penalty, up to 100%.
+    #[must_use]
+    pub fn lateness(&self, submitted_at: OffsetDateTime) -> Lateness {
+        const DAY_IN_SECS: i64 = 24 * 60 * 60;
+
+        let late_secs = (submitted_at - self.stop).whole_seconds();
+        if late_secs <= i64::from(self.late_grace_period_in_secs.max(0)) {
+            return Lateness::default();
+        }
+        let late_days =
+            i32::try_from((late_secs + DAY_IN_SECS - 1) / DAY_IN_SECS).unwrap_or(i32::MAX);
+        Lateness {
+            late_days,
+            penalty_percentage: late_days
+                .saturating_mul(self.late_penalty_percentage_per_day)
+                .clamp(0, 100),
+        }
+    }
 }
 
 pub enum NewGradingTask {
@@ -204,10 +336,16 @@ pub enum NewGradingTask {
         user_provider_name: String,
         repository: String,
         grader_repository: String,
+        trigger: GradingTrigger,
+        commit_ref: Option<String>,
     },
     External {
         assignment_uuid: String,
         user_uuid: String,
+        trigger: GradingTrigger,
+        commit_ref: Option<String>,
+        /// When the graded commit was submitted, if known beforehand, the task queuing time otherwise
+        submitted_at: Option<OffsetDateTime>,
     },
 }
 
@@ -226,6 +364,7 @@ pub struct UserModuleDesc {
 
 #[derive(sqlx::FromRow, Debug, Clone)]
 pub struct UnparseableWebhook {
+    pub id: i32,
     pub created_at: OffsetDateTime,
     pub origin: String,
     pub event: String,
@@ -288,6 +427,12 @@ pub struct UserAssignment {
     pub repo_linked: bool,
     pub user_provider_login: String,
     pub normalized_grade: f32,
+    pub raw_normalized_grade: f32,
+    pub late_days: i32,
+    pub penalty_percentage: i32,
+    pub late_grace_period_in_secs: i32,
+    pub late_penalty_percentage_per_day: i32,
+    pub late_hard_cutoff: Option<OffsetDateTime>,
     pub grades_history: Json<Vec<InstantGrade>>,
     pub grading_tasks: Json<Vec<RawGradingTask>>,
     pub grading_in_progress: bool,
@@ -306,6 +451,12 @@ pub struct InstantGrade {
     pub commit_url: String,
     pub grading_log_url: String,
     pub details: Vec<Details>,
+    #[serde(default, with = "entity_time_serde::option")]
+    pub submitted_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub late_days: i32,
+    #[serde(default)]
+    pub penalty_percentage: i32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -318,10 +469,17 @@ pub struct Details {
 
 #[derive(sqlx::FromRow, Debug, Clone)]
 pub struct GradingTask {
+    pub uuid: String,
     pub module_uuid: String,
     pub assignment_uuid: String,
     pub provider_login: String,
     pub status: String,
+    pub trigger: String,
+    pub priority: i32,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub not_before: Option<OffsetDateTime>,
+    pub commit_ref: Option<String>,
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
     pub repository_name: String,
@@ -341,6 +499,12 @@ pub struct GitHubGradingTask {
     pub installation_id: String,
     pub grader_url: String,
     pub grader_cli_v2: bool,
+    pub attempts: i32,
+    /// Commit SHA or ref to grade, the default branch head when absent
+    pub commit_ref: Option<String>,
+    /// Grader version to run, its default branch head when absent
+    pub grader_ref: Option<String>,
+    pub workflow_inputs: Json<BTreeMap<String, String>>,
 }
 
 #[derive(sqlx::FromRow, Deserialize, Debug, Clone)]
@@ -351,8 +515,15 @@ pub struct RawGradingTask {
     pub user_provider_login: String,
     pub repository: String,
     pub status: String,
+    pub trigger: String,
+    #[serde(default, with = "entity_time_serde::option")]
+    pub created_at: Option<OffsetDateTime>,
     #[serde(with = "entity_time_serde")]
     pub updated_at: OffsetDateTime,
+    /// When the graded commit was submitted, if known beforehand
+    #[sqlx(default)]
+    #[serde(default, with = "entity_time_serde::option")]
+    pub submitted_at: Option<OffsetDateTime>,
 }
 
 impl crate::service::trackable::WithTotalCount for GradingTask {
@@ -361,11 +532,190 @@ impl crate::service::trackable::WithTotalCount for GradingTask {
     }
 }
 
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct GradingRun {
+    pub uuid: String,
+    pub module_uuid: String,
+    pub assignment_uuid: String,
+    pub provider_login: String,
+    pub repository: String,
+    pub grader_repository: String,
+    pub trigger: String,
+    pub last_status: String,
+    pub end_status: String,
+    pub attempts: i32,
+    pub queued_at: OffsetDateTime,
+    pub reserved_at: Option<OffsetDateTime>,
+    pub ordered_at: Option<OffsetDateTime>,
+    pub started_at: Option<OffsetDateTime>,
+    pub ended_at: OffsetDateTime,
+    pub queue_duration_in_secs: Option<i32>,
+    pub run_duration_in_secs: Option<i32>,
+    pub short_commit_id: Option<String>,
+    pub commit_url: Option<String>,
+    pub full_log_url: Option<String>,
+    pub error: Option<String>,
+    total_count: i32,
+}
+
+impl crate::service::trackable::WithTotalCount for GradingRun {
+    fn total_count(&self) -> i32 {
+        self.total_count
+    }
+}
+
+/// Grading tasks to act upon, all the given criteria must match.
+#[derive(Debug, Clone, Default)]
+pub struct GradingTaskScope {
+    pub task_uuid: Option<String>,
+    pub module_uuid: Option<String>,
+    pub assignment_uuid: Option<String>,
+    pub user_uuid: Option<String>,
+    pub provider_login: Option<String>,
+    /// Names of the assignment repositories
+    pub repositories: Option<Vec<String>>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct CancelledGradingTask {
+    pub uuid: String,
+    pub user_assignment_id: i32,
+    /// Status at cancellation time
+    pub status: String,
+    /// Known once the grading started
+    pub full_log_url: Option<String>,
+    /// Runner the task was dispatched to, for backends spreading tasks over several ones
+    pub runner: Option<String>,
+    /// When the task was dispatched, if it was
+    pub ordered_at: Option<OffsetDateTime>,
+}
+
+/// A grading which already reached a terminal status
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct EndedGradingRun {
+    pub user_assignment_id: i32,
+    pub trigger: String,
+    pub end_status: String,
+    pub queued_at: OffsetDateTime,
+    pub submitted_at: Option<OffsetDateTime>,
+    pub accept_grades_after_timeout: bool,
+}
+
+/// Instance currently running the scheduler
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct SchedulerLease {
+    pub holder: String,
+    pub acquired_at: OffsetDateTime,
+    pub last_tick_at: OffsetDateTime,
+}
+
+/// Persisted state of a periodic job, absent until the job is first run or configured
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct ScheduledJobState {
+    pub name: String,
+    pub enabled: bool,
+    pub run_requested: bool,
+    pub last_started_at: Option<OffsetDateTime>,
+    pub last_ended_at: Option<OffsetDateTime>,
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Grading task dispatched to a runner, with the metadata reported when it started if any
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct DispatchedGradingTask {
+    pub uuid: String,
+    pub running_grading_metadata: Option<Json<GradingMetadata>>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct RunnerInFlight {
+    pub runner: String,
+    pub count: i32,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct GradingRunStats {
+    pub grader_repository: String,
+    pub count: i32,
+    pub successful: i32,
+    pub errored: i32,
+    pub timed_out: i32,
+    pub queue_duration_p50_in_secs: Option<f32>,
+    pub queue_duration_p95_in_secs: Option<f32>,
+    pub run_duration_p50_in_secs: Option<f32>,
+    pub run_duration_p95_in_secs: Option<f32>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub delivery_id: String,
+    pub event: String,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub received_at: OffsetDateTime,
+    pub processing_time_in_ms: Option<i32>,
+    pub redeliveries: i32,
+    pub total_count: i32,
+}
+
+impl crate::service::trackable::WithTotalCount for WebhookDelivery {
+    fn total_count(&self) -> i32 {
+        self.total_count
+    }
+}
+
+/// Criteria to select webhook deliveries, all optional
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct WebhookDeliveryFilter {
+    pub event: Option<String>,
+    pub outcome: Option<String>,
+    #[serde(default, with = "entity_time_serde::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "entity_time_serde::option")]
+    pub to: Option<OffsetDateTime>,
+}
+
+/// Criteria to select grading runs, all optional
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct GradingRunFilter {
+    pub module_id: Option<String>,
+    pub assignment_id: Option<String>,
+    pub provider_login: Option<String>,
+    pub status: Option<String>,
+    pub trigger: Option<String>,
+    pub grader_repository: Option<String>,
+    #[serde(default, with = "entity_time_serde::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "entity_time_serde::option")]
+    pub to: Option<OffsetDateTime>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct GradingEventContext {
+    pub user_uuid: String,
+    pub module_uuid: String,
+    pub assignment_uuid: String,
+    pub provider_login: String,
+    pub normalized_grade: f32,
+    pub running_grading_metadata: Option<Json<GradingMetadata>>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct GradingMetadata {
     pub short_commit_id: String,
     pub commit_url: String,
     pub full_log_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<GradingProgress>,
+}
+
+/// Last progress reported by the runner of an ongoing grading
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GradingProgress {
+    pub current_part: Option<String>,
+    pub parts: Vec<Details>,
+    pub percentage: Option<f32>,
 }
 
 #[derive(sqlx::FromRow, Debug, Clone)]
@@ -375,6 +725,7 @@ pub struct StudentGrades {
     pub first_name: String,
     pub last_name: String,
     pub school_email: String,
+    pub school_group: String,
     pub provider_login: String,
     pub grades: Json<Vec<AssignmentGrade>>,
     pub total: f32,
@@ -392,10 +743,245 @@ impl fmt::Display for StudentGrades {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct AssignmentGrade {
+    /// Uuid of the assignment
+    #[serde(default)]
+    pub id: String,
     #[serde(rename = "type")]
     pub a_type: String,
     pub name: String,
     pub description: String,
     pub grade: f32,
     pub factor_percentage: i32,
+    #[serde(default)]
+    pub raw_grade: f32,
+    #[serde(default)]
+    pub late_days: i32,
+    #[serde(default)]
+    pub penalty_percentage: i32,
+    /// Grade computed from the runs, before any override
+    #[serde(default)]
+    pub automatic_grade: f32,
+    /// Latest manual change of the grade, if any
+    #[serde(default)]
+    pub grade_override: Option<GradeOverride>,
+}
+
+/// Manual change of a student grade by a teacher, kept apart from the automatic grades history
+#[derive(sqlx::FromRow, Deserialize, Debug, Clone)]
+pub struct GradeOverride {
+    pub id: i32,
+    /// `set`, `adjust` or `reset`
+    pub kind: String,
+    /// Grade on 20 for `set`, added points for `adjust`
+    pub value: Option<f32>,
+    pub reason: String,
+    /// Provider login of the teacher, unknown once deleted
+    pub teacher: Option<String>,
+    #[serde(with = "entity_time_serde")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Written feedback of a teacher on the assignment of a student, hidden from them while a draft
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct AssignmentFeedback {
+    pub uuid: String,
+    /// Markdown
+    pub body: String,
+    /// Graded commit the feedback is about, if any
+    pub short_commit_id: Option<String>,
+    /// Provider login of the author, unknown once deleted
+    pub teacher: Option<String>,
+    pub published_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Deserialize, validator::Validate, Debug, Clone)]
+pub struct NewAssignmentFeedback {
+    #[validate(length(min = 1, max = 65536))]
+    pub body: String,
+    #[serde(default)]
+    #[validate(length(min = 1, max = 40))]
+    pub short_commit_id: Option<String>,
+    /// Drafts are only visible to teachers
+    #[serde(default)]
+    pub published: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewGradeOverride {
+    #[serde(flatten)]
+    pub change: GradeChange,
+    pub reason: String,
+}
+
+/// How a teacher changes a grade, on 20
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GradeChange {
+    /// Replaces the automatic grade
+    Set { grade: f32 },
+    /// Bonus or malus applied on top of the grade set last if any, otherwise of the automatic grade,
+    /// adding up with the previous adjustments
+    Adjust { points: f32 },
+    /// Back to the automatic grade
+    Reset,
+}
+
+impl GradeChange {
+    #[must_use]
+    pub const fn kind(self) -> &'static str {
+        match self {
+            Self::Set { .. } => "set",
+            Self::Adjust { .. } => "adjust",
+            Self::Reset => "reset",
+        }
+    }
+
+    #[must_use]
+    pub const fn value(self) -> Option<f32> {
+        match self {
+            Self::Set { grade } => Some(grade),
+            Self::Adjust { points } => Some(points),
+            Self::Reset => None,
+        }
+    }
+}
+
+impl validator::Validate for NewGradeOverride {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        let mut errors = validator::ValidationErrors::new();
+        let (field, valid) = match self.change {
+            GradeChange::Set { grade } => ("grade", (0.0..=20.0).contains(&grade)),
+            GradeChange::Adjust { points } => ("points", (-20.0..=20.0).contains(&points)),
+            GradeChange::Reset => ("kind", true),
+        };
+        if !valid {
+            errors.add(field, validator::ValidationError::new("range"));
+        }
+        if self.reason.trim().is_empty() {
+            errors.add("reason", validator::ValidationError::new("required"));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use time::Duration;
+
+    fn policy(grace_period_in_secs: i32, penalty_percentage_per_day: i32) -> LatePolicy {
+        LatePolicy {
+            stop: OffsetDateTime::UNIX_EPOCH,
+            late_grace_period_in_secs: grace_period_in_secs,
+            late_penalty_percentage_per_day: penalty_percentage_per_day,
+            late_hard_cutoff: None,
+        }
+    }
+
+    #[test]
+    fn submission_before_stop_or_within_grace_period_is_not_late() {
+        let policy = policy(3600, 10);
+        assert_eq!(
+            policy.lateness(OffsetDateTime::UNIX_EPOCH - Duration::days(1)),
+            Lateness::default()
+        );
+        assert_eq!(
+            policy.lateness(OffsetDateTime::UNIX_EPOCH + Duration::minutes(59)),
+            Lateness::default()
+        );
+    }
+
+    #[test]
+    fn each_started_late_day_adds_penalty() {
+        let policy = policy(3600, 10);
+        assert_eq!(
+            policy.lateness(OffsetDateTime::UNIX_EPOCH + Duration::hours(2)),
+            Lateness {
+                late_days: 1,
+                penalty_percentage: 10
+            }
+        );
+        assert_eq!(
+            policy.lateness(OffsetDateTime::UNIX_EPOCH + Duration::hours(49)),
+            Lateness {
+                late_days: 3,
+                penalty_percentage: 30
+            }
+        );
+    }
+
+    #[test]
+    fn penalty_is_capped_to_100_percent() {
+        assert_eq!(
+            policy(0, 40)
+                .lateness(OffsetDateTime::UNIX_EPOCH + Duration::days(5))
+                .penalty_percentage,
+            100
+        );
+    }
+
+    #[test]
+    fn grade_override_is_parsed_and_validated() {
+        use validator::Validate;
+
+        let adjust: NewGradeOverride =
+            serde_json::from_str(r#"{"kind": "adjust", "points": -2.5, "reason": "Plagiarism"}"#)
+                .unwrap();
+        let out_of_range: NewGradeOverride =
+            serde_json::from_str(r#"{"kind": "set", "grade": 21, "reason": "Oral defence"}"#)
+                .unwrap();
+        let without_reason: NewGradeOverride =
+            serde_json::from_str(r#"{"kind": "reset", "reason": " "}"#).unwrap();
+
+        assert_eq!(adjust.change, GradeChange::Adjust { points: -2.5 });
+        assert!(adjust.validate().is_ok());
+        assert!(out_of_range.validate().is_err());
+        assert!(without_reason.validate().is_err());
+    }
+
+    #[test]
+    fn grade_aggregation_last_n_must_be_positive() {
+        use validator::Validate;
+
+        let assignment = |last_n: &str| -> NewAssignment {
+            serde_json::from_str(&format!(
+                r#"{{"name": "TP1", "description": "", "start": "2024-01-01T00:00:00Z", "stop": "2024-02-01T00:00:00Z",
+                    "type": "GITHUB", "subject_url": "", "grader_url": "", "repository_name": "",
+                    "factor_percentage": 100, "grader_run_url": "", "hidden_by_teacher": false,
+                    "grader_cli_v2": false, "grade_aggregation": "MEAN_OF_LAST_N",
+                    "grade_aggregation_last_n": {last_n}}}"#
+            ))
+            .unwrap()
+        };
+
+        assert!(assignment("1").validate().is_ok());
+        assert!(assignment("0").validate().is_err());
+        assert!(assignment("-3").validate().is_err());
+    }
+
+    #[test]
+    fn push_filter_globs_must_compile() {
+        use validator::Validate;
+
+        let assignment = |glob: &str| -> NewAssignment {
+            serde_json::from_str(&format!(
+                r#"{{"name": "TP1", "description": "", "start": "2024-01-01T00:00:00Z", "stop": "2024-02-01T00:00:00Z",
+                    "type": "GITHUB", "subject_url": "", "grader_url": "", "repository_name": "",
+                    "factor_percentage": 100, "grader_run_url": "", "hidden_by_teacher": false,
+                    "grader_cli_v2": false, "push_filter": {{"paths": ["{glob}"]}}}}"#
+            ))
+            .unwrap()
+        };
+
+        assert!(assignment("src/**").validate().is_ok());
+        let errors = assignment(&"?".repeat(100_000)).validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("push_filter"));
+    }
 }
@@ -7,7 +7,9 @@ use {once_cell::sync::Lazy, regex::Regex};
 
 mod client;
 pub mod client_cache;
+pub(crate) mod jwks;
 pub(crate) mod runner;
+pub(crate) mod runner_pool;
 pub mod webhook_models;
 
 pub fn create_gh_app_client(app_id: u64, key: &str) -> anyhow::Result<Octocrab> {
@@ -37,6 +39,24 @@ pub fn url_to_slug(url: &str) -> Option<GitRepoSlug> {
     })
 }
 
+/// Extracts the repository and the id of a GitHub Actions workflow run from its URL.
+pub fn run_url_to_run_id(url: &str) -> Option<(GitRepoSlug, u64)> {
+    #[allow(clippy::expect_used)]
+    static RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"https://github.com/(?<org>[^/]+)/(?<repo>[^/]+)/actions/runs/(?<run_id>\d+)")
+            .expect("Infallible !")
+    });
+    RE.captures(url).and_then(|caps| {
+        Some((
+            GitRepoSlug {
+                org: caps["org"].to_owned(),
+                repo: caps["repo"].to_owned(),
+            },
+            caps["run_id"].parse().ok()?,
+        ))
+    })
+}
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct GitRepoSlug {
     pub org: String,
@@ -67,7 +87,7 @@ impl FromStr for GitRepoSlug {
 
 #[cfg(test)]
 mod tests {
-    use crate::github::{url_to_slug, GitRepoSlug};
+    use crate::github::{run_url_to_run_id, url_to_slug, GitRepoSlug};
 
     #[test]
     fn gitlab_url_not_matching() {
@@ -92,4 +112,26 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn run_url_matching() {
+        let result =
+            run_url_to_run_id("https://github.com/lernejo/korekto-runner/actions/runs/9255315322");
+        pretty_assertions::assert_eq!(
+            result,
+            Some((
+                GitRepoSlug {
+                    org: "lernejo".to_string(),
+                    repo: "korekto-runner".to_string()
+                },
+                9_255_315_322
+            ))
+        );
+    }
+
+    #[test]
+    fn local_run_url_not_matching() {
+        let result = run_url_to_run_id("local://0b5f3e4c-5b8a-4c5e-8e0f-4b1f0e6f2c1a");
+        pretty_assertions::assert_eq!(result, None);
+    }
 }
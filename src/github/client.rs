@@ -1,4 +1,6 @@
 use octocrab::Octocrab;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone)]
@@ -14,6 +16,39 @@ impl GitHubClient {
     pub async fn current_user(&self) -> anyhow::Result<CustomAuthor> {
         Ok(self.0.get("/user", None::<&()>).await?)
     }
+
+    /// SHA of the last commit of the default branch whose committer date is before `until`.
+    ///
+    /// The committer date is set by the student's machine and may not reflect the actual push time.
+    pub async fn last_commit_dated_before(
+        &self,
+        owner: &str,
+        repo: &str,
+        until: OffsetDateTime,
+    ) -> anyhow::Result<Option<String>> {
+        let commits: Vec<CommitRef> = self
+            .0
+            .get(
+                format!("/repos/{owner}/{repo}/commits"),
+                Some(&CommitsQuery {
+                    until: until.format(&Rfc3339)?,
+                    per_page: 1,
+                }),
+            )
+            .await?;
+        Ok(commits.into_iter().next().map(|commit| commit.sha))
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CommitsQuery {
+    until: String,
+    per_page: u8,
+}
+
+#[derive(serde::Deserialize)]
+struct CommitRef {
+    sha: String,
 }
 
 #[derive(Debug, serde::Deserialize)]
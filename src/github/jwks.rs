@@ -0,0 +1,205 @@
+use crate::config::Config;
+use anyhow::{anyhow, Context};
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Minimum delay between two fetches forced by an unknown key id, so that forged tokens cannot flood the issuer.
+const MIN_FORCED_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keys of the OIDC issuer, refetched once expired or when a token is signed with an unknown key.
+///
+/// An unreachable issuer is not fatal: the last fetched keys are kept until the next successful refresh.
+/// Lookups of fresh known keys never wait for a fetch in progress.
+#[derive(Clone)]
+pub struct JwksCache {
+    jwks_url: String,
+    ttl: Duration,
+    state: Arc<Mutex<JwksState>>,
+    /// Held while fetching, so that callers needing fresh keys wait for a single fetch
+    refreshing: Arc<Mutex<()>>,
+}
+
+#[derive(Default)]
+struct JwksState {
+    jwk_set: Option<JwkSet>,
+    fetched_at: Option<Instant>,
+    last_attempt_at: Option<Instant>,
+}
+
+impl JwksState {
+    fn is_stale(&self, ttl: Duration, now: Instant) -> bool {
+        !matches!(self.fetched_at, Some(fetched_at) if now.duration_since(fetched_at) < ttl)
+    }
+
+    fn may_force_refresh(&self, now: Instant) -> bool {
+        !matches!(self.last_attempt_at, Some(attempt_at) if now.duration_since(attempt_at) < MIN_FORCED_REFRESH_INTERVAL)
+    }
+
+    fn find(&self, kid: &str) -> Option<Jwk> {
+        self.jwk_set
+            .as_ref()
+            .and_then(|jwk_set| jwk_set.find(kid))
+            .cloned()
+    }
+}
+
+impl JwksCache {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            jwks_url: format!(
+                "{}/.well-known/jwks",
+                config.github_oidc_issuer_url.trim_end_matches('/')
+            ),
+            ttl: Duration::from_secs(config.github_jwks_ttl_in_secs),
+            state: Arc::new(Mutex::new(JwksState::default())),
+            refreshing: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Fetches the keys if never fetched or expired, failures are only logged.
+    pub async fn refresh_if_stale(&self) {
+        let _refreshing = self.refreshing.lock().await;
+        let stale = self.state.lock().await.is_stale(self.ttl, Instant::now());
+        if stale {
+            if let Err(err) = self.refresh().await {
+                warn!(error = ?err, url = self.jwks_url, "[jwks] Unable to refresh the key set");
+            }
+        }
+    }
+
+    pub async fn find(&self, kid: &str) -> anyhow::Result<Jwk> {
+        if let Some(jwk) = self.find_fresh(kid).await {
+            return Ok(jwk);
+        }
+
+        let _refreshing = self.refreshing.lock().await;
+        // The keys may have been refreshed while waiting for the lock
+        let (jwk, may_refresh) = {
+            let state = self.state.lock().await;
+            let now = Instant::now();
+            let jwk = state.find(kid);
+            let needs_refresh = jwk.is_none() || state.is_stale(self.ttl, now);
+            (jwk, needs_refresh && state.may_force_refresh(now))
+        };
+        if !may_refresh {
+            return jwk.ok_or_else(|| anyhow!("No JWK matching kid={kid}"));
+        }
+        if jwk.is_none() {
+            // The issuer may have rotated its keys since the last fetch
+            info!("[jwks] Unknown kid={kid}, refreshing the key set");
+        }
+        match (self.refresh().await, jwk) {
+            (Ok(()), _) => self
+                .state
+                .lock()
+                .await
+                .find(kid)
+                .ok_or_else(|| anyhow!("No JWK matching kid={kid}")),
+            (Err(err), Some(jwk)) => {
+                warn!(error = ?err, url = self.jwks_url, "[jwks] Unable to refresh the key set, using the previous one");
+                Ok(jwk)
+            }
+            (Err(err), None) => Err(err),
+        }
+    }
+
+    async fn find_fresh(&self, kid: &str) -> Option<Jwk> {
+        let state = self.state.lock().await;
+        if state.is_stale(self.ttl, Instant::now()) {
+            None
+        } else {
+            state.find(kid)
+        }
+    }
+
+    /// The state is only locked to record the attempt and store the keys, not while fetching them.
+    async fn refresh(&self) -> anyhow::Result<()> {
+        self.state.lock().await.last_attempt_at = Some(Instant::now());
+        let raw_jwk_set = reqwest::Client::new()
+            .get(&self.jwks_url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let jwk_set: JwkSet =
+            serde_json::from_str(&raw_jwk_set).context("[jwks] Unparseable key set")?;
+        info!(
+            "[jwks] Fetched {} keys from {}",
+            jwk_set.keys.len(),
+            self.jwks_url
+        );
+        let mut state = self.state.lock().await;
+        state.jwk_set = Some(jwk_set);
+        state.fetched_at = Some(Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn never_fetched_keys_are_stale() {
+        assert!(JwksState::default().is_stale(Duration::from_secs(60), Instant::now()));
+    }
+
+    #[test]
+    fn keys_expire_after_ttl() {
+        let now = Instant::now();
+        let state = JwksState {
+            fetched_at: Some(now),
+            last_attempt_at: Some(now),
+            ..JwksState::default()
+        };
+
+        assert!(!state.is_stale(Duration::from_secs(60), now + Duration::from_secs(59)));
+        assert!(state.is_stale(Duration::from_secs(60), now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn forced_refreshes_are_rate_limited() {
+        let now = Instant::now();
+        let state = JwksState {
+            last_attempt_at: Some(now),
+            ..JwksState::default()
+        };
+
+        assert!(!state.may_force_refresh(now + Duration::from_secs(1)));
+        assert!(state.may_force_refresh(now + MIN_FORCED_REFRESH_INTERVAL));
+    }
+
+    #[tokio::test]
+    async fn fresh_keys_are_found_while_refreshing() {
+        let jwk_set: JwkSet = serde_json::from_str(
+            r#"{"keys": [{"kty": "RSA", "kid": "k1", "alg": "RS256", "n": "AQAB", "e": "AQAB"}]}"#,
+        )
+        .expect("Valid key set");
+        let cache = JwksCache {
+            jwks_url: "http://localhost/.well-known/jwks".to_string(),
+            ttl: Duration::from_secs(60),
+            state: Arc::new(Mutex::new(JwksState {
+                jwk_set: Some(jwk_set),
+                fetched_at: Some(Instant::now()),
+                last_attempt_at: Some(Instant::now()),
+            })),
+            refreshing: Arc::new(Mutex::new(())),
+        };
+
+        let _refreshing = cache.refreshing.lock().await;
+        let jwk = tokio::time::timeout(Duration::from_secs(1), cache.find("k1"))
+            .await
+            .expect("Not waiting for the refresh")
+            .expect("Known key");
+
+        assert_eq!(jwk.common.key_id.as_deref(), Some("k1"));
+    }
+}
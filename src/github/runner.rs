@@ -1,60 +1,119 @@
-use crate::config::Config;
+use crate::config::{Config, RunnerConfig};
 use crate::entities::GitHubGradingTask;
-use crate::github::url_to_slug;
+use crate::github::{url_to_slug, GitRepoSlug};
+use crate::grading::DispatchError;
 use anyhow::anyhow;
-use jsonwebtoken::{
-    decode, decode_header,
-    jwk::{AlgorithmParameters, JwkSet},
-    Algorithm, DecodingKey, Validation,
+use http::StatusCode;
+use octocrab::{
+    models::{App, Repository},
+    Octocrab, Page,
 };
-use octocrab::{models::Repository, Octocrab, Page};
-use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
-use tracing::info;
+use tracing::{info, warn};
+use validator::ValidationError;
 
+/// Inputs of the grading workflow set on each dispatch, which assignments cannot override
+pub const RESERVED_WORKFLOW_INPUTS: [&str; 8] = [
+    "grader-repo",
+    "student-login",
+    "student-repo",
+    "callback-url",
+    "task-id",
+    "grader-exec-v2",
+    "student-ref",
+    "grader-ref",
+];
+
+/// Maximum number of inputs of a workflow dispatch accepted by GitHub
+const MAX_WORKFLOW_INPUTS: usize = 25;
+
+/// Maximum size of the extra inputs of an assignment, serialized as JSON
+pub const MAX_WORKFLOW_INPUTS_SIZE: usize = 4 * 1024;
+
+/// A repository hosting the grading workflow.
 #[derive(Clone)]
 pub struct Runner {
     org_name: String,
     repo_name: String,
+    workflow_id: String,
+    max_parallel_gradings: i32,
     app_client: Octocrab,
     installation_client: Octocrab,
     config: Config,
-    jwk_set: JwkSet,
 }
 
+/// State of a runner repository, its app details being missing if it could not be reached
 #[derive(serde::Serialize, Debug, Clone)]
 pub struct Metadata {
-    app_id: u64,
-    app_name: String,
+    slug: String,
+    app_id: Option<u64>,
+    app_name: Option<String>,
     accessible_repositories: Vec<String>,
+    max_parallel_gradings: i32,
+    available: bool,
+    error: Option<String>,
 }
 
 impl Runner {
-    pub async fn new(
-        org_name: String,
-        repo_name: String,
+    pub fn new(
+        runner_config: &RunnerConfig,
         app_client: Octocrab,
-        installation_client: Octocrab,
         config: Config,
     ) -> anyhow::Result<Self> {
-        let raw_jwk_set =
-            reqwest::get("https://token.actions.githubusercontent.com/.well-known/jwks")
-                .await?
-                .text()
-                .await?;
-        let jwk_set = serde_json::from_str(&raw_jwk_set)?;
+        let slug = GitRepoSlug::from_str(&runner_config.slug)?;
+        let installation_client = app_client.installation(runner_config.installation_id.into());
         Ok(Self {
-            org_name,
-            repo_name,
+            org_name: slug.org,
+            repo_name: slug.repo,
+            workflow_id: runner_config.workflow_id.clone(),
+            max_parallel_gradings: runner_config.max_parallel_gradings,
             app_client,
             installation_client,
             config,
-            jwk_set,
         })
     }
 
-    pub async fn metadata(&self) -> anyhow::Result<Metadata> {
+    /// `org/repo` slug, identifying the runner
+    #[must_use]
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.org_name, self.repo_name)
+    }
+
+    #[must_use]
+    pub fn org_name(&self) -> &str {
+        &self.org_name
+    }
+
+    #[must_use]
+    pub const fn max_parallel_gradings(&self) -> i32 {
+        self.max_parallel_gradings
+    }
+
+    /// Never fails, an unreachable runner repository being reported in the `error` of its metadata.
+    pub async fn metadata(&self, available: bool) -> Metadata {
+        let (app, accessible_repositories, error) = match self.installation_metadata().await {
+            Ok((app, accessible_repositories)) => (Some(app), accessible_repositories, None),
+            Err(err) => {
+                warn!(error = ?err, runner = self.name(), "Unable to get runner metadata");
+                (None, vec![], Some(format!("{err:#}")))
+            }
+        };
+        Metadata {
+            slug: self.name(),
+            app_id: app.as_ref().map(|app| app.id.into_inner()),
+            app_name: app.map(|app| app.name),
+            accessible_repositories,
+            max_parallel_gradings: self.max_parallel_gradings,
+            available,
+            error,
+        }
+    }
+
+    async fn installation_metadata(&self) -> anyhow::Result<(App, Vec<String>)> {
         let app = self.app_client.current().app().await?;
         let repos: Page<Repository> = self
             .installation_client
@@ -65,16 +124,12 @@ impl Runner {
             .map(|r| {
                 format!(
                     "{}/{}",
-                    r.owner.map_or("<unknown>".to_string(), |o| o.login),
+                    r.owner.map_or_else(|| "<unknown>".to_string(), |o| o.login),
                     r.name
                 )
             })
             .collect();
-        Ok(Metadata {
-            app_id: app.id.into_inner(),
-            app_name: app.name,
-            accessible_repositories,
-        })
+        Ok((app, accessible_repositories))
     }
 
     pub async fn send_grading_command(&self, task: &GitHubGradingTask) -> anyhow::Result<()> {
@@ -91,67 +146,102 @@ impl Runner {
             .unwrap_or(&original_callback_url);
         info!(
             "Triggering remote job: {}/{} - {}",
-            &self.org_name, &self.repo_name, &self.config.github_runner_workflow_id
+            &self.org_name, &self.repo_name, &self.workflow_id
         );
+        let mut inputs = serde_json::json!({
+            "grader-repo": slug.to_string(),
+            "student-login": task.provider_login,
+            "student-repo": task.repository_name,
+            "callback-url": callback_url,
+            "task-id": task.uuid,
+            "grader-exec-v2": task.grader_cli_v2.to_string(),
+        });
+        if let Some(commit_ref) = &task.commit_ref {
+            inputs["student-ref"] = serde_json::Value::from(commit_ref.as_str());
+        }
+        if let Some(grader_ref) = &task.grader_ref {
+            inputs["grader-ref"] = serde_json::Value::from(grader_ref.as_str());
+        }
+        // Validated not to override the inputs above
+        for (name, value) in &task.workflow_inputs.0 {
+            inputs[name.as_str()] = serde_json::Value::from(value.as_str());
+        }
         self.installation_client
             .actions()
-            .create_workflow_dispatch(
-                &self.org_name,
-                &self.repo_name,
-                &self.config.github_runner_workflow_id,
-                "main",
-            )
-            .inputs(serde_json::json!({
-                "grader-repo": slug.to_string(),
-                "student-login": task.provider_login,
-                "student-repo": task.repository_name,
-                "callback-url": callback_url,
-                "task-id": task.uuid,
-                "grader-exec-v2": task.grader_cli_v2.to_string(),
-            }))
+            .create_workflow_dispatch(&self.org_name, &self.repo_name, &self.workflow_id, "main")
+            .inputs(inputs)
             .send()
             .await?;
 
         Ok(())
     }
 
-    pub fn verify_jwt(&self, jwt: &str) -> anyhow::Result<()> {
-        let header = decode_header(jwt)?;
-        let key_store = self
-            .jwk_set
-            .find(
-                header
-                    .kid
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("Mising KID from JWT"))?,
+    pub async fn cancel_workflow_run(&self, run_id: u64) -> anyhow::Result<()> {
+        info!(
+            "Cancelling remote job: {}/{} - {run_id}",
+            &self.org_name, &self.repo_name
+        );
+        self.installation_client
+            .actions()
+            .cancel_workflow_run(&self.org_name, &self.repo_name, run_id.into())
+            .await?;
+        Ok(())
+    }
+
+    /// Id of the not yet completed workflow run grading the given task, if any,
+    /// found by the task uuid carried by the name of the run or of one of its jobs.
+    ///
+    /// Only runs created since `since` are looked at, the run of a task dispatched
+    /// a moment ago possibly not being created yet.
+    pub async fn find_task_run_id(
+        &self,
+        task_uuid: &str,
+        since: Option<OffsetDateTime>,
+    ) -> anyhow::Result<Option<u64>> {
+        let runs: WorkflowRuns = self
+            .installation_client
+            .get(
+                format!(
+                    "/repos/{}/{}/actions/workflows/{}/runs",
+                    &self.org_name, &self.repo_name, &self.workflow_id
+                ),
+                Some(&WorkflowRunsQuery {
+                    created: since
+                        .map(|since| since.format(&Rfc3339).map(|since| format!(">={since}")))
+                        .transpose()?,
+                    per_page: 100,
+                }),
             )
-            .ok_or_else(|| anyhow!("No GH JWK matching kid={:?}", &header.kid))?;
-        let alg = Algorithm::from_str(
-            key_store
-                .common
-                .key_algorithm
-                .ok_or_else(|| {
-                    anyhow!(
-                        "Key {:?} is missing algorithm in JWK set",
-                        key_store.common.key_id
-                    )
-                })?
-                .to_string()
-                .as_str(),
-        )?;
-        let mut validation = Validation::new(alg);
-        validation.set_audience(&[format!("https://github.com/{}", self.org_name)]);
-
-        let token_message =
-            decode::<GitHubClaims>(jwt, &key_store.algorithm.decoding_key()?, &validation)?;
-        if token_message.claims.repository == format!("{}/{}", self.org_name, self.repo_name) {
-            Ok(())
-        } else {
-            Err(anyhow!(
-                "Grading from a rogue repo: {}",
-                token_message.claims.repository
-            ))
+            .await?;
+        for run in runs
+            .workflow_runs
+            .into_iter()
+            .filter(|run| run.status != "completed")
+        {
+            if run.display_title.contains(task_uuid) {
+                return Ok(Some(run.id));
+            }
+            let jobs: WorkflowJobs = self
+                .installation_client
+                .get(
+                    format!(
+                        "/repos/{}/{}/actions/runs/{}/jobs",
+                        &self.org_name, &self.repo_name, run.id
+                    ),
+                    None::<&()>,
+                )
+                .await?;
+            if jobs.jobs.iter().any(|job| job.name.contains(task_uuid)) {
+                return Ok(Some(run.id));
+            }
         }
+        Ok(None)
+    }
+
+    pub async fn dispatch(&self, task: &GitHubGradingTask) -> Result<(), DispatchError> {
+        self.send_grading_command(task)
+            .await
+            .map_err(classify_dispatch_error)
     }
 
     pub fn is_signature_valid(payload: &str, secret: &str, signature: &str) -> anyhow::Result<()> {
@@ -160,6 +250,9 @@ impl Runner {
         type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 
         if let Some((_raw_alg, sig)) = signature.split_once('=') {
+            if sig.len() % 2 != 0 {
+                Err(anyhow!("Malformed signature: odd number of hex digits"))?;
+            }
             #[allow(clippy::expect_used)]
             let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
                 .expect("could not fail, waiting for into_ok() stabilization");
@@ -184,23 +277,136 @@ impl Runner {
     }
 }
 
-trait AlgParams {
-    fn decoding_key(&self) -> anyhow::Result<DecodingKey>;
+#[derive(serde::Serialize)]
+struct WorkflowRunsQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    per_page: u8,
 }
 
-impl AlgParams for AlgorithmParameters {
-    fn decoding_key(&self) -> anyhow::Result<DecodingKey> {
-        match self {
-            Self::RSA(params) => Ok(DecodingKey::from_rsa_components(&params.n, &params.e)?),
-            _ => Err(anyhow!("Unsupported algorithm: {self:?}")),
+#[derive(serde::Deserialize)]
+struct WorkflowRuns {
+    workflow_runs: Vec<WorkflowRun>,
+}
+
+#[derive(serde::Deserialize)]
+struct WorkflowRun {
+    id: u64,
+    status: String,
+    #[serde(default)]
+    display_title: String,
+}
+
+#[derive(serde::Deserialize)]
+struct WorkflowJobs {
+    jobs: Vec<WorkflowJobName>,
+}
+
+#[derive(serde::Deserialize)]
+struct WorkflowJobName {
+    name: String,
+}
+
+fn classify_dispatch_error(err: anyhow::Error) -> DispatchError {
+    let transient = match err.downcast_ref::<octocrab::Error>() {
+        Some(octocrab::Error::GitHub { source, .. }) => {
+            source.status_code.is_server_error()
+                || source.status_code == StatusCode::TOO_MANY_REQUESTS
+                || (source.status_code == StatusCode::FORBIDDEN
+                    && source.message.to_lowercase().contains("rate limit"))
         }
+        Some(octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. }) => true,
+        _ => false,
+    };
+    if transient {
+        DispatchError::Transient(err)
+    } else {
+        DispatchError::Permanent(err)
+    }
+}
+
+/// A grader ref must be a valid branch, tag or SHA, checked out as is by the workflow.
+///
+/// Also used for the commit refs of students and teachers, passed the same way to the runner.
+pub fn validate_grader_ref(grader_ref: &str) -> Result<(), ValidationError> {
+    let valid = !grader_ref.is_empty()
+        && grader_ref.len() <= 255
+        && !grader_ref.starts_with(['-', '/'])
+        && !grader_ref.contains("..")
+        && grader_ref
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'));
+    if valid {
+        Ok(())
+    } else {
+        Err(validation_error(
+            "ref",
+            format!("Invalid ref: {grader_ref:?}"),
+        ))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct GitHubClaims {
-    repository: String,
-    iss: String,
-    #[serde(with = "time::serde::timestamp")]
-    exp: OffsetDateTime,
+pub fn validate_workflow_inputs(inputs: &BTreeMap<String, String>) -> Result<(), ValidationError> {
+    if let Some(name) = inputs
+        .keys()
+        .find(|name| RESERVED_WORKFLOW_INPUTS.contains(&name.as_str()))
+    {
+        return Err(validation_error(
+            "workflow_inputs",
+            format!("Input {name} is set by Korekto"),
+        ));
+    }
+    if inputs.len() + RESERVED_WORKFLOW_INPUTS.len() > MAX_WORKFLOW_INPUTS {
+        return Err(validation_error(
+            "workflow_inputs",
+            format!(
+                "At most {} extra inputs are allowed",
+                MAX_WORKFLOW_INPUTS - RESERVED_WORKFLOW_INPUTS.len()
+            ),
+        ));
+    }
+    let size = serde_json::to_string(inputs).map_or(usize::MAX, |json| json.len());
+    if size > MAX_WORKFLOW_INPUTS_SIZE {
+        return Err(validation_error(
+            "workflow_inputs",
+            format!("Inputs exceed {MAX_WORKFLOW_INPUTS_SIZE} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+fn validation_error(code: &'static str, message: String) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Owned(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn grader_refs_are_validated() {
+        for grader_ref in ["main", "v1.2.0", "release/2024", "8c4863de2f67dd4b"] {
+            assert_eq!(validate_grader_ref(grader_ref), Ok(()), "{grader_ref}");
+        }
+        for grader_ref in ["", "-x", "a..b", "main branch", "a;b"] {
+            assert!(validate_grader_ref(grader_ref).is_err(), "{grader_ref}");
+        }
+    }
+
+    #[test]
+    fn workflow_inputs_cannot_override_reserved_ones() {
+        let inputs = BTreeMap::from([("task-id".to_string(), "other".to_string())]);
+
+        assert!(validate_workflow_inputs(&inputs).is_err());
+    }
+
+    #[test]
+    fn workflow_inputs_are_limited_in_size() {
+        let small = BTreeMap::from([("variant".to_string(), "b".to_string())]);
+        let large = BTreeMap::from([("variant".to_string(), "b".repeat(MAX_WORKFLOW_INPUTS_SIZE))]);
+
+        assert_eq!(validate_workflow_inputs(&small), Ok(()));
+        assert!(validate_workflow_inputs(&large).is_err());
+    }
 }
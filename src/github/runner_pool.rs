@@ -0,0 +1,356 @@
+use crate::config::Config;
+use crate::entities::{CancelledGradingTask, GitHubGradingTask};
+use crate::github::jwks::JwksCache;
+use crate::github::run_url_to_run_id;
+use crate::github::runner::{Metadata, Runner};
+use crate::grading::{DispatchError, Dispatched, GradingBackend, RunnerLoad};
+use anyhow::anyhow;
+use axum::async_trait;
+use jsonwebtoken::{
+    decode, decode_header, jwk::AlgorithmParameters, Algorithm, DecodingKey, Validation,
+};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tracing::warn;
+
+/// Consecutive transient dispatch failures after which a runner is set aside.
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// Duration during which a set aside runner does not receive any task.
+const UNAVAILABILITY_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// Runner repositories between which grading tasks are spread, the least loaded first.
+///
+/// A runner whose dispatches keep failing is set aside for a while, its tasks going to the other ones.
+#[derive(Clone)]
+pub struct RunnerPool {
+    runners: Vec<Runner>,
+    health: Arc<Mutex<HashMap<String, RunnerHealth>>>,
+    jwks: JwksCache,
+    oidc_issuer_url: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+struct RunnerHealth {
+    consecutive_failures: u32,
+    unavailable_until: Option<Instant>,
+}
+
+impl RunnerHealth {
+    fn is_available(&self, now: Instant) -> bool {
+        !matches!(self.unavailable_until, Some(until) if until > now)
+    }
+
+    /// Once past its unavailability, a runner failing again is immediately set aside anew.
+    fn record_failure(&mut self, now: Instant) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            self.unavailable_until = Some(now + UNAVAILABILITY_DURATION);
+        }
+    }
+}
+
+impl RunnerPool {
+    pub async fn new(config: &Config, app_client: &Octocrab) -> anyhow::Result<Self> {
+        let runners = config
+            .runners()?
+            .iter()
+            .map(|runner_config| Runner::new(runner_config, app_client.clone(), config.clone()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // An unreachable issuer is only logged, keys being fetched again on the next callbacks
+        let jwks = JwksCache::new(config);
+        jwks.refresh_if_stale().await;
+        Ok(Self {
+            runners,
+            health: Arc::new(Mutex::new(HashMap::new())),
+            jwks,
+            oidc_issuer_url: config.github_oidc_issuer_url.clone(),
+        })
+    }
+
+    #[must_use]
+    pub const fn jwks(&self) -> &JwksCache {
+        &self.jwks
+    }
+
+    /// Metadata of each runner, a runner which cannot be reached being reported without failing the others.
+    pub async fn metadata(&self) -> anyhow::Result<Vec<Metadata>> {
+        let now = Instant::now();
+        let availabilities = {
+            let health = self.lock_health()?;
+            self.runners
+                .iter()
+                .map(|runner| {
+                    health
+                        .get(&runner.name())
+                        .into_iter()
+                        .all(|h| h.is_available(now))
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut metadata = Vec::with_capacity(self.runners.len());
+        for (runner, available) in self.runners.iter().zip(availabilities) {
+            metadata.push(runner.metadata(available).await);
+        }
+        Ok(metadata)
+    }
+
+    /// Accepts OIDC tokens issued to any of the registered runner repositories.
+    pub async fn verify_jwt(&self, jwt: &str) -> anyhow::Result<()> {
+        let header = decode_header(jwt)?;
+        let key_store = self
+            .jwks
+            .find(
+                header
+                    .kid
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Mising KID from JWT"))?,
+            )
+            .await?;
+        let alg = Algorithm::from_str(
+            key_store
+                .common
+                .key_algorithm
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Key {:?} is missing algorithm in JWK set",
+                        key_store.common.key_id
+                    )
+                })?
+                .to_string()
+                .as_str(),
+        )?;
+        let mut validation = Validation::new(alg);
+        let audiences: Vec<String> = self
+            .runners
+            .iter()
+            .map(|runner| format!("https://github.com/{}", runner.org_name()))
+            .collect();
+        validation.set_audience(&audiences);
+        validation.set_issuer(&[&self.oidc_issuer_url]);
+
+        let token_message =
+            decode::<GitHubClaims>(jwt, &key_store.algorithm.decoding_key()?, &validation)?;
+        if self
+            .runners
+            .iter()
+            .any(|runner| runner.name() == token_message.claims.repository)
+        {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Grading from a rogue repo: {}",
+                token_message.claims.repository
+            ))
+        }
+    }
+
+    fn lock_health(
+        &self,
+    ) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, RunnerHealth>>> {
+        self.health
+            .lock()
+            .map_err(|_| anyhow!("Previous thread using the mutex panicked"))
+    }
+
+    fn runner(&self, runner_name: &str) -> anyhow::Result<&Runner> {
+        self.runners
+            .iter()
+            .find(|runner| runner.name() == runner_name)
+            .ok_or_else(|| anyhow!("Unknown runner: {runner_name}"))
+    }
+
+    fn update_health(&self, runner_name: &str, update: impl FnOnce(&mut RunnerHealth)) {
+        match self.lock_health() {
+            Ok(mut health) => update(health.entry(runner_name.to_string()).or_default()),
+            Err(err) => warn!(error = ?err, "[runner-pool] Unable to update runner health"),
+        }
+    }
+}
+
+#[async_trait]
+impl GradingBackend for RunnerPool {
+    fn capacity(&self) -> Option<i32> {
+        Some(self.runners.iter().map(Runner::max_parallel_gradings).sum())
+    }
+
+    async fn dispatch(
+        &self,
+        task: &GitHubGradingTask,
+        load: &RunnerLoad,
+    ) -> Result<Dispatched, DispatchError> {
+        let order = {
+            let health = self.lock_health().map_err(DispatchError::Transient)?;
+            let slots: Vec<(String, i32)> = self
+                .runners
+                .iter()
+                .map(|runner| (runner.name(), runner.max_parallel_gradings()))
+                .collect();
+            dispatch_order(&slots, load, &health, Instant::now())
+        };
+
+        let mut last_error = None;
+        for runner in order
+            .into_iter()
+            .filter_map(|index| self.runners.get(index))
+        {
+            let runner_name = runner.name();
+            match runner.dispatch(task).await {
+                Ok(()) => {
+                    self.update_health(&runner_name, |health| *health = RunnerHealth::default());
+                    return Ok(Dispatched {
+                        runner: Some(runner_name),
+                        work: None,
+                    });
+                }
+                Err(DispatchError::Transient(err)) => {
+                    warn!(error = ?err, runner = runner_name, task_id = task.uuid, "[runner-pool] Dispatch failed, trying next runner");
+                    self.update_health(&runner_name, |health| {
+                        health.record_failure(Instant::now());
+                    });
+                    last_error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_error.map_or(DispatchError::Saturated, DispatchError::Transient))
+    }
+
+    /// Started gradings are found by their log URL, ordered ones by their task uuid in the runner they were sent to.
+    async fn cancel(&self, task: &CancelledGradingTask) -> anyhow::Result<()> {
+        if let Some(full_log_url) = &task.full_log_url {
+            let (slug, run_id) = run_url_to_run_id(full_log_url)
+                .ok_or_else(|| anyhow!("Not a workflow run URL: {full_log_url}"))?;
+            return self
+                .runner(&slug.to_string())?
+                .cancel_workflow_run(run_id)
+                .await;
+        }
+        let runner_name = task
+            .runner
+            .as_deref()
+            .ok_or_else(|| anyhow!("Runner of task {} unknown", task.uuid))?;
+        let runner = self.runner(runner_name)?;
+        let run_id = runner
+            .find_task_run_id(&task.uuid, task.ordered_at)
+            .await?
+            .ok_or_else(|| anyhow!("No workflow run found for task {}", task.uuid))?;
+        runner.cancel_workflow_run(run_id).await
+    }
+}
+
+/// Indexes of the available runners having free slots, the ones with the most free slots first.
+fn dispatch_order(
+    slots: &[(String, i32)],
+    load: &RunnerLoad,
+    health: &HashMap<String, RunnerHealth>,
+    now: Instant,
+) -> Vec<usize> {
+    let mut order: Vec<(usize, i32)> = slots
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, _))| health.get(name).into_iter().all(|h| h.is_available(now)))
+        .map(|(index, (name, max_parallel_gradings))| {
+            (
+                index,
+                max_parallel_gradings - load.get(name).copied().unwrap_or_default(),
+            )
+        })
+        .filter(|(_, free_slots)| *free_slots > 0)
+        .collect();
+    order.sort_by_key(|(_, free_slots)| std::cmp::Reverse(*free_slots));
+    order.into_iter().map(|(index, _)| index).collect()
+}
+
+trait AlgParams {
+    fn decoding_key(&self) -> anyhow::Result<DecodingKey>;
+}
+
+impl AlgParams for AlgorithmParameters {
+    fn decoding_key(&self) -> anyhow::Result<DecodingKey> {
+        match self {
+            Self::RSA(params) => Ok(DecodingKey::from_rsa_components(&params.n, &params.e)?),
+            _ => Err(anyhow!("Unsupported algorithm: {self:?}")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GitHubClaims {
+    repository: String,
+    iss: String,
+    #[serde(with = "time::serde::timestamp")]
+    exp: OffsetDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn slots() -> Vec<(String, i32)> {
+        vec![
+            ("org/runner-a".to_string(), 2),
+            ("org/runner-b".to_string(), 4),
+            ("org/runner-c".to_string(), 1),
+        ]
+    }
+
+    #[test]
+    fn least_loaded_runners_come_first() {
+        let load = RunnerLoad::from([
+            ("org/runner-a".to_string(), 1),
+            ("org/runner-b".to_string(), 1),
+        ]);
+
+        let order = dispatch_order(&slots(), &load, &HashMap::new(), Instant::now());
+
+        assert_eq!(order, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn full_runners_are_skipped() {
+        let load = RunnerLoad::from([
+            ("org/runner-b".to_string(), 4),
+            ("org/runner-c".to_string(), 1),
+        ]);
+
+        let order = dispatch_order(&slots(), &load, &HashMap::new(), Instant::now());
+
+        assert_eq!(order, vec![0]);
+    }
+
+    #[test]
+    fn repeatedly_failing_runner_is_set_aside_for_a_while() {
+        let now = Instant::now();
+        let mut failing = RunnerHealth::default();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            failing.record_failure(now);
+        }
+        let health = HashMap::from([("org/runner-b".to_string(), failing)]);
+
+        let order = dispatch_order(&slots(), &RunnerLoad::new(), &health, now);
+        assert_eq!(order, vec![0, 2]);
+
+        let order = dispatch_order(
+            &slots(),
+            &RunnerLoad::new(),
+            &health,
+            now + UNAVAILABILITY_DURATION,
+        );
+        assert_eq!(order, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn occasional_failure_keeps_runner_available() {
+        let now = Instant::now();
+        let mut health = RunnerHealth::default();
+        health.record_failure(now);
+
+        assert!(health.is_available(now));
+    }
+}
@@ -1,3 +1,5 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::Deserialize;
 use time::serde::rfc3339 as gh_webhook_time_serde;
 use time::OffsetDateTime;
@@ -20,6 +22,9 @@ pub struct WorkflowJobEvent {
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 pub struct WorkflowJob {
+    pub run_id: u64,
+    /// Name of the job, carrying the uuid of its grading task in runner workflows (`grade ${{ inputs.task-id }}`)
+    pub name: String,
     pub html_url: String,
     pub status: WorkflowJobStatus,
     pub conclusion: Option<WorkflowJobConclusion>,
@@ -45,16 +50,95 @@ pub enum WorkflowJobConclusion {
     Failure,
     Skipped,
     Cancelled,
+    TimedOut,
+    #[serde(other)]
+    Other,
+}
+
+impl WorkflowJob {
+    /// Uuid of the grading task run by this job, if its name carries one
+    #[must_use]
+    pub fn task_id(&self) -> Option<&str> {
+        #[allow(clippy::expect_used)]
+        static RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}")
+                .expect("Infallible !")
+        });
+        RE.find(&self.name).map(|m| m.as_str())
+    }
+
+    /// Error ending the grading task of a job completed without calling back, `None` if the callback may still come
+    #[must_use]
+    pub fn failure(&self) -> Option<&'static str> {
+        if self.status != WorkflowJobStatus::Completed {
+            return None;
+        }
+        match self.conclusion.as_ref()? {
+            WorkflowJobConclusion::Failure => Some("GitHub runner job failed"),
+            WorkflowJobConclusion::Cancelled => Some("GitHub runner job cancelled"),
+            WorkflowJobConclusion::TimedOut => Some("GitHub runner job timed out"),
+            WorkflowJobConclusion::Skipped => Some("GitHub runner job skipped"),
+            WorkflowJobConclusion::Success | WorkflowJobConclusion::Other => None,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 pub struct Push {
     #[serde(rename = "ref")]
     pub git_ref: String,
+    /// SHA of the most recent commit after the push
+    #[serde(default)]
+    pub after: Option<String>,
+    /// Whether the branch or tag was deleted
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub commits: Vec<PushCommit>,
     pub repository: RepositoryWithOwner,
     pub sender: Account,
 }
 
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct PushCommit {
+    #[serde(default)]
+    pub added: Vec<String>,
+    #[serde(default)]
+    pub removed: Vec<String>,
+    #[serde(default)]
+    pub modified: Vec<String>,
+}
+
+impl Push {
+    /// Files changed by the pushed commits, without duplicates
+    #[must_use]
+    pub fn changed_files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = self
+            .commits
+            .iter()
+            .flat_map(|commit| {
+                commit
+                    .added
+                    .iter()
+                    .chain(&commit.removed)
+                    .chain(&commit.modified)
+            })
+            .map(String::as_str)
+            .collect();
+        files.sort_unstable();
+        files.dedup();
+        files
+    }
+
+    #[must_use]
+    pub fn is_to_default_branch(&self) -> bool {
+        self.repository
+            .default_branch
+            .as_deref()
+            .is_some_and(|branch| self.git_ref.strip_prefix("refs/heads/") == Some(branch))
+    }
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 pub struct PushRepository {
     pub name: String,
@@ -76,6 +160,8 @@ pub struct InstallationRepositories {
 pub struct InstallationModification {
     pub action: RepositoryAction,
     pub installation: Installation,
+    /// Absent for `suspend` and `unsuspend` actions
+    #[serde(default)]
     pub repositories: Vec<Repository>,
     pub sender: Account,
 }
@@ -84,6 +170,65 @@ pub struct InstallationModification {
 pub struct RepositoryModification {
     pub action: RepositoryAction,
     pub repository: RepositoryWithOwner,
+    /// Previous values of a `renamed` or `transferred` repository
+    #[serde(default)]
+    pub changes: Option<RepositoryChanges>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct RepositoryChanges {
+    pub repository: Option<RepositoryNameChange>,
+    pub owner: Option<RepositoryOwnerChange>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct RepositoryNameChange {
+    pub name: PreviousValue<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct RepositoryOwnerChange {
+    pub from: PreviousOwner,
+}
+
+/// Either a user or an organization
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct PreviousOwner {
+    pub user: Option<Account>,
+    pub organization: Option<Account>,
+}
+
+impl PreviousOwner {
+    #[must_use]
+    pub fn login(&self) -> Option<&str> {
+        self.user
+            .as_ref()
+            .or(self.organization.as_ref())
+            .map(|account| account.login.as_str())
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct PreviousValue<T> {
+    pub from: T,
+}
+
+impl RepositoryModification {
+    #[must_use]
+    pub fn previous_name(&self) -> Option<&str> {
+        self.changes
+            .as_ref()
+            .and_then(|changes| changes.repository.as_ref())
+            .map(|change| change.name.from.as_str())
+    }
+
+    #[must_use]
+    pub fn previous_owner(&self) -> Option<&str> {
+        self.changes
+            .as_ref()
+            .and_then(|changes| changes.owner.as_ref())
+            .and_then(|change| change.from.login())
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -92,6 +237,14 @@ pub enum RepositoryAction {
     Added,
     Created,
     Removed,
+    Deleted,
+    Renamed,
+    Transferred,
+    Suspend,
+    Unsuspend,
+    /// Actions without effect on gradings (`edited`, `archived`, `new_permissions_accepted`, etc.)
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -128,6 +281,8 @@ pub struct RepositoryWithOwner {
     pub full_name: String,
     pub private: bool,
     pub owner: Account,
+    #[serde(default)]
+    pub default_branch: Option<String>,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -150,9 +305,9 @@ pub fn parse_event(event_type: &str, payload: &str) -> serde_json::Result<GhWebh
 mod tests {
     use crate::github::webhook_models::{
         parse_event, Account, GhWebhookEvent, Installation, InstallationModification,
-        InstallationRepositories, Push, Repository, RepositoryAction, RepositoryModification,
-        RepositorySelection, RepositoryWithOwner, TargetType, WorkflowJob, WorkflowJobEvent,
-        WorkflowJobStatus,
+        InstallationRepositories, Push, PushCommit, Repository, RepositoryAction,
+        RepositoryModification, RepositorySelection, RepositoryWithOwner, TargetType, WorkflowJob,
+        WorkflowJobConclusion, WorkflowJobEvent, WorkflowJobStatus,
     };
     use pretty_assertions::assert_eq;
     use std::fs;
@@ -239,6 +394,13 @@ mod tests {
             result,
             GhWebhookEvent::Push(Push {
                 git_ref: "refs/heads/main".to_string(),
+                after: Some("a31edc36a6f40961ebb9ea90be5df730cd8386cd".to_string()),
+                deleted: false,
+                commits: vec![PushCommit {
+                    added: vec!["README.md".to_string()],
+                    removed: vec![],
+                    modified: vec![],
+                }],
                 repository: RepositoryWithOwner {
                     name: "tutu".to_string(),
                     full_name: "ledoyen/tutu".to_string(),
@@ -246,6 +408,7 @@ mod tests {
                     owner: Account {
                         login: "ledoyen".to_string()
                     },
+                    default_branch: Some("main".to_string()),
                 },
                 sender: Account {
                     login: "ledoyen".to_string()
@@ -271,11 +434,100 @@ mod tests {
                     owner: Account {
                         login: "ledoyen".to_string()
                     },
+                    default_branch: Some("main".to_string()),
                 },
+                changes: None,
             })
         );
     }
 
+    #[test]
+    #[cfg_attr(not(feature = "tests-with-resources"), ignore)]
+    fn parse_repository_renamed_event() {
+        let payload =
+            fs::read_to_string("test_files/webhook_repository_renamed_payload.json").unwrap();
+        let GhWebhookEvent::Repository(modification) = parse_event("repository", &payload).unwrap()
+        else {
+            panic!("Not a repository event");
+        };
+
+        assert_eq!(modification.action, RepositoryAction::Renamed);
+        assert_eq!(modification.repository.name, "titi");
+        assert_eq!(modification.previous_name(), Some("tutu"));
+        assert_eq!(modification.previous_owner(), None);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "tests-with-resources"), ignore)]
+    fn parse_repository_transferred_event() {
+        let payload =
+            fs::read_to_string("test_files/webhook_repository_transferred_payload.json").unwrap();
+        let GhWebhookEvent::Repository(modification) = parse_event("repository", &payload).unwrap()
+        else {
+            panic!("Not a repository event");
+        };
+
+        assert_eq!(modification.action, RepositoryAction::Transferred);
+        assert_eq!(modification.repository.owner.login, "lernejo");
+        assert_eq!(modification.previous_owner(), Some("ledoyen"));
+    }
+
+    fn completed_job(name: &str, conclusion: WorkflowJobConclusion) -> WorkflowJob {
+        WorkflowJob {
+            run_id: 9_160_150_850,
+            name: name.to_string(),
+            html_url:
+                "https://github.com/lernejo/korekto-runner/actions/runs/9160150850/job/25182159656"
+                    .to_string(),
+            status: WorkflowJobStatus::Completed,
+            conclusion: Some(conclusion),
+            created_at: datetime!(2024-05-20 14:19:46 UTC),
+            completed_at: Some(datetime!(2024-05-20 14:21:02 UTC)),
+        }
+    }
+
+    #[test]
+    fn task_id_is_extracted_from_job_name() {
+        let job = completed_job(
+            "grade 0b5f3e4c-5b8a-4c5e-8e0f-4b1f0e6f2c1a",
+            WorkflowJobConclusion::Failure,
+        );
+
+        assert_eq!(job.task_id(), Some("0b5f3e4c-5b8a-4c5e-8e0f-4b1f0e6f2c1a"));
+        assert_eq!(
+            completed_job("grade", WorkflowJobConclusion::Failure).task_id(),
+            None
+        );
+    }
+
+    #[test]
+    fn only_unsuccessful_completed_jobs_fail_their_task() {
+        assert_eq!(
+            completed_job("grade", WorkflowJobConclusion::Cancelled).failure(),
+            Some("GitHub runner job cancelled")
+        );
+        assert_eq!(
+            completed_job("grade", WorkflowJobConclusion::Success).failure(),
+            None
+        );
+        let in_progress = WorkflowJob {
+            status: WorkflowJobStatus::InProgress,
+            conclusion: None,
+            ..completed_job("grade", WorkflowJobConclusion::Success)
+        };
+        assert_eq!(in_progress.failure(), None);
+        let conclusion: WorkflowJobConclusion =
+            serde_json::from_str(r#""startup_failure""#).unwrap();
+        assert_eq!(conclusion, WorkflowJobConclusion::Other);
+    }
+
+    #[test]
+    fn unknown_actions_are_parsed() {
+        let action: RepositoryAction = serde_json::from_str(r#""archived""#).unwrap();
+
+        assert_eq!(action, RepositoryAction::Other);
+    }
+
     #[test]
     #[cfg_attr(not(feature = "tests-with-resources"), ignore)]
     fn parse_workflow_job_event() {
@@ -286,6 +538,8 @@ mod tests {
             result,
             GhWebhookEvent::WorkflowJob(WorkflowJobEvent {
                 workflow_job: WorkflowJob {
+                    run_id: 9_160_150_850,
+                    name: "grade".to_string(),
                     html_url: "https://github.com/lernejo/korekto-runner/actions/runs/9160150850/job/25182159656".to_string(),
                     status: WorkflowJobStatus::Queued,
                     conclusion: None,
@@ -299,6 +553,7 @@ mod tests {
                     owner: Account {
                         login: "lernejo".to_string()
                     },
+                    default_branch: Some("main".to_string()),
                 },
             })
         );
@@ -0,0 +1,74 @@
+use crate::entities::{CancelledGradingTask, GitHubGradingTask};
+use axum::async_trait;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt;
+
+pub mod local;
+pub mod signature;
+
+/// Something able to execute a grading task.
+///
+/// Implementations only need to *start* the grading, the outcome is expected to be reported
+/// asynchronously through [`crate::service::Service::on_runner_webhook`].
+#[async_trait]
+pub trait GradingBackend: Send + Sync {
+    /// Maximum number of gradings in flight, `None` to rely on `MAX_PARALLEL_GRADINGS`.
+    fn capacity(&self) -> Option<i32> {
+        None
+    }
+
+    /// Called while the task is still being reserved, any in-process work being returned to be started once committed.
+    async fn dispatch(
+        &self,
+        task: &GitHubGradingTask,
+        load: &RunnerLoad,
+    ) -> Result<Dispatched, DispatchError>;
+
+    /// Stops a dispatched grading, whether already started or only ordered.
+    ///
+    /// Backends unable to do so let the grading run, its outcome being ignored.
+    async fn cancel(&self, _task: &CancelledGradingTask) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// A grading handed over to a backend.
+#[derive(Default)]
+pub struct Dispatched {
+    /// Name of the runner the task was sent to, for backends spreading tasks over several ones
+    pub runner: Option<String>,
+    /// Grading executed in-process, to be spawned once the task is recorded as ordered
+    pub work: Option<BoxFuture<'static, ()>>,
+}
+
+/// Number of gradings in flight per runner name.
+pub type RunnerLoad = HashMap<String, i32>;
+
+#[derive(serde::Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GradingBackendKind {
+    #[default]
+    Github,
+    Local,
+}
+
+#[derive(Debug)]
+pub enum DispatchError {
+    /// The dispatch may succeed later (network error, rate limiting, remote outage)
+    Transient(anyhow::Error),
+    /// Retrying will not help (invalid grader URL, missing permissions, etc.)
+    Permanent(anyhow::Error),
+    /// No grading slot is free, the task waits for one without counting as an attempt
+    Saturated,
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Transient(err) => write!(f, "transient error: {err:?}"),
+            Self::Permanent(err) => write!(f, "{err:?}"),
+            Self::Saturated => write!(f, "no runner with free capacity"),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::entities::{CancelledGradingTask, GitHubGradingTask};
+use crate::grading::{DispatchError, Dispatched, GradingBackend, RunnerLoad};
+use crate::service::webhook_models::{
+    RunnerGradeDetails, RunnerMetadata, RunnerPayload, RunnerStatus,
+};
+use crate::service::Service;
+use anyhow::anyhow;
+use axum::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, warn};
+
+/// Runs the grader as a local subprocess instead of dispatching a GitHub workflow.
+///
+/// The command is run through `sh -c` with the same parameters as the GitHub workflow inputs,
+/// exposed as environment variables (`GRADER_REPO`, `STUDENT_LOGIN`, `STUDENT_REPO`, `TASK_ID`, `GRADER_EXEC_V2`,
+/// and `STUDENT_REF` when a specific commit is to be graded).
+/// On success, its standard output is expected to be the JSON grading details, as sent by the GitHub runner.
+/// A grader running longer than `timeout`, or whose task is cancelled, is killed.
+#[derive(Clone)]
+pub struct LocalExecutor {
+    command: String,
+    workdir: Option<PathBuf>,
+    timeout: Duration,
+    service: Service,
+    /// Cancellation triggers of the dispatched gradings, by task uuid
+    running: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+}
+
+impl LocalExecutor {
+    #[must_use]
+    pub fn new(
+        command: String,
+        workdir: Option<PathBuf>,
+        timeout: Duration,
+        service: Service,
+    ) -> Self {
+        Self {
+            command,
+            workdir,
+            timeout,
+            service,
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn lock_running(
+        &self,
+    ) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, oneshot::Sender<()>>>> {
+        self.running
+            .lock()
+            .map_err(|_| anyhow!("Previous thread using the mutex panicked"))
+    }
+
+    async fn run(&self, task: GitHubGradingTask, cancelled: oneshot::Receiver<()>) {
+        self.report(&task, RunnerStatus::Started, None).await;
+
+        // The grader is killed when its future is dropped
+        let outcome = tokio::select! {
+            outcome = self.execute(&task) => outcome,
+            Ok(()) = cancelled => {
+                info!(task_id = task.uuid, "[local-executor] Grading cancelled");
+                return;
+            }
+        };
+        match self.lock_running() {
+            Ok(mut running) => drop(running.remove(&task.uuid)),
+            Err(err) => warn!(error = ?err, "[local-executor] Unable to forget grading"),
+        }
+
+        match outcome {
+            Ok(details) => {
+                self.report(&task, RunnerStatus::Completed, Some(details))
+                    .await;
+            }
+            Err(err) => {
+                warn!(error = ?err, task_id = task.uuid, "[local-executor] Grading failed");
+                self.report(&task, RunnerStatus::Failure, None).await;
+            }
+        }
+    }
+
+    async fn execute(&self, task: &GitHubGradingTask) -> anyhow::Result<RunnerGradeDetails> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .env("GRADER_REPO", &task.grader_url)
+            .env("STUDENT_LOGIN", &task.provider_login)
+            .env("STUDENT_REPO", &task.repository_name)
+            .env("TASK_ID", &task.uuid)
+            .env("GRADER_EXEC_V2", task.grader_cli_v2.to_string())
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(commit_ref) = &task.commit_ref {
+            command.env("STUDENT_REF", commit_ref);
+        }
+        if let Some(grader_ref) = &task.grader_ref {
+            command.env("GRADER_REF", grader_ref);
+        }
+        if !task.workflow_inputs.is_empty() {
+            command.env(
+                "WORKFLOW_INPUTS",
+                serde_json::to_string(&task.workflow_inputs)?,
+            );
+        }
+        if let Some(workdir) = &self.workdir {
+            command.current_dir(workdir);
+        }
+
+        info!(
+            "[local-executor] Starting grading of {}/{} (task {})",
+            &task.provider_login, &task.repository_name, &task.uuid
+        );
+        // The child is killed on drop, when timing out
+        let output = tokio::time::timeout(self.timeout, command.output())
+            .await
+            .map_err(|_| anyhow!("Grader timed out after {:?}", self.timeout))??;
+        debug!(
+            task_id = task.uuid,
+            stderr = %String::from_utf8_lossy(&output.stderr),
+            "[local-executor] Grader logs"
+        );
+
+        if output.status.success() {
+            Ok(serde_json::from_slice(&output.stdout)?)
+        } else {
+            Err(anyhow!("Grader exited with {}", output.status))
+        }
+    }
+
+    async fn report(
+        &self,
+        task: &GitHubGradingTask,
+        status: RunnerStatus,
+        details: Option<RunnerGradeDetails>,
+    ) {
+        let payload = RunnerPayload {
+            event_id: None,
+            status,
+            student_login: task.provider_login.clone(),
+            grader_repo: task.grader_url.clone(),
+            task_id: task.uuid.clone(),
+            full_log_url: format!("local://{}", task.uuid),
+            details,
+            progress: None,
+            metadata: RunnerMetadata {
+                commit_id: None,
+                short_commit_id: None,
+                commit_url: None,
+            },
+        };
+        if let Err(err) = self.service.on_runner_webhook(&payload).await {
+            error!(error = ?err, ?payload, "[local-executor] Unable to report grading status");
+        }
+    }
+}
+
+#[async_trait]
+impl GradingBackend for LocalExecutor {
+    async fn dispatch(
+        &self,
+        task: &GitHubGradingTask,
+        _load: &RunnerLoad,
+    ) -> Result<Dispatched, DispatchError> {
+        // Registered before being started, to be cancellable as soon as ordered
+        let (cancel, cancelled) = oneshot::channel();
+        let mut running = self.lock_running().map_err(DispatchError::Transient)?;
+        // Gradings never started, their transaction having failed
+        running.retain(|_, cancel| !cancel.is_closed());
+        running.insert(task.uuid.clone(), cancel);
+        drop(running);
+        let executor = self.clone();
+        let task = task.clone();
+        Ok(Dispatched {
+            runner: None,
+            work: Some(Box::pin(async move { executor.run(task, cancelled).await })),
+        })
+    }
+
+    async fn cancel(&self, task: &CancelledGradingTask) -> anyhow::Result<()> {
+        let cancel = self
+            .lock_running()?
+            .remove(&task.uuid)
+            .ok_or_else(|| anyhow!("Grading of task {} not running", task.uuid))?;
+        // The grading may have just ended
+        let _ = cancel.send(());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use sqlx::postgres::PgPoolOptions;
+    use sqlx::types::Json;
+    use time::OffsetDateTime;
+
+    fn executor(command: &str, timeout: Duration) -> LocalExecutor {
+        // Never connected, status reports failing fast
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://localhost/korekto")
+            .expect("Valid URL");
+        LocalExecutor::new(command.to_string(), None, timeout, Service::new(pool))
+    }
+
+    fn task() -> GitHubGradingTask {
+        GitHubGradingTask {
+            id: 1,
+            uuid: "c0ffee".to_string(),
+            user_assignment_id: 1,
+            provider_login: "student".to_string(),
+            status: "RESERVED".to_string(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+            repository_name: "exercise-1".to_string(),
+            installation_id: "1".to_string(),
+            grader_url: "https://github.com/org/grader".to_string(),
+            grader_cli_v2: true,
+            attempts: 0,
+            commit_ref: None,
+            grader_ref: None,
+            workflow_inputs: Json::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn grader_output_is_parsed_as_grading_details() {
+        let executor = executor(
+            r#"echo "{\"grade\": 4, \"maxGrade\": 5, \"parts\": [], \"student\": \"$STUDENT_LOGIN\"}""#,
+            Duration::from_secs(10),
+        );
+
+        let details = executor.execute(&task()).await.expect("Grading details");
+
+        assert_eq!(details.grade, 4.0);
+        assert_eq!(details.max_grade, 5.0);
+    }
+
+    #[tokio::test]
+    async fn failing_grader_is_an_error() {
+        let executor = executor("echo oops; exit 3", Duration::from_secs(10));
+
+        assert!(executor.execute(&task()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn hung_grader_is_killed() {
+        let executor = executor("sleep 10", Duration::from_millis(100));
+
+        let err = executor
+            .execute(&task())
+            .await
+            .expect_err("Grader timed out");
+
+        assert!(err.to_string().starts_with("Grader timed out"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_grader_is_killed() {
+        let executor = executor("sleep 10", Duration::from_secs(10));
+        let task = task();
+        let work = executor
+            .dispatch(&task, &RunnerLoad::new())
+            .await
+            .expect("Dispatched")
+            .work
+            .expect("Local work");
+        let grading = tokio::spawn(work);
+
+        executor
+            .cancel(&CancelledGradingTask {
+                uuid: task.uuid.clone(),
+                user_assignment_id: task.user_assignment_id,
+                status: "ORDERED".to_string(),
+                full_log_url: None,
+                runner: None,
+                ordered_at: None,
+            })
+            .await
+            .expect("Cancelled");
+
+        tokio::time::timeout(Duration::from_secs(5), grading)
+            .await
+            .expect("Grading stopped before the grader ended")
+            .expect("Grading completed");
+        assert!(executor.lock_running().expect("Lock").is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::config::Config;
+use crate::github::runner::Runner;
+use anyhow::anyhow;
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+/// Authenticates callbacks of self-hosted runners, which cannot provide a GitHub OIDC token.
+///
+/// Each runner signs `{timestamp}.{body}` with its own secret (HMAC-SHA256, hex encoded, prefixed with `sha256=`),
+/// the timestamp (Unix epoch in seconds) preventing a captured callback from being replayed later on.
+#[derive(Clone)]
+pub struct RunnerSignatures {
+    secrets: HashMap<String, String>,
+    tolerance_in_secs: i64,
+}
+
+impl RunnerSignatures {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            secrets: config
+                .self_hosted_runners()?
+                .into_iter()
+                .map(|runner| (runner.name, runner.secret))
+                .collect(),
+            tolerance_in_secs: config.runner_signature_tolerance_in_secs,
+        })
+    }
+
+    pub fn verify(
+        &self,
+        runner: &str,
+        timestamp: &str,
+        signature: &str,
+        payload: &str,
+    ) -> anyhow::Result<()> {
+        self.verify_at(
+            runner,
+            timestamp,
+            signature,
+            payload,
+            OffsetDateTime::now_utc(),
+        )
+    }
+
+    fn verify_at(
+        &self,
+        runner: &str,
+        timestamp: &str,
+        signature: &str,
+        payload: &str,
+        now: OffsetDateTime,
+    ) -> anyhow::Result<()> {
+        let secret = self
+            .secrets
+            .get(runner)
+            .ok_or_else(|| anyhow!("Unknown self-hosted runner: {runner}"))?;
+        let signed_at: i64 = timestamp
+            .parse()
+            .map_err(|_| anyhow!("Unparseable timestamp: {timestamp}"))?;
+        // Not subtracted, the timestamp not being authenticated yet and possibly overflowing
+        if now.unix_timestamp().abs_diff(signed_at) > self.tolerance_in_secs.unsigned_abs() {
+            Err(anyhow!(
+                "Callback of runner {runner} signed at {signed_at}, outside of the accepted window"
+            ))?;
+        }
+        Runner::is_signature_valid(&format!("{timestamp}.{payload}"), secret, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hmac::Mac;
+
+    const PAYLOAD: &str = r#"{"status":"started"}"#;
+
+    fn signatures() -> RunnerSignatures {
+        RunnerSignatures {
+            secrets: HashMap::from([("farm".to_string(), "s3cr3t".to_string())]),
+            tolerance_in_secs: 300,
+        }
+    }
+
+    fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{timestamp}.{payload}").as_bytes());
+        let hex: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("sha256={hex}")
+    }
+
+    #[test]
+    fn valid_signature_is_accepted() {
+        let now = OffsetDateTime::now_utc();
+        let timestamp = now.unix_timestamp() - 10;
+
+        let result = signatures().verify_at(
+            "farm",
+            &timestamp.to_string(),
+            &sign("s3cr3t", timestamp, PAYLOAD),
+            PAYLOAD,
+            now,
+        );
+
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let now = OffsetDateTime::now_utc();
+        let timestamp = now.unix_timestamp();
+
+        let result = signatures().verify_at(
+            "farm",
+            &timestamp.to_string(),
+            &sign("s3cr3t", timestamp, PAYLOAD),
+            r#"{"status":"completed"}"#,
+            now,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn replayed_callback_is_rejected() {
+        let now = OffsetDateTime::now_utc();
+        let timestamp = now.unix_timestamp() - 301;
+
+        let result = signatures().verify_at(
+            "farm",
+            &timestamp.to_string(),
+            &sign("s3cr3t", timestamp, PAYLOAD),
+            PAYLOAD,
+            now,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn signature_of_another_runner_is_rejected() {
+        let now = OffsetDateTime::now_utc();
+        let timestamp = now.unix_timestamp();
+
+        let result = signatures().verify_at(
+            "rogue",
+            &timestamp.to_string(),
+            &sign("s3cr3t", timestamp, PAYLOAD),
+            PAYLOAD,
+            now,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn extreme_timestamps_are_rejected() {
+        let now = OffsetDateTime::now_utc();
+
+        for timestamp in [i64::MIN, i64::MAX] {
+            let result = signatures().verify_at(
+                "farm",
+                &timestamp.to_string(),
+                &sign("s3cr3t", timestamp, PAYLOAD),
+                PAYLOAD,
+                now,
+            );
+
+            assert!(result.is_err());
+        }
+    }
+}
@@ -1,6 +1,7 @@
 pub mod config;
 pub mod entities;
 pub mod github;
+pub mod grading;
 pub mod repository;
 pub mod router;
 pub mod scheduler;
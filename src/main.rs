@@ -16,7 +16,8 @@ async fn main(
     state.service.repo.run_migrations().await?;
 
     let router = router::router(state.clone());
+    let service = state.service.clone();
     let scheduler = Scheduler::new(state);
 
-    KorektoService::new(router, scheduler)
+    KorektoService::new(router, scheduler, service)
 }
@@ -1,12 +1,19 @@
 use sqlx::{PgPool, Postgres, Transaction};
 
+mod assignment_feedback;
 mod db;
 mod delete_users_by_id;
 mod error;
 mod find_user;
 mod find_users;
+mod grade_overrides;
+mod grading_event;
+mod grading_run;
 pub mod grading_task;
 mod migration;
+mod runner_event;
+mod scheduled_job;
+mod scheduler_lease;
 mod set_user_admin;
 mod set_users_teacher;
 mod teacher_assignments;
@@ -16,6 +23,7 @@ mod update_installation_id;
 mod upsert_user;
 mod user_assignments;
 mod user_modules;
+mod webhook_delivery;
 
 pub type PgTransaction<'a> = Transaction<'a, Postgres>;
 
@@ -30,7 +38,7 @@ impl Repository {
         Self { pool }
     }
 
-    pub async fn start_transaction(&self) -> Result<PgTransaction, sqlx::Error> {
+    pub async fn start_transaction(&self) -> Result<PgTransaction<'_>, sqlx::Error> {
         self.pool.begin().await
     }
 }
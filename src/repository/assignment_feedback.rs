@@ -0,0 +1,226 @@
+use crate::entities::{AssignmentFeedback, NewAssignmentFeedback, User};
+use anyhow::Context;
+use tracing::info;
+
+use super::Repository;
+
+impl Repository {
+    /// Writes a feedback to a student, who must be a member of a module of the teacher.
+    ///
+    /// Returns `None` if the assignment or the student is not found.
+    pub async fn insert_assignment_feedback(
+        &self,
+        module_uuid: &str,
+        assignment_uuid: &str,
+        provider_login: &str,
+        feedback: &NewAssignmentFeedback,
+        teacher: &User,
+    ) -> anyhow::Result<Option<AssignmentFeedback>> {
+        const QUERY: &str = "\
+            WITH inserted_feedback AS (
+                INSERT INTO assignment_feedback (user_id, assignment_id, teacher_id, body, short_commit_id, published_at)
+                SELECT u.id, a.id, tm.teacher_id, $5, $6, CASE WHEN $7 THEN NOW() END
+                FROM assignment a
+                JOIN module m ON m.id = a.module_id
+                JOIN teacher_module tm ON tm.module_id = m.id
+                JOIN user_module um ON um.module_id = m.id
+                JOIN \"user\" u ON u.id = um.user_id
+                WHERE
+                  m.uuid::varchar = $1
+                  AND a.uuid::varchar = $2
+                  AND u.provider_login = $3
+                  AND tm.teacher_id = $4
+                RETURNING *
+            )
+            SELECT
+              f.uuid::varchar as uuid,
+              f.body,
+              f.short_commit_id,
+              t.provider_login as teacher,
+              f.published_at,
+              f.created_at,
+              f.updated_at
+            FROM inserted_feedback f
+            LEFT JOIN \"user\" t ON t.id = f.teacher_id
+        ";
+
+        sqlx::query_as::<_, AssignmentFeedback>(QUERY)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .bind(provider_login)
+            .bind(teacher.id)
+            .bind(&feedback.body)
+            .bind(&feedback.short_commit_id)
+            .bind(feedback.published)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("[sql] insert_assignment_feedback(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, provider_login={provider_login:?}, teacher={teacher})"))
+            .inspect(|_| info!("[sql] insert_assignment_feedback(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, provider_login={provider_login:?}, teacher={teacher})"))
+    }
+
+    /// All the feedback written to a student, drafts included, the latest first.
+    pub async fn find_assignment_feedback(
+        &self,
+        module_uuid: &str,
+        assignment_uuid: &str,
+        provider_login: &str,
+        teacher: &User,
+    ) -> anyhow::Result<Vec<AssignmentFeedback>> {
+        const QUERY: &str = "\
+            SELECT
+              f.uuid::varchar as uuid,
+              f.body,
+              f.short_commit_id,
+              t.provider_login as teacher,
+              f.published_at,
+              f.created_at,
+              f.updated_at
+            FROM assignment_feedback f
+            JOIN assignment a ON a.id = f.assignment_id
+            JOIN module m ON m.id = a.module_id
+            JOIN teacher_module tm ON tm.module_id = m.id
+            JOIN \"user\" u ON u.id = f.user_id
+            LEFT JOIN \"user\" t ON t.id = f.teacher_id
+            WHERE
+              m.uuid::varchar = $1
+              AND a.uuid::varchar = $2
+              AND u.provider_login = $3
+              AND tm.teacher_id = $4
+            ORDER BY f.created_at DESC
+        ";
+
+        sqlx::query_as::<_, AssignmentFeedback>(QUERY)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .bind(provider_login)
+            .bind(teacher.id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("[sql] find_assignment_feedback(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, provider_login={provider_login:?}, teacher={teacher})"))
+    }
+
+    /// Edits a feedback, keeping its first publication time while it stays published.
+    ///
+    /// Returns `None` if the feedback is not found in a module of the teacher.
+    pub async fn update_assignment_feedback(
+        &self,
+        module_uuid: &str,
+        assignment_uuid: &str,
+        uuid: &str,
+        feedback: &NewAssignmentFeedback,
+        teacher: &User,
+    ) -> anyhow::Result<Option<AssignmentFeedback>> {
+        const QUERY: &str = "\
+            WITH updated_feedback AS (
+                UPDATE assignment_feedback f SET
+                  body = $5,
+                  short_commit_id = $6,
+                  published_at = CASE WHEN $7 THEN COALESCE(f.published_at, NOW()) END,
+                  updated_at = NOW()
+                FROM assignment a
+                JOIN module m ON m.id = a.module_id
+                JOIN teacher_module tm ON tm.module_id = m.id
+                WHERE
+                  a.id = f.assignment_id
+                  AND m.uuid::varchar = $1
+                  AND a.uuid::varchar = $2
+                  AND f.uuid::varchar = $3
+                  AND tm.teacher_id = $4
+                RETURNING f.*
+            )
+            SELECT
+              f.uuid::varchar as uuid,
+              f.body,
+              f.short_commit_id,
+              t.provider_login as teacher,
+              f.published_at,
+              f.created_at,
+              f.updated_at
+            FROM updated_feedback f
+            LEFT JOIN \"user\" t ON t.id = f.teacher_id
+        ";
+
+        sqlx::query_as::<_, AssignmentFeedback>(QUERY)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .bind(uuid)
+            .bind(teacher.id)
+            .bind(&feedback.body)
+            .bind(&feedback.short_commit_id)
+            .bind(feedback.published)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("[sql] update_assignment_feedback(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, uuid={uuid:?}, teacher={teacher})"))
+    }
+
+    pub async fn delete_assignment_feedback(
+        &self,
+        module_uuid: &str,
+        assignment_uuid: &str,
+        uuid: &str,
+        teacher: &User,
+    ) -> anyhow::Result<u64> {
+        const QUERY: &str = "\
+            DELETE FROM assignment_feedback f
+            USING assignment a, module m, teacher_module tm
+            WHERE
+              a.id = f.assignment_id
+              AND m.id = a.module_id
+              AND tm.module_id = m.id
+              AND m.uuid::varchar = $1
+              AND a.uuid::varchar = $2
+              AND f.uuid::varchar = $3
+              AND tm.teacher_id = $4
+        ";
+
+        sqlx::query(QUERY)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .bind(uuid)
+            .bind(teacher.id)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+            .context(format!("[sql] delete_assignment_feedback(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, uuid={uuid:?}, teacher={teacher})"))
+    }
+
+    /// Published feedback written to the user on one of their assignments, the latest first.
+    ///
+    /// Nothing is returned while the assignment is hidden by the teacher.
+    pub async fn find_published_assignment_feedback(
+        &self,
+        user: &User,
+        module_uuid: &str,
+        assignment_uuid: &str,
+    ) -> anyhow::Result<Vec<AssignmentFeedback>> {
+        const QUERY: &str = "\
+            SELECT
+              f.uuid::varchar as uuid,
+              f.body,
+              f.short_commit_id,
+              t.provider_login as teacher,
+              f.published_at,
+              f.created_at,
+              f.updated_at
+            FROM assignment_feedback f
+            JOIN assignment a ON a.id = f.assignment_id
+            JOIN module m ON m.id = a.module_id
+            LEFT JOIN \"user\" t ON t.id = f.teacher_id
+            WHERE
+              f.user_id = $1
+              AND m.uuid::varchar = $2
+              AND a.uuid::varchar = $3
+              AND f.published_at IS NOT NULL
+              AND a.hidden_by_teacher IS NOT TRUE
+            ORDER BY f.published_at DESC
+        ";
+
+        sqlx::query_as::<_, AssignmentFeedback>(QUERY)
+            .bind(user.id)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("[sql] find_published_assignment_feedback(user={user}, module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?})"))
+    }
+}
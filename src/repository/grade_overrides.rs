@@ -0,0 +1,99 @@
+use crate::entities::{GradeOverride, NewGradeOverride, User};
+use anyhow::Context;
+use tracing::info;
+
+use super::Repository;
+
+impl Repository {
+    /// Records a manual change of the grade of a student, who must be a member of a module of the teacher.
+    ///
+    /// Returns `None` if the assignment or the student is not found.
+    pub async fn insert_grade_override(
+        &self,
+        module_uuid: &str,
+        assignment_uuid: &str,
+        provider_login: &str,
+        grade_override: &NewGradeOverride,
+        teacher: &User,
+    ) -> anyhow::Result<Option<GradeOverride>> {
+        const QUERY: &str = "\
+            WITH inserted_grade_override AS (
+                INSERT INTO grade_override (user_id, assignment_id, kind, value, reason, teacher_id)
+                SELECT u.id, a.id, $5, $6::NUMERIC(4, 2), $7, tm.teacher_id
+                FROM assignment a
+                JOIN module m ON m.id = a.module_id
+                JOIN teacher_module tm ON tm.module_id = m.id
+                JOIN user_module um ON um.module_id = m.id
+                JOIN \"user\" u ON u.id = um.user_id
+                WHERE
+                  m.uuid::varchar = $1
+                  AND a.uuid::varchar = $2
+                  AND u.provider_login = $3
+                  AND tm.teacher_id = $4
+                RETURNING *
+            )
+            SELECT
+              igo.id,
+              igo.kind,
+              igo.value::real as value,
+              igo.reason,
+              t.provider_login as teacher,
+              igo.created_at
+            FROM inserted_grade_override igo
+            LEFT JOIN \"user\" t ON t.id = igo.teacher_id
+        ";
+
+        sqlx::query_as::<_, GradeOverride>(QUERY)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .bind(provider_login)
+            .bind(teacher.id)
+            .bind(grade_override.change.kind())
+            .bind(grade_override.change.value())
+            .bind(&grade_override.reason)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!("[sql] insert_grade_override(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, provider_login={provider_login:?}, grade_override={grade_override:?}, teacher={teacher})"))
+            .inspect(|_| info!("[sql] insert_grade_override(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, provider_login={provider_login:?}, grade_override={grade_override:?}, teacher={teacher})"))
+    }
+
+    /// All the manual changes of the grade of a student, the latest first.
+    pub async fn find_grade_overrides(
+        &self,
+        module_uuid: &str,
+        assignment_uuid: &str,
+        provider_login: &str,
+        teacher: &User,
+    ) -> anyhow::Result<Vec<GradeOverride>> {
+        const QUERY: &str = "\
+            SELECT
+              go.id,
+              go.kind,
+              go.value::real as value,
+              go.reason,
+              t.provider_login as teacher,
+              go.created_at
+            FROM grade_override go
+            JOIN assignment a ON a.id = go.assignment_id
+            JOIN module m ON m.id = a.module_id
+            JOIN teacher_module tm ON tm.module_id = m.id
+            JOIN \"user\" u ON u.id = go.user_id
+            LEFT JOIN \"user\" t ON t.id = go.teacher_id
+            WHERE
+              m.uuid::varchar = $1
+              AND a.uuid::varchar = $2
+              AND u.provider_login = $3
+              AND tm.teacher_id = $4
+            ORDER BY go.id DESC
+        ";
+
+        sqlx::query_as::<_, GradeOverride>(QUERY)
+            .bind(module_uuid)
+            .bind(assignment_uuid)
+            .bind(provider_login)
+            .bind(teacher.id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("[sql] find_grade_overrides(module_uuid={module_uuid:?}, assignment_uuid={assignment_uuid:?}, provider_login={provider_login:?}, teacher={teacher})"))
+    }
+}
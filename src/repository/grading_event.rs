@@ -0,0 +1,32 @@
+use crate::repository::Repository;
+use anyhow::Context;
+use sqlx::postgres::PgListener;
+
+/// Postgres channel through which grading events reach the subscribers of all instances
+const GRADING_EVENTS_CHANNEL: &str = "grading_events";
+
+impl Repository {
+    pub async fn notify_grading_event(&self, payload: &str) -> anyhow::Result<()> {
+        const QUERY: &str = "SELECT pg_notify($1, $2)";
+
+        sqlx::query(QUERY)
+            .bind(GRADING_EVENTS_CHANNEL)
+            .bind(payload)
+            .execute(&self.pool)
+            .await
+            .context(format!("[sql] notify_grading_event(payload={payload:?})"))?;
+        Ok(())
+    }
+
+    /// Dedicated connection receiving the notified grading events, reconnected on the next receive when lost.
+    pub async fn listen_grading_events(&self) -> anyhow::Result<PgListener> {
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .context("[sql] listen_grading_events()")?;
+        listener
+            .listen(GRADING_EVENTS_CHANNEL)
+            .await
+            .context("[sql] listen_grading_events()")?;
+        Ok(listener)
+    }
+}
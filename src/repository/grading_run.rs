@@ -0,0 +1,221 @@
+use crate::entities::{
+    EndedGradingRun, GradingMetadata, GradingRun, GradingRunFilter, GradingRunStats, User,
+};
+use crate::repository::grading_task::GradingStatus;
+use crate::repository::Repository;
+use anyhow::Context;
+use const_format::formatcp;
+use sqlx::types::Json;
+use sqlx::{Executor, Postgres};
+use tracing::info;
+
+/// Shared by the listing and stats queries, filter parameters are bound from `$1` to `$9`
+const GRADING_RUN_FILTERED_FROM: &str = "\
+    FROM grading_run gr
+    JOIN user_assignment ua ON ua.id = gr.user_assignment_id
+    JOIN assignment a ON a.id = ua.assignment_id
+    JOIN module m ON m.id = a.module_id
+    WHERE ($1::varchar IS NULL OR m.uuid::varchar = $1)
+      AND ($2::varchar IS NULL OR a.uuid::varchar = $2)
+      AND ($3::varchar IS NULL OR gr.user_provider_login = $3)
+      AND ($4::varchar IS NULL OR gr.end_status = $4)
+      AND ($5::varchar IS NULL OR gr.trigger = $5)
+      AND ($6::varchar IS NULL OR gr.grader_repository = $6)
+      AND ($7::timestamptz IS NULL OR gr.ended_at >= $7)
+      AND ($8::timestamptz IS NULL OR gr.ended_at < $8)
+      AND ($9::integer IS NULL OR EXISTS (
+        SELECT 1 FROM teacher_module tm WHERE tm.module_id = m.id AND tm.teacher_id = $9
+      ))
+";
+
+impl Repository {
+    /// Lists the ended gradings matching the given filter, most recent first.
+    ///
+    /// If a `teacher` is given, only runs of modules they own are returned.
+    pub async fn get_grading_runs(
+        &self,
+        filter: &GradingRunFilter,
+        teacher: Option<&User>,
+        page: i32,
+        per_page: i32,
+    ) -> anyhow::Result<Vec<GradingRun>> {
+        const QUERY: &str = formatcp!(
+            "\
+            SELECT
+              gr.uuid::varchar as uuid,
+              m.uuid::varchar as module_uuid,
+              a.uuid::varchar as assignment_uuid,
+              gr.user_provider_login as provider_login,
+              gr.repository,
+              gr.grader_repository,
+              gr.trigger,
+              gr.last_status,
+              gr.end_status,
+              gr.attempts,
+              gr.queued_at,
+              gr.reserved_at,
+              gr.ordered_at,
+              gr.started_at,
+              gr.ended_at,
+              EXTRACT(EPOCH FROM (gr.ordered_at - gr.queued_at))::integer as queue_duration_in_secs,
+              EXTRACT(EPOCH FROM (gr.ended_at - gr.started_at))::integer as run_duration_in_secs,
+              gr.short_commit_id,
+              gr.commit_url,
+              gr.full_log_url,
+              gr.error,
+              (count(*) OVER ())::integer as total_count
+            {GRADING_RUN_FILTERED_FROM}
+            ORDER BY gr.ended_at DESC
+            LIMIT $10
+            OFFSET $11
+        "
+        );
+
+        let offset = if page == 1 { 0 } else { (page - 1) * per_page };
+        let teacher_id = teacher.map(|t| t.id);
+
+        sqlx::query_as::<_, GradingRun>(QUERY)
+            .bind(&filter.module_id)
+            .bind(&filter.assignment_id)
+            .bind(&filter.provider_login)
+            .bind(&filter.status)
+            .bind(&filter.trigger)
+            .bind(&filter.grader_repository)
+            .bind(filter.from)
+            .bind(filter.to)
+            .bind(teacher_id)
+            .bind(per_page)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!(
+                "[sql] get_grading_runs(filter={filter:?}, teacher_id={teacher_id:?}, page={page:?}, per_page={per_page:?})"
+            ))
+            .inspect(|res| {
+                info!(
+                    "[sql] get_grading_runs(filter={filter:?}, teacher_id={teacher_id:?}, page={page:?}, per_page={per_page:?}): {} items",
+                    res.len()
+                );
+            })
+    }
+
+    /// Outcome counts and latency percentiles of the runs matching the given filter, per grader.
+    pub async fn get_grading_run_stats(
+        &self,
+        filter: &GradingRunFilter,
+    ) -> anyhow::Result<Vec<GradingRunStats>> {
+        const QUERY: &str = formatcp!(
+            "\
+            SELECT
+              gr.grader_repository,
+              count(*)::integer as count,
+              (count(*) FILTER (WHERE gr.end_status = $10))::integer as successful,
+              (count(*) FILTER (WHERE gr.end_status = $11))::integer as errored,
+              (count(*) FILTER (WHERE gr.end_status = $12))::integer as timed_out,
+              (percentile_cont(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM (gr.ordered_at - gr.queued_at))))::real as queue_duration_p50_in_secs,
+              (percentile_cont(0.95) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM (gr.ordered_at - gr.queued_at))))::real as queue_duration_p95_in_secs,
+              (percentile_cont(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM (gr.ended_at - gr.started_at))))::real as run_duration_p50_in_secs,
+              (percentile_cont(0.95) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM (gr.ended_at - gr.started_at))))::real as run_duration_p95_in_secs
+            {GRADING_RUN_FILTERED_FROM}
+            GROUP BY gr.grader_repository
+            ORDER BY count DESC
+        "
+        );
+
+        sqlx::query_as::<_, GradingRunStats>(QUERY)
+            .bind(&filter.module_id)
+            .bind(&filter.assignment_id)
+            .bind(&filter.provider_login)
+            .bind(&filter.status)
+            .bind(&filter.trigger)
+            .bind(&filter.grader_repository)
+            .bind(filter.from)
+            .bind(filter.to)
+            .bind(None::<i32>)
+            .bind(GradingStatus::SUCCESSFUL.to_string())
+            .bind(GradingStatus::ERROR.to_string())
+            .bind(GradingStatus::TIMEOUT.to_string())
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("[sql] get_grading_run_stats(filter={filter:?})"))
+    }
+
+    pub async fn is_grading_cancelled(&self, uuid: &str) -> anyhow::Result<bool> {
+        const QUERY: &str = "\
+            SELECT EXISTS (
+              SELECT 1 FROM grading_run WHERE uuid::varchar = $1 AND end_status = $2
+            )
+        ";
+
+        sqlx::query_scalar(QUERY)
+            .bind(uuid)
+            .bind(GradingStatus::CANCELLED.to_string())
+            .fetch_one(&self.pool)
+            .await
+            .context(format!("[sql] is_grading_cancelled(uuid={uuid:?})"))
+    }
+
+    /// Locks the run until the end of the transaction, so that concurrent late events are applied once.
+    pub async fn find_ended_grading_run_transact<'e, 'c: 'e, E>(
+        uuid: &str,
+        transaction: E,
+    ) -> anyhow::Result<Option<EndedGradingRun>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            SELECT
+              gr.user_assignment_id,
+              gr.trigger,
+              gr.end_status,
+              gr.queued_at,
+              gr.submitted_at,
+              a.accept_grades_after_timeout
+            FROM grading_run gr
+            JOIN user_assignment ua ON ua.id = gr.user_assignment_id
+            JOIN assignment a ON a.id = ua.assignment_id
+            WHERE gr.uuid::varchar = $1
+            FOR UPDATE OF gr
+        ";
+
+        sqlx::query_as::<_, EndedGradingRun>(QUERY)
+            .bind(uuid)
+            .fetch_optional(transaction)
+            .await
+            .context(format!(
+                "[sql] find_ended_grading_run_transact(uuid={uuid:?})"
+            ))
+    }
+
+    /// Marks a timed out run as successful, its grade having been received afterward.
+    pub async fn record_late_grading_success_transact<'e, 'c: 'e, E>(
+        uuid: &str,
+        grading_metadata: &GradingMetadata,
+        transaction: E,
+    ) -> anyhow::Result<()>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            UPDATE grading_run
+            SET
+              end_status = $2,
+              short_commit_id = $3::jsonb->>'short_commit_id',
+              commit_url = $3::jsonb->>'commit_url',
+              full_log_url = $3::jsonb->>'full_log_url'
+            WHERE uuid::varchar = $1
+        ";
+
+        sqlx::query(QUERY)
+            .bind(uuid)
+            .bind(GradingStatus::SUCCESSFUL.to_string())
+            .bind(Json(grading_metadata))
+            .execute(transaction)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] record_late_grading_success_transact(uuid={uuid:?}, grading_metadata={grading_metadata:?})"
+            ))
+            .inspect(|()| info!("[sql] record_late_grading_success_transact(uuid={uuid:?})"))
+    }
+}
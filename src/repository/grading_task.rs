@@ -18,14 +18,26 @@
 //! (source https://arthursonzogni.com/Diagon/#GraphDAG)
 //! queued -> reserved -> ordered -> started -> successful
 //! started -> error
+//! ordered -> error (when its workflow job fails before calling back)
 //! queued -> error
 //! reserved  -> error
 //! ```
+//!
+//! Any non-terminal task can also be cancelled by its student, a teacher of the module or an admin.
+//!
+//! Tasks reaching a terminal status (including `timeout` and `cancelled`) are moved to the `grading_run` table.
+//!
+//! Runner events not following from the current status of their task (duplicated, late, out of order) are ignored,
+//! except for grades received after a timeout if the assignment accepts them.
 
-use crate::entities::{GitHubGradingTask, GradingTask, NewGradingTask, RawGradingTask};
+use crate::entities::{
+    CancelledGradingTask, DispatchedGradingTask, GitHubGradingTask, GradingMetadata, GradingTask,
+    GradingTaskScope, NewGradingTask, RawGradingTask, RunnerInFlight, User,
+};
 use crate::repository::Repository;
 use anyhow::{anyhow, Context};
 use serde::Serialize;
+use sqlx::types::Json;
 use sqlx::{Executor, Postgres};
 use std::fmt;
 use std::str::FromStr;
@@ -39,6 +51,8 @@ pub enum GradingStatus {
     ORDERED,
     STARTED,
     ERROR,
+    TIMEOUT,
+    CANCELLED,
     SUCCESSFUL,
 }
 
@@ -50,7 +64,7 @@ impl Terminal for GradingStatus {
     fn is_terminal(&self) -> bool {
         match self {
             Self::QUEUED | Self::RESERVED | Self::ORDERED | Self::STARTED => false,
-            Self::ERROR | Self::SUCCESSFUL => true,
+            Self::ERROR | Self::TIMEOUT | Self::CANCELLED | Self::SUCCESSFUL => true,
         }
     }
 }
@@ -65,6 +79,8 @@ impl FromStr for GradingStatus {
             "ORDERED" => Ok(Self::ORDERED),
             "STARTED" => Ok(Self::STARTED),
             "ERROR" => Ok(Self::ERROR),
+            "TIMEOUT" => Ok(Self::TIMEOUT),
+            "CANCELLED" => Ok(Self::CANCELLED),
             "SUCCESSFUL" => Ok(Self::SUCCESSFUL),
             _ => Err(()),
         }
@@ -77,24 +93,99 @@ impl fmt::Display for GradingStatus {
     }
 }
 
+/// Priority boost given to tasks of assignments close to their `stop` date.
+///
+/// It is larger than the gap between two trigger classes, so that near a deadline a teacher bulk grading
+/// goes before pushes on other assignments, and a push before student requests on other assignments.
+pub const DEADLINE_PRIORITY_BOOST: i32 = 15;
+
+/// What caused a grading task to be queued
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GradingTrigger {
+    /// Event received from GitHub (push, repository created, etc.)
+    PUSH,
+    /// Student explicitly asking for a grading
+    STUDENT,
+    /// Bulk grading triggered by a teacher for a whole assignment
+    TEACHER,
+}
+
+impl GradingTrigger {
+    /// Base priority stored on the task, higher is executed first
+    #[must_use]
+    pub const fn priority(&self) -> i32 {
+        match self {
+            Self::STUDENT => 20,
+            Self::PUSH => 10,
+            Self::TEACHER => 0,
+        }
+    }
+}
+
+impl FromStr for GradingTrigger {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "PUSH" => Ok(Self::PUSH),
+            "STUDENT" => Ok(Self::STUDENT),
+            "TEACHER" => Ok(Self::TEACHER),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GradingTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Submissions are accepted from `start` until the hard cutoff if any,
+/// with no end if late penalties apply, or until the end of the grace period otherwise
+const TIME_WINDOW_CLAUSE: &str = "\
+          AND NOW() >= a.start
+          AND NOW() <= COALESCE(
+            a.late_hard_cutoff,
+            CASE
+              WHEN a.late_penalty_percentage_per_day > 0 THEN 'infinity'::timestamptz
+              ELSE a.stop + interval '1 seconds' * a.late_grace_period_in_secs
+            END
+          )";
+
+/// A task already queued keeps the highest priority of all the requests it merges,
+/// and the commit to grade (with its submission time) of the most important (or else latest) one
+const UPSERT_CONFLICT_CLAUSE: &str = "\
+        ON CONFLICT (user_assignment_id, user_provider_login, status) DO UPDATE
+        SET
+          updated_at = NOW(),
+          trigger = CASE WHEN EXCLUDED.priority > grading_task.priority THEN EXCLUDED.trigger ELSE grading_task.trigger END,
+          commit_ref = CASE WHEN EXCLUDED.priority >= grading_task.priority THEN EXCLUDED.commit_ref ELSE grading_task.commit_ref END,
+          submitted_at = CASE WHEN EXCLUDED.priority >= grading_task.priority THEN EXCLUDED.submitted_at ELSE grading_task.submitted_at END,
+          priority = GREATEST(EXCLUDED.priority, grading_task.priority)";
+
 impl Repository {
     pub async fn upsert_grading_task(
         &self,
         task: &NewGradingTask,
         enforce_time_window: bool,
-    ) -> anyhow::Result<Option<OffsetDateTime>> {
+    ) -> anyhow::Result<Option<RawGradingTask>> {
         match task {
             NewGradingTask::Internal {
                 user_assignment_id,
                 user_provider_name,
                 repository,
                 grader_repository,
+                trigger,
+                commit_ref,
             } => {
                 self.upsert_grading_task_internal(
                     *user_assignment_id,
                     user_provider_name,
                     repository,
                     grader_repository,
+                    trigger,
+                    commit_ref.as_deref(),
                     enforce_time_window,
                 )
                 .await
@@ -102,70 +193,86 @@ impl Repository {
             NewGradingTask::External {
                 assignment_uuid,
                 user_uuid,
+                trigger,
+                commit_ref,
+                submitted_at,
             } => {
-                self.upsert_grading_task_external(assignment_uuid, user_uuid, enforce_time_window)
-                    .await
+                self.upsert_grading_task_external(
+                    assignment_uuid,
+                    user_uuid,
+                    trigger,
+                    commit_ref.as_deref(),
+                    *submitted_at,
+                    enforce_time_window,
+                )
+                .await
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn upsert_grading_task_internal(
         &self,
         user_assignment_id: i32,
         user_provider_name: &str,
         repository: &str,
         grader_repository: &str,
+        trigger: &GradingTrigger,
+        commit_ref: Option<&str>,
         enforce_time_window: bool,
-    ) -> anyhow::Result<Option<OffsetDateTime>> {
+    ) -> anyhow::Result<Option<RawGradingTask>> {
         let time_window_clause = if enforce_time_window {
-            "AND NOW() BETWEEN a.start AND a.stop "
+            TIME_WINDOW_CLAUSE
         } else {
             ""
         };
 
         let query = format!("INSERT INTO grading_task
-          (user_assignment_id, user_provider_login, status, repository, grader_repository, updated_at)
-        SELECT ua.id, $2, $3, $4, $5, NOW())
+          (user_assignment_id, user_provider_login, status, repository, grader_repository, updated_at, trigger, priority, commit_ref)
+        SELECT ua.id, $2, $3, $4, $5, NOW(), $6, $7, $8
         FROM user_assignment ua, assignment a
         WHERE
           ua.id = $1
           AND ua.assignment_id = a.id
           {time_window_clause}
-        ON CONFLICT (user_assignment_id, user_provider_login, status) DO UPDATE
-        SET updated_at = NOW()
+        {UPSERT_CONFLICT_CLAUSE}
         RETURNING *, uuid::varchar as uuid");
 
-        let result = sqlx::query_as::<_, RawGradingTask>(&query)
+        sqlx::query_as::<_, RawGradingTask>(&query)
             .bind(user_assignment_id)
             .bind(user_provider_name)
             .bind(GradingStatus::QUEUED.to_string())
             .bind(repository)
             .bind(grader_repository)
+            .bind(trigger.to_string())
+            .bind(trigger.priority())
+            .bind(commit_ref)
             .fetch_optional(&self.pool)
             .await
-            .context(format!("[sql] upsert_grading_task_internal(user_assignment_id={user_assignment_id:?}, user_provider_name={user_provider_name:?}, repository={repository:?}, grader_repository={grader_repository:?})"))
+            .context(format!("[sql] upsert_grading_task_internal(user_assignment_id={user_assignment_id:?}, user_provider_name={user_provider_name:?}, repository={repository:?}, grader_repository={grader_repository:?}, trigger={trigger:?}, commit_ref={commit_ref:?})"))
             .inspect(|res|
-                info!("[sql] upsert_grading_task_internal(user_assignment_id={user_assignment_id:?}, user_provider_name={user_provider_name:?}, repository={repository:?}, grader_repository={grader_repository:?}): {res:?}")
-            )?;
-
-        Ok(result.map(|rgt| rgt.updated_at))
+                info!("[sql] upsert_grading_task_internal(user_assignment_id={user_assignment_id:?}, user_provider_name={user_provider_name:?}, repository={repository:?}, grader_repository={grader_repository:?}, trigger={trigger:?}, commit_ref={commit_ref:?}): {res:?}")
+            )
     }
 
     async fn upsert_grading_task_external(
         &self,
         assignment_uuid: &str,
         user_uuid: &str,
+        trigger: &GradingTrigger,
+        commit_ref: Option<&str>,
+        submitted_at: Option<OffsetDateTime>,
         enforce_time_window: bool,
-    ) -> anyhow::Result<Option<OffsetDateTime>> {
+    ) -> anyhow::Result<Option<RawGradingTask>> {
         let time_window_clause = if enforce_time_window {
-            "AND NOW() BETWEEN a.start AND a.stop "
+            TIME_WINDOW_CLAUSE
         } else {
             ""
         };
 
         let query = format!("INSERT INTO grading_task
-          (user_assignment_id, user_provider_login, status, repository, grader_repository, updated_at)
-        SELECT ua.id, u.provider_login, $3, a.repository_name, a.grader_url, NOW()
+          (user_assignment_id, user_provider_login, status, repository, grader_repository, updated_at, trigger, priority, commit_ref, submitted_at)
+        SELECT ua.id, u.provider_login, $3, a.repository_name, a.grader_url, NOW(), $4, $5, $6, $7
         FROM user_assignment ua, \"user\" u, assignment a
         WHERE
           ua.user_id = u.id
@@ -173,18 +280,21 @@ impl Repository {
           AND a.uuid::varchar = $1
           AND u.uuid::varchar = $2
           {time_window_clause} 
-        ON CONFLICT (user_assignment_id, user_provider_login, status) DO UPDATE
-        SET updated_at = NOW()
-        RETURNING updated_at");
+        {UPSERT_CONFLICT_CLAUSE}
+        RETURNING *, uuid::varchar as uuid");
 
-        sqlx::query_scalar(&query)
+        sqlx::query_as::<_, RawGradingTask>(&query)
             .bind(assignment_uuid)
             .bind(user_uuid)
             .bind(GradingStatus::QUEUED.to_string())
+            .bind(trigger.to_string())
+            .bind(trigger.priority())
+            .bind(commit_ref)
+            .bind(submitted_at)
             .fetch_optional(&self.pool)
             .await
-            .context(format!("[sql] upsert_grading_task_external(assignment_uuid={assignment_uuid:?}, user_uuid={user_uuid:?})"))
-            .inspect(|res| info!("[sql] upsert_grading_task_external(assignment_uuid={assignment_uuid:?}, user_uuid={user_uuid:?}): {res:?}"))
+            .context(format!("[sql] upsert_grading_task_external(assignment_uuid={assignment_uuid:?}, user_uuid={user_uuid:?}, trigger={trigger:?}, commit_ref={commit_ref:?}, submitted_at={submitted_at:?})"))
+            .inspect(|res| info!("[sql] upsert_grading_task_external(assignment_uuid={assignment_uuid:?}, user_uuid={user_uuid:?}, trigger={trigger:?}, commit_ref={commit_ref:?}, submitted_at={submitted_at:?}): {res:?}"))
     }
 
     pub async fn get_grading_tasks(
@@ -194,10 +304,17 @@ impl Repository {
     ) -> anyhow::Result<Vec<GradingTask>> {
         const QUERY: &str = "\
             SELECT
+              gt.uuid::varchar as uuid,
               m.uuid::varchar as module_uuid,
               a.uuid::varchar as assignment_uuid,
               gt.user_provider_login as provider_login,
               gt.status,
+              gt.trigger,
+              gt.priority,
+              gt.attempts,
+              gt.last_error,
+              gt.not_before,
+              gt.commit_ref,
               gt.created_at,
               gt.updated_at,
               a.repository_name,
@@ -230,16 +347,26 @@ impl Repository {
             })
     }
 
+    /// Reserves the next tasks to execute, within the limit of `max_tasks` tasks in flight.
+    ///
+    /// Tasks are picked by decreasing priority (boosted when the assignment deadline is near),
+    /// then round-robin between users, then by age.
     pub async fn reserve_grading_tasks_to_execute_transact<'e, 'c: 'e, E>(
         min_execution_interval_in_secs: i32,
         max_tasks: i32,
+        deadline_boost_window_in_secs: i32,
         transaction: E,
     ) -> anyhow::Result<Vec<GitHubGradingTask>>
     where
         E: 'e + Executor<'c, Database = Postgres>,
     {
         const QUERY: &str = "\
-            WITH max_tasks as (
+            WITH in_flight AS (
+              SELECT count(*)::integer as count
+              FROM grading_task
+              WHERE status = ANY ($3)
+            ),
+            candidate AS (
               SELECT
                 gt.id,
                 gt.uuid::varchar as uuid,
@@ -252,23 +379,37 @@ impl Repository {
                 a.repository_name,
                 u.installation_id,
                 a.grader_url as grader_url,
-                a.grader_cli_v2
+                a.grader_cli_v2,
+                gt.attempts,
+                gt.commit_ref,
+                a.grader_ref,
+                a.workflow_inputs,
+                gt.priority + CASE
+                  WHEN a.stop > NOW() AND a.stop < NOW() + interval '1 seconds' * $6 THEN $7
+                  ELSE 0
+                END as effective_priority,
+                ROW_NUMBER() OVER (PARTITION BY ua.user_id ORDER BY gt.priority DESC, gt.created_at ASC) as user_rank
               FROM grading_task gt, user_assignment ua, assignment a, module m, \"user\" u
               WHERE gt.user_assignment_id = ua.id
               AND ua.assignment_id = a.id
               AND a.module_id = m.id
               AND ua.user_id = u.id
+              AND u.installation_suspended_at IS NULL
               AND ua.grading_in_progress IS FALSE
               AND (ua.graded_last_at IS NULL OR ua.graded_last_at < NOW() - interval '1 seconds' * $1)
-              AND gt.status = ANY ($3)
-              ORDER BY gt.created_at ASC
-              LIMIT $2
+              AND gt.status = $4
+              AND (gt.not_before IS NULL OR gt.not_before <= NOW())
+            ),
+            max_tasks as (
+              SELECT *
+              FROM candidate
+              ORDER BY effective_priority DESC, user_rank ASC, created_at ASC
+              LIMIT GREATEST($2 - (SELECT count FROM in_flight), 0)
             ),
             grading_task_update as (
-              UPDATE grading_task gt SET status = $5, updated_at = NOW()
+              UPDATE grading_task gt SET status = $5, updated_at = NOW(), reserved_at = NOW()
               FROM max_tasks mt
               WHERE mt.id = gt.id
-              AND mt.status = $4
               RETURNING mt.*
             )
             UPDATE user_assignment ua SET
@@ -282,26 +423,34 @@ impl Repository {
         sqlx::query_as::<_, GitHubGradingTask>(QUERY)
             .bind(min_execution_interval_in_secs)
             .bind(max_tasks)
-            .bind(&[GradingStatus::QUEUED.to_string(), GradingStatus::RESERVED.to_string(), GradingStatus::ORDERED.to_string(), GradingStatus::STARTED.to_string()])
+            .bind(&[GradingStatus::RESERVED.to_string(), GradingStatus::ORDERED.to_string(), GradingStatus::STARTED.to_string()])
             .bind(GradingStatus::QUEUED.to_string())
             .bind(GradingStatus::RESERVED.to_string())
+            .bind(deadline_boost_window_in_secs)
+            .bind(DEADLINE_PRIORITY_BOOST)
             .fetch_all(transaction)
             .await
-            .context(format!("[sql] reserve_grading_tasks_to_execute_transact(min_execution_interval_in_secs={min_execution_interval_in_secs:?}, max_tasks={max_tasks:?})"))
-            .inspect(|res| if !res.is_empty() {info!("[sql] reserve_grading_tasks_to_execute_transact(min_execution_interval_in_secs={min_execution_interval_in_secs:?}, max_tasks={max_tasks:?}): reserved {} tasks", res.len())})
+            .context(format!("[sql] reserve_grading_tasks_to_execute_transact(min_execution_interval_in_secs={min_execution_interval_in_secs:?}, max_tasks={max_tasks:?}, deadline_boost_window_in_secs={deadline_boost_window_in_secs:?})"))
+            .inspect(|res| if !res.is_empty() {info!("[sql] reserve_grading_tasks_to_execute_transact(min_execution_interval_in_secs={min_execution_interval_in_secs:?}, max_tasks={max_tasks:?}, deadline_boost_window_in_secs={deadline_boost_window_in_secs:?}): reserved {} tasks", res.len())})
     }
 
     pub async fn delete_grading_task(
         &self,
         uuid: &str,
         error_message: Option<String>,
+        grading_metadata: Option<&GradingMetadata>,
     ) -> anyhow::Result<RawGradingTask> {
-        Self::delete_grading_task_transact(uuid, error_message, &self.pool).await
+        Self::delete_grading_task_transact(uuid, error_message, grading_metadata, &self.pool).await
     }
 
+    /// Ends the given task, `SUCCESSFUL` if no `error_message` is given, `ERROR` otherwise.
+    ///
+    /// The task is recorded in `grading_run` with the given `grading_metadata`,
+    /// or the one of the running grading if none.
     pub async fn delete_grading_task_transact<'e, 'c: 'e, E>(
         uuid: &str,
         error_message: Option<String>,
+        grading_metadata: Option<&GradingMetadata>,
         transaction: E,
     ) -> anyhow::Result<RawGradingTask>
     where
@@ -310,7 +459,22 @@ impl Repository {
         const QUERY: &str = "\
             WITH deleted_grading_task AS (
                 DELETE FROM grading_task WHERE uuid::varchar = $1
-                RETURNING *, uuid::varchar as uuid
+                RETURNING *
+            ), inserted_grading_run AS (
+                INSERT INTO grading_run (
+                  uuid, user_assignment_id, user_provider_login, repository, grader_repository, trigger,
+                  last_status, end_status, attempts, queued_at, reserved_at, ordered_at, started_at, submitted_at,
+                  short_commit_id, commit_url, full_log_url, error
+                )
+                SELECT
+                  dgt.uuid, dgt.user_assignment_id, dgt.user_provider_login, dgt.repository, dgt.grader_repository, dgt.trigger,
+                  dgt.status, $3, dgt.attempts, COALESCE(dgt.created_at, NOW()), dgt.reserved_at, dgt.ordered_at, dgt.started_at, dgt.submitted_at,
+                  COALESCE($4::jsonb, ua.running_grading_metadata)->>'short_commit_id',
+                  COALESCE($4::jsonb, ua.running_grading_metadata)->>'commit_url',
+                  COALESCE($4::jsonb, ua.running_grading_metadata)->>'full_log_url',
+                  $2
+                FROM deleted_grading_task dgt
+                JOIN user_assignment ua ON ua.id = dgt.user_assignment_id
             )
             UPDATE user_assignment ua
             SET
@@ -320,16 +484,135 @@ impl Repository {
               running_grading_metadata = NULL
             FROM deleted_grading_task dgt
             WHERE dgt.user_assignment_id = ua.id
-            RETURNING dgt.*
+            RETURNING
+              dgt.id,
+              dgt.uuid::varchar as uuid,
+              dgt.user_assignment_id,
+              dgt.user_provider_login,
+              dgt.repository,
+              dgt.status,
+              dgt.trigger,
+              dgt.created_at,
+              dgt.updated_at,
+              dgt.submitted_at
         ";
 
+        let end_status = if error_message.is_none() {
+            GradingStatus::SUCCESSFUL
+        } else {
+            GradingStatus::ERROR
+        };
+
         sqlx::query_as::<_, RawGradingTask>(QUERY)
             .bind(uuid)
             .bind(&error_message)
+            .bind(end_status.to_string())
+            .bind(grading_metadata.map(Json))
             .fetch_one(transaction)
             .await
-            .context(format!("[sql] delete_grading_task_transact(uuid={uuid:?}, error_message={error_message:?})"))
-            .inspect(|_| info!("[sql] delete_grading_task_transact(uuid={uuid:?}, error_message={error_message:?})"))
+            .context(format!("[sql] delete_grading_task_transact(uuid={uuid:?}, error_message={error_message:?}, grading_metadata={grading_metadata:?})"))
+            .inspect(|_| info!("[sql] delete_grading_task_transact(uuid={uuid:?}, error_message={error_message:?}, grading_metadata={grading_metadata:?})"))
+    }
+
+    /// Puts a task which could not be dispatched back in the queue, to be retried after `backoff_in_secs`.
+    ///
+    /// Returns `None` if another task is already queued for the same user assignment,
+    /// in which case the given task is left untouched.
+    pub async fn requeue_grading_task_transact<'e, 'c: 'e, E>(
+        uuid: &str,
+        error_message: &str,
+        backoff_in_secs: i32,
+        transaction: E,
+    ) -> anyhow::Result<Option<RawGradingTask>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            WITH requeued_grading_task AS (
+                UPDATE grading_task gt
+                SET
+                  status = $2,
+                  attempts = gt.attempts + 1,
+                  last_error = $3,
+                  not_before = NOW() + interval '1 seconds' * $4,
+                  reserved_at = NULL,
+                  updated_at = NOW()
+                WHERE gt.uuid::varchar = $1
+                AND NOT EXISTS (
+                  SELECT 1 FROM grading_task other
+                  WHERE other.user_assignment_id = gt.user_assignment_id
+                  AND other.user_provider_login = gt.user_provider_login
+                  AND other.status = $2
+                )
+                RETURNING gt.*, gt.uuid::varchar as uuid
+            )
+            UPDATE user_assignment ua
+            SET grading_in_progress = FALSE
+            FROM requeued_grading_task rgt
+            WHERE rgt.user_assignment_id = ua.id
+            RETURNING rgt.*
+        ";
+
+        sqlx::query_as::<_, RawGradingTask>(QUERY)
+            .bind(uuid)
+            .bind(GradingStatus::QUEUED.to_string())
+            .bind(error_message)
+            .bind(backoff_in_secs)
+            .fetch_optional(transaction)
+            .await
+            .context(format!("[sql] requeue_grading_task_transact(uuid={uuid:?}, error_message={error_message:?}, backoff_in_secs={backoff_in_secs:?})"))
+            .inspect(|res| info!("[sql] requeue_grading_task_transact(uuid={uuid:?}, error_message={error_message:?}, backoff_in_secs={backoff_in_secs:?}): requeued={}", res.is_some()))
+    }
+
+    /// Puts a reserved task back in the queue as is, without counting an attempt,
+    /// when no grading slot is free to dispatch it.
+    ///
+    /// Returns `None` if another task is already queued for the same user assignment,
+    /// in which case the given task is left untouched.
+    pub async fn release_grading_task_transact<'e, 'c: 'e, E>(
+        uuid: &str,
+        transaction: E,
+    ) -> anyhow::Result<Option<RawGradingTask>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            WITH released_grading_task AS (
+                UPDATE grading_task gt
+                SET
+                  status = $2,
+                  reserved_at = NULL,
+                  updated_at = NOW()
+                WHERE gt.uuid::varchar = $1
+                AND NOT EXISTS (
+                  SELECT 1 FROM grading_task other
+                  WHERE other.user_assignment_id = gt.user_assignment_id
+                  AND other.user_provider_login = gt.user_provider_login
+                  AND other.status = $2
+                )
+                RETURNING gt.*, gt.uuid::varchar as uuid
+            )
+            UPDATE user_assignment ua
+            SET grading_in_progress = FALSE
+            FROM released_grading_task rgt
+            WHERE rgt.user_assignment_id = ua.id
+            RETURNING rgt.*
+        ";
+
+        sqlx::query_as::<_, RawGradingTask>(QUERY)
+            .bind(uuid)
+            .bind(GradingStatus::QUEUED.to_string())
+            .fetch_optional(transaction)
+            .await
+            .context(format!(
+                "[sql] release_grading_task_transact(uuid={uuid:?})"
+            ))
+            .inspect(|res| {
+                info!(
+                    "[sql] release_grading_task_transact(uuid={uuid:?}): released={}",
+                    res.is_some()
+                )
+            })
     }
 
     pub async fn update_grading_task_non_terminal_status(
@@ -350,7 +633,11 @@ impl Repository {
     {
         const QUERY: &str = "\
             UPDATE grading_task
-            SET status = $2, updated_at = NOW()
+            SET
+              status = $2,
+              updated_at = NOW(),
+              ordered_at = CASE WHEN $2 = $3 THEN NOW() ELSE ordered_at END,
+              started_at = CASE WHEN $2 = $4 THEN NOW() ELSE started_at END
             WHERE uuid::varchar = $1
             RETURNING *, uuid::varchar as uuid
         ";
@@ -364,17 +651,206 @@ impl Repository {
         sqlx::query_as::<_, RawGradingTask>(QUERY)
             .bind(uuid)
             .bind(status.to_string())
+            .bind(GradingStatus::ORDERED.to_string())
+            .bind(GradingStatus::STARTED.to_string())
             .fetch_one(transaction)
             .await
             .context(format!("[sql] update_grading_task_non_terminal_status_transact(uuid={uuid:?}, status={status:?})"))
             .inspect(|_| info!("[sql] update_grading_task_non_terminal_status_transact(uuid={uuid:?}, status={status:?})"))
     }
 
+    /// Locks the task until the end of the transaction, so that concurrent runner events are applied one after the other.
+    pub async fn find_grading_task_status_transact<'e, 'c: 'e, E>(
+        uuid: &str,
+        transaction: E,
+    ) -> anyhow::Result<Option<GradingStatus>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "SELECT status FROM grading_task WHERE uuid::varchar = $1 FOR UPDATE";
+
+        let status: Option<String> = sqlx::query_scalar(QUERY)
+            .bind(uuid)
+            .fetch_optional(transaction)
+            .await
+            .context(format!(
+                "[sql] find_grading_task_status_transact(uuid={uuid:?})"
+            ))?;
+        status
+            .map(|status| {
+                GradingStatus::from_str(&status)
+                    .map_err(|()| anyhow!("Unknown status of grading task {uuid}: {status}"))
+            })
+            .transpose()
+    }
+
+    pub async fn set_grading_task_runner_transact<'e, 'c: 'e, E>(
+        uuid: &str,
+        runner: &str,
+        transaction: E,
+    ) -> anyhow::Result<()>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "UPDATE grading_task SET runner = $2 WHERE uuid::varchar = $1";
+
+        sqlx::query(QUERY)
+            .bind(uuid)
+            .bind(runner)
+            .execute(transaction)
+            .await
+            .context(format!(
+                "[sql] set_grading_task_runner_transact(uuid={uuid:?}, runner={runner:?})"
+            ))?;
+        Ok(())
+    }
+
+    /// Finds the task dispatched to `runner` and run by a workflow job,
+    /// by its uuid if the job carries it, otherwise by the log URL of the workflow run reported when it started.
+    pub async fn find_dispatched_grading_task_transact<'e, 'c: 'e, E>(
+        runner: &str,
+        uuid: Option<&str>,
+        run_url: &str,
+        transaction: E,
+    ) -> anyhow::Result<Option<DispatchedGradingTask>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            SELECT gt.uuid::varchar as uuid, ua.running_grading_metadata
+            FROM grading_task gt
+            JOIN user_assignment ua ON ua.id = gt.user_assignment_id
+            WHERE gt.runner = $1
+            AND gt.status = ANY ($4)
+            AND (
+              gt.uuid::varchar = $2
+              OR ($2 IS NULL AND ua.running_grading_metadata->>'full_log_url' = $3)
+            )
+            FOR UPDATE OF gt
+        ";
+
+        sqlx::query_as::<_, DispatchedGradingTask>(QUERY)
+            .bind(runner)
+            .bind(uuid)
+            .bind(run_url)
+            .bind(&[
+                GradingStatus::RESERVED.to_string(),
+                GradingStatus::ORDERED.to_string(),
+                GradingStatus::STARTED.to_string(),
+            ])
+            .fetch_optional(transaction)
+            .await
+            .context(format!(
+                "[sql] find_dispatched_grading_task_transact(runner={runner:?}, uuid={uuid:?}, run_url={run_url:?})"
+            ))
+    }
+
+    pub async fn count_in_flight_grading_tasks_by_runner_transact<'e, 'c: 'e, E>(
+        transaction: E,
+    ) -> anyhow::Result<Vec<RunnerInFlight>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            SELECT runner, count(*)::integer as count
+            FROM grading_task
+            WHERE status = ANY ($1)
+            AND runner IS NOT NULL
+            GROUP BY runner
+        ";
+
+        sqlx::query_as::<_, RunnerInFlight>(QUERY)
+            .bind(&[
+                GradingStatus::ORDERED.to_string(),
+                GradingStatus::STARTED.to_string(),
+            ])
+            .fetch_all(transaction)
+            .await
+            .context("[sql] count_in_flight_grading_tasks_by_runner_transact")
+    }
+
+    /// Ends all the non-terminal tasks in the given scope, recording them as `CANCELLED` runs.
+    ///
+    /// If a `teacher` is given, only tasks of modules they own are cancelled.
+    pub async fn cancel_grading_tasks(
+        &self,
+        scope: &GradingTaskScope,
+        teacher: Option<&User>,
+    ) -> anyhow::Result<Vec<CancelledGradingTask>> {
+        const QUERY: &str = "\
+            WITH cancelled_grading_task AS (
+                DELETE FROM grading_task gt
+                USING user_assignment ua, assignment a, module m, \"user\" u
+                WHERE gt.user_assignment_id = ua.id
+                AND ua.assignment_id = a.id
+                AND a.module_id = m.id
+                AND ua.user_id = u.id
+                AND ($1::varchar IS NULL OR gt.uuid::varchar = $1)
+                AND ($2::varchar IS NULL OR m.uuid::varchar = $2)
+                AND ($3::varchar IS NULL OR a.uuid::varchar = $3)
+                AND ($4::varchar IS NULL OR u.uuid::varchar = $4)
+                AND ($5::integer IS NULL OR EXISTS (
+                  SELECT 1 FROM teacher_module tm WHERE tm.module_id = m.id AND tm.teacher_id = $5
+                ))
+                AND ($8::varchar IS NULL OR u.provider_login = $8)
+                AND ($9::varchar[] IS NULL OR a.repository_name = ANY($9))
+                RETURNING gt.*, ua.running_grading_metadata
+            ), inserted_grading_run AS (
+                INSERT INTO grading_run (
+                  uuid, user_assignment_id, user_provider_login, repository, grader_repository, trigger,
+                  last_status, end_status, attempts, queued_at, reserved_at, ordered_at, started_at, submitted_at,
+                  short_commit_id, commit_url, full_log_url, error
+                )
+                SELECT
+                  cgt.uuid, cgt.user_assignment_id, cgt.user_provider_login, cgt.repository, cgt.grader_repository, cgt.trigger,
+                  cgt.status, $6, cgt.attempts, COALESCE(cgt.created_at, NOW()), cgt.reserved_at, cgt.ordered_at, cgt.started_at, cgt.submitted_at,
+                  cgt.running_grading_metadata->>'short_commit_id',
+                  cgt.running_grading_metadata->>'commit_url',
+                  cgt.running_grading_metadata->>'full_log_url',
+                  'Cancelled'
+                FROM cancelled_grading_task cgt
+            ), updated_user_assignment AS (
+                UPDATE user_assignment ua
+                SET
+                  grading_in_progress = FALSE,
+                  running_grading_metadata = NULL
+                FROM cancelled_grading_task cgt
+                WHERE cgt.user_assignment_id = ua.id
+                AND cgt.status <> $7
+                RETURNING ua.id
+            )
+            SELECT
+              cgt.uuid::varchar as uuid,
+              cgt.user_assignment_id,
+              cgt.status,
+              cgt.running_grading_metadata->>'full_log_url' as full_log_url,
+              cgt.runner,
+              cgt.ordered_at
+            FROM cancelled_grading_task cgt
+        ";
+
+        let teacher_id = teacher.map(|t| t.id);
+        sqlx::query_as::<_, CancelledGradingTask>(QUERY)
+            .bind(&scope.task_uuid)
+            .bind(&scope.module_uuid)
+            .bind(&scope.assignment_uuid)
+            .bind(&scope.user_uuid)
+            .bind(teacher_id)
+            .bind(GradingStatus::CANCELLED.to_string())
+            .bind(GradingStatus::QUEUED.to_string())
+            .bind(&scope.provider_login)
+            .bind(&scope.repositories)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("[sql] cancel_grading_tasks(scope={scope:?}, teacher_id={teacher_id:?})"))
+            .inspect(|res| info!("[sql] cancel_grading_tasks(scope={scope:?}, teacher_id={teacher_id:?}): cancelled {} tasks", res.len()))
+    }
+
     pub async fn timeout_grading_tasks(
         &self,
         status: &GradingStatus,
         min_creation_interval_in_secs: i32,
-    ) -> anyhow::Result<usize> {
+    ) -> anyhow::Result<Vec<RawGradingTask>> {
         const QUERY: &str = "\
             WITH deleted_grading_task AS (
                 DELETE FROM grading_task
@@ -382,6 +858,21 @@ impl Repository {
                   status = $1
                   AND updated_at < NOW() - interval '1 seconds' * $2
                 RETURNING *
+            ), inserted_grading_run AS (
+                INSERT INTO grading_run (
+                  uuid, user_assignment_id, user_provider_login, repository, grader_repository, trigger,
+                  last_status, end_status, attempts, queued_at, reserved_at, ordered_at, started_at, submitted_at,
+                  short_commit_id, commit_url, full_log_url, error
+                )
+                SELECT
+                  dgt.uuid, dgt.user_assignment_id, dgt.user_provider_login, dgt.repository, dgt.grader_repository, dgt.trigger,
+                  dgt.status, $3, dgt.attempts, COALESCE(dgt.created_at, NOW()), dgt.reserved_at, dgt.ordered_at, dgt.started_at, dgt.submitted_at,
+                  ua.running_grading_metadata->>'short_commit_id',
+                  ua.running_grading_metadata->>'commit_url',
+                  ua.running_grading_metadata->>'full_log_url',
+                  'Status ' || $1 || ' timed out after ' || $2 || ' secs'
+                FROM deleted_grading_task dgt
+                JOIN user_assignment ua ON ua.id = dgt.user_assignment_id
             ), updated_user_assignment AS (
                 UPDATE user_assignment ua
                 SET
@@ -393,20 +884,20 @@ impl Repository {
                 WHERE dgt.user_assignment_id = ua.id
                 RETURNING ua.*
             )
-            SELECT dgt.uuid::varchar
+            SELECT dgt.*, dgt.uuid::varchar as uuid
             FROM deleted_grading_task dgt
             LEFT JOIN updated_user_assignment uua ON uua.id = dgt.user_assignment_id
         ";
 
-        let deleted_uuids : Vec<String> = sqlx::query_scalar(QUERY)
+        sqlx::query_as::<_, RawGradingTask>(QUERY)
             .bind(status.to_string())
             .bind(min_creation_interval_in_secs)
+            .bind(GradingStatus::TIMEOUT.to_string())
             .fetch_all(&self.pool)
             .await
             .context(format!("[sql] timeout_grading_tasks(status={status:?}, min_creation_interval_in_secs={min_creation_interval_in_secs:?})"))
             .inspect(|res| if !res.is_empty() {
-                info!("[sql] timeout_grading_tasks(status={status:?}, min_creation_interval_in_secs={min_creation_interval_in_secs:?}): deleted {} tasks ({res:?})", res.len());
-            })?;
-        Ok(deleted_uuids.len())
+                info!("[sql] timeout_grading_tasks(status={status:?}, min_creation_interval_in_secs={min_creation_interval_in_secs:?}): deleted {} tasks ({:?})", res.len(), res.iter().map(|task| &task.uuid).collect::<Vec<_>>());
+            })
     }
 }
@@ -0,0 +1,51 @@
+use crate::repository::Repository;
+use crate::service::webhook_models::{RunnerEventOutcome, RunnerPayload};
+use anyhow::Context;
+use sqlx::{Executor, Postgres};
+
+impl Repository {
+    /// Returns `false` if an event with the same dedupe key was already recorded.
+    pub async fn record_runner_event_transact<'e, 'c: 'e, E>(
+        event: &RunnerPayload,
+        outcome: RunnerEventOutcome,
+        transaction: E,
+    ) -> anyhow::Result<bool>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            INSERT INTO runner_event (dedupe_key, task_uuid, status, outcome)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (dedupe_key) DO NOTHING
+            RETURNING id
+        ";
+
+        let dedupe_key = event.dedupe_key();
+        sqlx::query_scalar::<_, i32>(QUERY)
+            .bind(&dedupe_key)
+            .bind(&event.task_id)
+            .bind(event.status.to_string())
+            .bind(outcome.to_string())
+            .fetch_optional(transaction)
+            .await
+            .map(|id| id.is_some())
+            .context(format!(
+                "[sql] record_runner_event_transact(dedupe_key={dedupe_key:?}, outcome={outcome:?})"
+            ))
+    }
+
+    /// Returns the number of deleted events.
+    pub async fn purge_runner_events(&self, older_than_days: i32) -> anyhow::Result<u64> {
+        const QUERY: &str =
+            "DELETE FROM runner_event WHERE received_at < NOW() - make_interval(days => $1)";
+
+        sqlx::query(QUERY)
+            .bind(older_than_days)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+            .context(format!(
+                "[sql] purge_runner_events(older_than_days={older_than_days:?})"
+            ))
+    }
+}
@@ -0,0 +1,122 @@
+use crate::entities::ScheduledJobState;
+use crate::repository::Repository;
+use crate::scheduler::job::JobRunStatus;
+use anyhow::Context;
+
+impl Repository {
+    pub async fn find_scheduled_jobs(&self) -> anyhow::Result<Vec<ScheduledJobState>> {
+        const QUERY: &str = "SELECT * FROM scheduled_job ORDER BY name";
+
+        sqlx::query_as::<_, ScheduledJobState>(QUERY)
+            .fetch_all(&self.pool)
+            .await
+            .context("[sql] find_scheduled_jobs")
+    }
+
+    /// Marks the job as running, consuming a pending run request.
+    pub async fn start_scheduled_job(&self, name: &str) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            INSERT INTO scheduled_job (name, last_started_at, last_ended_at, last_status, last_error)
+            VALUES ($1, NOW(), NULL, $2, NULL)
+            ON CONFLICT (name) DO UPDATE
+            SET
+              run_requested = FALSE,
+              last_started_at = EXCLUDED.last_started_at,
+              last_ended_at = NULL,
+              last_status = EXCLUDED.last_status,
+              last_error = NULL
+        ";
+
+        sqlx::query(QUERY)
+            .bind(name)
+            .bind(JobRunStatus::Running.to_string())
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!("[sql] start_scheduled_job(name={name:?})"))
+    }
+
+    pub async fn end_scheduled_job(
+        &self,
+        name: &str,
+        status: JobRunStatus,
+        error: Option<&str>,
+    ) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            UPDATE scheduled_job
+            SET last_ended_at = NOW(), last_status = $2, last_error = $3
+            WHERE name = $1
+        ";
+
+        sqlx::query(QUERY)
+            .bind(name)
+            .bind(status.to_string())
+            .bind(error)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] end_scheduled_job(name={name:?}, status={status:?})"
+            ))
+    }
+
+    /// Marks the jobs left running, apart from `running_jobs`, as failed.
+    pub async fn fail_interrupted_scheduled_jobs(
+        &self,
+        running_jobs: &[&str],
+    ) -> anyhow::Result<u64> {
+        const QUERY: &str = "\
+            UPDATE scheduled_job
+            SET last_ended_at = NOW(), last_status = $1, last_error = $2
+            WHERE last_status = $3 AND NOT (name = ANY($4))
+        ";
+
+        sqlx::query(QUERY)
+            .bind(JobRunStatus::Failed.to_string())
+            .bind("Interrupted, the scheduling instance stopped while running the job")
+            .bind(JobRunStatus::Running.to_string())
+            .bind(running_jobs)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .context(format!(
+                "[sql] fail_interrupted_scheduled_jobs(running_jobs={running_jobs:?})"
+            ))
+    }
+
+    pub async fn set_scheduled_job_enabled(&self, name: &str, enabled: bool) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            INSERT INTO scheduled_job (name, enabled)
+            VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE
+            SET enabled = EXCLUDED.enabled
+        ";
+
+        sqlx::query(QUERY)
+            .bind(name)
+            .bind(enabled)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] set_scheduled_job_enabled(name={name:?}, enabled={enabled:?})"
+            ))
+    }
+
+    /// Asks the scheduling instance to run the job on its next tick, even if paused.
+    pub async fn request_scheduled_job_run(&self, name: &str) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            INSERT INTO scheduled_job (name, run_requested)
+            VALUES ($1, TRUE)
+            ON CONFLICT (name) DO UPDATE
+            SET run_requested = TRUE
+        ";
+
+        sqlx::query(QUERY)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!("[sql] request_scheduled_job_run(name={name:?})"))
+    }
+}
@@ -0,0 +1,79 @@
+use crate::entities::SchedulerLease;
+use crate::repository::Repository;
+use anyhow::Context;
+use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, Postgres};
+
+/// Key of the session advisory lock held by the scheduling instance
+const SCHEDULER_LOCK_KEY: i64 = 0x4B6F_7265;
+const SCHEDULER_LEASE_NAME: &str = "scheduler";
+
+impl Repository {
+    /// Returns the connection holding the scheduler lock if it could be taken.
+    ///
+    /// The lock is released when the connection is closed, letting another instance take it over.
+    pub async fn try_acquire_scheduler_lock(
+        &self,
+    ) -> anyhow::Result<Option<PoolConnection<Postgres>>> {
+        const QUERY: &str = "SELECT pg_try_advisory_lock($1)";
+
+        let mut connection = self.pool.acquire().await?;
+        let acquired: bool = sqlx::query_scalar(QUERY)
+            .bind(SCHEDULER_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await
+            .context("[sql] try_acquire_scheduler_lock")?;
+        Ok(acquired.then_some(connection))
+    }
+
+    /// Checks that the connection holding the scheduler lock is still alive.
+    ///
+    /// A `bigint` advisory lock key is split in `pg_locks` between `classid` (high bits) and `objid` (low bits).
+    pub async fn is_scheduler_lock_held(connection: &mut PgConnection) -> bool {
+        const QUERY: &str = "\
+            SELECT EXISTS (
+              SELECT 1 FROM pg_locks
+              WHERE locktype = 'advisory' AND pid = pg_backend_pid() AND granted
+                AND classid::bigint = $1 >> 32 AND objid::bigint = $1 & 4294967295 AND objsubid = 1
+            )
+        ";
+
+        sqlx::query_scalar(QUERY)
+            .bind(SCHEDULER_LOCK_KEY)
+            .fetch_one(connection)
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Records a tick of the scheduling instance, the lease changing hands if `holder` is a new one.
+    pub async fn record_scheduler_tick(&self, holder: &str) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            INSERT INTO scheduler_lease AS sl (name, holder)
+            VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE
+            SET
+              holder = EXCLUDED.holder,
+              acquired_at = CASE WHEN sl.holder = EXCLUDED.holder THEN sl.acquired_at ELSE NOW() END,
+              last_tick_at = NOW()
+        ";
+
+        sqlx::query(QUERY)
+            .bind(SCHEDULER_LEASE_NAME)
+            .bind(holder)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!("[sql] record_scheduler_tick(holder={holder:?})"))
+    }
+
+    pub async fn find_scheduler_lease(&self) -> anyhow::Result<Option<SchedulerLease>> {
+        const QUERY: &str =
+            "SELECT holder, acquired_at, last_tick_at FROM scheduler_lease WHERE name = $1";
+
+        sqlx::query_as::<_, SchedulerLease>(QUERY)
+            .bind(SCHEDULER_LEASE_NAME)
+            .fetch_optional(&self.pool)
+            .await
+            .context("[sql] find_scheduler_lease")
+    }
+}
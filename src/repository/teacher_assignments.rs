@@ -1,5 +1,7 @@
 use crate::entities::{Assignment, NewAssignment, User};
 use anyhow::Context;
+use sqlx::types::Json;
+use sqlx::{Executor, Postgres};
 use tracing::debug;
 
 use super::Repository;
@@ -12,8 +14,8 @@ impl Repository {
         teacher: &User,
     ) -> anyhow::Result<Assignment> {
         const QUERY: &str = "INSERT INTO assignment AS a
-            (module_id, name, start, stop, description, type, subject_url, grader_url, repository_name, factor_percentage, grader_run_url, hidden_by_teacher, grader_cli_v2)
-            SELECT m.id, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14
+            (module_id, name, start, stop, description, type, subject_url, grader_url, repository_name, factor_percentage, grader_run_url, hidden_by_teacher, grader_cli_v2, late_grace_period_in_secs, late_penalty_percentage_per_day, late_hard_cutoff, grade_aggregation, grade_aggregation_last_n, accept_grades_after_timeout, push_filter, grader_ref, workflow_inputs)
+            SELECT m.id, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23
             FROM module m, teacher_module tm
             WHERE
               m.uuid::varchar = $1
@@ -37,6 +39,15 @@ impl Repository {
             .bind(&assignment.grader_run_url)
             .bind(assignment.hidden_by_teacher)
             .bind(assignment.grader_cli_v2)
+            .bind(assignment.late_grace_period_in_secs)
+            .bind(assignment.late_penalty_percentage_per_day)
+            .bind(assignment.late_hard_cutoff)
+            .bind(assignment.grade_aggregation.to_string())
+            .bind(assignment.grade_aggregation_last_n)
+            .bind(assignment.accept_grades_after_timeout)
+            .bind(Json(&assignment.push_filter))
+            .bind(&assignment.grader_ref)
+            .bind(Json(&assignment.workflow_inputs))
             .fetch_one(&self.pool)
             .await
             .context(format!("[sql] create_assignment(module_uuid={module_uuid:?}, assignment={assignment:?}, teacher={teacher})"))
@@ -62,7 +73,16 @@ impl Repository {
             a.factor_percentage,
             a.grader_run_url,
             a.hidden_by_teacher,
-            a.grader_cli_v2
+            a.grader_cli_v2,
+            a.late_grace_period_in_secs,
+            a.late_penalty_percentage_per_day,
+            a.late_hard_cutoff,
+            a.grade_aggregation,
+            a.grade_aggregation_last_n,
+            a.accept_grades_after_timeout,
+            a.push_filter,
+            a.grader_ref,
+            a.workflow_inputs
             FROM assignment a
             JOIN module m ON m.id = a.module_id
             JOIN teacher_module tm ON tm.module_id = m.id
@@ -83,13 +103,16 @@ impl Repository {
             .context(format!("[sql] find_assignment(module_uuid={module_uuid:?}, uuid={uuid:?}, teacher={teacher})"))
     }
 
-    pub async fn update_assignment(
-        &self,
+    pub async fn update_assignment_transact<'e, 'c: 'e, E>(
         module_uuid: &str,
         uuid: &str,
         assignment: &NewAssignment,
         teacher: &User,
-    ) -> anyhow::Result<Assignment> {
+        transaction: E,
+    ) -> anyhow::Result<Assignment>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
         const QUERY: &str = "\
             UPDATE assignment AS a SET
               name = $4,
@@ -103,7 +126,16 @@ impl Repository {
               factor_percentage = $12,
               grader_run_url = $13,
               hidden_by_teacher = $14,
-              grader_cli_v2 = $15
+              grader_cli_v2 = $15,
+              late_grace_period_in_secs = $16,
+              late_penalty_percentage_per_day = $17,
+              late_hard_cutoff = $18,
+              grade_aggregation = $19,
+              grade_aggregation_last_n = $20,
+              accept_grades_after_timeout = $21,
+              push_filter = $22,
+              grader_ref = $23,
+              workflow_inputs = $24
             FROM module AS m
             JOIN teacher_module tm ON tm.module_id = m.id
             WHERE m.id = a.module_id
@@ -131,9 +163,18 @@ impl Repository {
             .bind(&assignment.grader_run_url)
             .bind(assignment.hidden_by_teacher)
             .bind(assignment.grader_cli_v2)
-            .fetch_one(&self.pool)
+            .bind(assignment.late_grace_period_in_secs)
+            .bind(assignment.late_penalty_percentage_per_day)
+            .bind(assignment.late_hard_cutoff)
+            .bind(assignment.grade_aggregation.to_string())
+            .bind(assignment.grade_aggregation_last_n)
+            .bind(assignment.accept_grades_after_timeout)
+            .bind(Json(&assignment.push_filter))
+            .bind(&assignment.grader_ref)
+            .bind(Json(&assignment.workflow_inputs))
+            .fetch_one(transaction)
             .await
-            .context(format!("[sql] update_assignment(module_uuid={module_uuid:?}, uuid={uuid:?}, assignment={assignment:?}, teacher={teacher})"))
+            .context(format!("[sql] update_assignment_transact(module_uuid={module_uuid:?}, uuid={uuid:?}, assignment={assignment:?}, teacher={teacher})"))
     }
 
     pub async fn delete_assignments(
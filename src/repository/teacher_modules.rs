@@ -198,6 +198,10 @@ impl Repository {
             ))
     }
 
+    /// Grades of the students of the module, the latest manual override applied.
+    ///
+    /// The latest `set` replaces the automatic grade until a `reset`,
+    /// the sum of the `adjust` since then being applied on top of it, within 0 and 20.
     pub async fn get_module_grades(
         &self,
         uuid: &str,
@@ -207,17 +211,57 @@ impl Repository {
             WITH enhanced_assignment AS (
                 SELECT
                   a.id,
+                  a.uuid,
                   a.module_id,
                   a.type,
                   a.name,
                   a.description,
                   a.factor_percentage,
-                  COALESCE(ua.normalized_grade, 0) as grade,
+                  CASE
+                    WHEN ago.value IS NOT NULL THEN
+                      LEAST(GREATEST(COALESCE(sgo.value, ua.normalized_grade, 0) + ago.value, 0), 20)
+                    ELSE COALESCE(sgo.value, ua.normalized_grade, 0)
+                  END as grade,
+                  COALESCE(ua.normalized_grade, 0) as automatic_grade,
+                  COALESCE(ua.raw_normalized_grade, 0) as raw_grade,
+                  COALESCE(ua.late_days, 0) as late_days,
+                  COALESCE(ua.penalty_percentage, 0) as penalty_percentage,
+                  CASE WHEN go.id IS NOT NULL THEN
+                    json_build_object(
+                      'id', go.id,
+                      'kind', go.kind,
+                      'value', go.value::real,
+                      'reason', go.reason,
+                      'teacher', go.teacher,
+                      'created_at', go.created_at
+                    )
+                  END as grade_override,
                   u.id as user_id
                 FROM assignment a
                 JOIN user_module um ON um.module_id = a.module_id
 				JOIN \"user\" u ON u.id = um.user_id
                 LEFT JOIN user_assignment ua ON ua.assignment_id = a.id AND ua.user_id = u.id
+                LEFT JOIN LATERAL (
+                  SELECT lgo.id, lgo.kind, lgo.value, lgo.reason, t.provider_login as teacher, lgo.created_at
+                  FROM grade_override lgo
+                  LEFT JOIN \"user\" t ON t.id = lgo.teacher_id
+                  WHERE lgo.assignment_id = a.id AND lgo.user_id = u.id
+                  ORDER BY lgo.id DESC
+                  LIMIT 1
+                ) go ON TRUE
+                LEFT JOIN LATERAL (
+                  SELECT rgo.id, CASE WHEN rgo.kind = 'set' THEN rgo.value END as value
+                  FROM grade_override rgo
+                  WHERE rgo.assignment_id = a.id AND rgo.user_id = u.id AND rgo.kind IN ('set', 'reset')
+                  ORDER BY rgo.id DESC
+                  LIMIT 1
+                ) sgo ON TRUE
+                LEFT JOIN LATERAL (
+                  SELECT SUM(pgo.value) as value
+                  FROM grade_override pgo
+                  WHERE pgo.assignment_id = a.id AND pgo.user_id = u.id AND pgo.kind = 'adjust'
+                    AND pgo.id > COALESCE(sgo.id, 0)
+                ) ago ON TRUE
                 ORDER BY a.id
             )
             SELECT
@@ -226,14 +270,21 @@ impl Repository {
               u.first_name,
               u.last_name,
               u.school_email,
+              u.school_group,
               u.provider_login,
               json_agg(
                 json_build_object(
+                  'id', ea.uuid,
                   'type', ea.type,
                   'name', ea.name,
                   'description', ea.description,
                   'factor_percentage', ea.factor_percentage,
-                  'grade', ea.grade
+                  'grade', ea.grade,
+                  'raw_grade', ea.raw_grade,
+                  'late_days', ea.late_days,
+                  'penalty_percentage', ea.penalty_percentage,
+                  'automatic_grade', ea.automatic_grade,
+                  'grade_override', ea.grade_override
                 ) ORDER BY ea.id ASC
               ) as grades,
               COALESCE(SUM(ea.grade * ea.factor_percentage / 100), 0)::real as total
@@ -50,6 +50,36 @@ impl Repository {
             ))
     }
 
+    /// Stored webhooks from the oldest to the newest, all of them if no `id` is given.
+    pub async fn find_unparseable_webhooks(
+        &self,
+        id: Option<i32>,
+    ) -> anyhow::Result<Vec<UnparseableWebhook>> {
+        const QUERY: &str = "\
+            SELECT *, (count(*) OVER ())::integer as total_count
+            FROM unparseable_webhook
+            WHERE ($1::integer IS NULL OR id = $1)
+            ORDER BY created_at, id
+        ";
+
+        sqlx::query_as::<_, UnparseableWebhook>(QUERY)
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!("[sql] find_unparseable_webhooks(id={id:?})"))
+    }
+
+    pub async fn delete_unparseable_webhook(&self, id: i32) -> anyhow::Result<()> {
+        const QUERY: &str = "DELETE FROM unparseable_webhook WHERE id = $1";
+
+        sqlx::query(QUERY)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!("[sql] delete_unparseable_webhook(id={id:?})"))
+    }
+
     pub async fn delete_unparseable_webhooks(&self) -> anyhow::Result<()> {
         const QUERY: &str = "TRUNCATE unparseable_webhook";
 
@@ -59,4 +89,19 @@ impl Repository {
             .map(|_| ())
             .context("[sql] delete_unparseable_webhooks()")
     }
+
+    /// Returns the number of deleted webhooks.
+    pub async fn purge_unparseable_webhooks(&self, older_than_days: i32) -> anyhow::Result<u64> {
+        const QUERY: &str =
+            "DELETE FROM unparseable_webhook WHERE created_at < NOW() - make_interval(days => $1)";
+
+        sqlx::query(QUERY)
+            .bind(older_than_days)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+            .context(format!(
+                "[sql] purge_unparseable_webhooks(older_than_days={older_than_days:?})"
+            ))
+    }
 }
@@ -20,4 +20,44 @@ impl Repository {
             .map(|_| ())
             .context(format!("[sql] update_installation_id(user_id={user_id:?}, installation_id={installation_id:?})"))
     }
+
+    /// Forgets an installation removed from GitHub, returns the login of its user if known.
+    pub async fn clear_installation_id(
+        &self,
+        installation_id: &str,
+    ) -> anyhow::Result<Option<String>> {
+        const QUERY: &str = "UPDATE \"user\" \
+                             SET installation_id = NULL, installation_suspended_at = NULL \
+                             WHERE installation_id = $1 \
+                             RETURNING provider_login";
+
+        sqlx::query_scalar(QUERY)
+            .bind(installation_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!(
+                "[sql] clear_installation_id(installation_id={installation_id:?})"
+            ))
+    }
+
+    /// Gradings of users whose installation is suspended are not dispatched until it is unsuspended.
+    pub async fn set_installation_suspended(
+        &self,
+        installation_id: &str,
+        suspended: bool,
+    ) -> anyhow::Result<()> {
+        const QUERY: &str = "UPDATE \"user\" \
+                             SET installation_suspended_at = CASE WHEN $2 THEN NOW() END \
+                             WHERE installation_id = $1";
+
+        sqlx::query(QUERY)
+            .bind(installation_id)
+            .bind(suspended)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] set_installation_suspended(installation_id={installation_id:?}, suspended={suspended:?})"
+            ))
+    }
 }
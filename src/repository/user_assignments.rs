@@ -1,5 +1,9 @@
-use crate::entities::{Assignment, GradingMetadata, InstantGrade, User, UserAssignment};
+use crate::entities::{
+    Assignment, GradingEventContext, GradingMetadata, GradingRules, InstantGrade, User,
+    UserAssignment, UserAssignmentGradesHistory,
+};
 use crate::repository::Repository;
+use crate::service::grade_aggregation::AggregatedGrade;
 use anyhow::Context;
 use const_format::formatcp;
 use sqlx::types::Json;
@@ -43,9 +47,41 @@ impl Repository {
             ))
     }
 
+    /// Marks the repositories of the user as unlinked, all of them if none are given.
+    ///
+    /// Returns the number of assignments no longer linked.
+    pub async fn unlink_user_assignments(
+        &self,
+        provider_login: &str,
+        repositories: Option<&[&str]>,
+    ) -> anyhow::Result<u64> {
+        const QUERY: &str = "\
+            UPDATE user_assignment ua
+            SET repository_linked = FALSE
+            FROM assignment a, \"user\" u
+            WHERE ua.assignment_id = a.id
+            AND ua.user_id = u.id
+            AND u.provider_login = $1
+            AND ($2::varchar[] IS NULL OR a.repository_name = ANY($2))
+            AND ua.repository_linked
+        ";
+
+        sqlx::query(QUERY)
+            .bind(provider_login)
+            .bind(repositories)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+            .context(format!(
+                "[sql] unlink_user_assignments(provider_login={provider_login:?}, repositories={repositories:?})"
+            ))
+    }
+
+    /// Appends the given grade to the history and stores the resulting `aggregated` grade.
     pub async fn update_assignment_grade_transact<'e, 'c: 'e, E>(
         user_assignment_id: i32,
         grade: &InstantGrade,
+        aggregated: &AggregatedGrade,
         transaction: E,
     ) -> anyhow::Result<()>
     where
@@ -55,25 +91,120 @@ impl Repository {
             UPDATE user_assignment ua
             SET
               updated_at = $2,
-              normalized_grade = GREATEST($3::NUMERIC(4, 2), normalized_grade),
-              grades_history = grades_history || $4,
+              normalized_grade = $3::NUMERIC(4, 2),
+              raw_normalized_grade = $4::NUMERIC(4, 2),
+              late_days = $5,
+              penalty_percentage = $6,
+              grades_history = grades_history || $7,
               graded_last_at = NOW()
             WHERE
               ua.id = $1
         ";
 
-        let normalized_grade = grade.grade * 20.0 / grade.max_grade;
-
         sqlx::query(QUERY)
             .bind(user_assignment_id)
             .bind(grade.time)
-            .bind(normalized_grade)
+            .bind(aggregated.normalized_grade)
+            .bind(aggregated.raw_normalized_grade)
+            .bind(aggregated.late_days)
+            .bind(aggregated.penalty_percentage)
             .bind(Json(grade))
             .execute(transaction)
             .await
             .map(|_| ())
             .context(format!(
-                "[sql] update_assignment_grade_transact(user_assignment_id={user_assignment_id:?}, grade={grade:?})"
+                "[sql] update_assignment_grade_transact(user_assignment_id={user_assignment_id:?}, grade={grade:?}, aggregated={aggregated:?})"
+            ))
+    }
+
+    pub async fn set_aggregated_grade_transact<'e, 'c: 'e, E>(
+        user_assignment_id: i32,
+        aggregated: &AggregatedGrade,
+        transaction: E,
+    ) -> anyhow::Result<()>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            UPDATE user_assignment ua
+            SET
+              updated_at = NOW(),
+              normalized_grade = $2::NUMERIC(4, 2),
+              raw_normalized_grade = $3::NUMERIC(4, 2),
+              late_days = $4,
+              penalty_percentage = $5
+            WHERE
+              ua.id = $1
+        ";
+
+        sqlx::query(QUERY)
+            .bind(user_assignment_id)
+            .bind(aggregated.normalized_grade)
+            .bind(aggregated.raw_normalized_grade)
+            .bind(aggregated.late_days)
+            .bind(aggregated.penalty_percentage)
+            .execute(transaction)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] set_aggregated_grade_transact(user_assignment_id={user_assignment_id:?}, aggregated={aggregated:?})"
+            ))
+    }
+
+    /// Locks the user assignment until the end of the transaction, so that concurrent gradings do not overwrite each other.
+    pub async fn find_grading_rules_transact<'e, 'c: 'e, E>(
+        user_assignment_id: i32,
+        transaction: E,
+    ) -> anyhow::Result<GradingRules>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            SELECT
+              a.stop,
+              a.late_grace_period_in_secs,
+              a.late_penalty_percentage_per_day,
+              a.late_hard_cutoff,
+              a.grade_aggregation,
+              a.grade_aggregation_last_n,
+              COALESCE(ua.grades_history, '[]'::jsonb) as grades_history
+            FROM user_assignment ua
+            JOIN assignment a ON a.id = ua.assignment_id
+            WHERE ua.id = $1
+            FOR UPDATE OF ua
+        ";
+
+        sqlx::query_as::<_, GradingRules>(QUERY)
+            .bind(user_assignment_id)
+            .fetch_one(transaction)
+            .await
+            .context(format!(
+                "[sql] find_grading_rules_transact(user_assignment_id={user_assignment_id:?})"
+            ))
+    }
+
+    pub async fn find_grades_histories_transact<'e, 'c: 'e, E>(
+        assignment_id: i32,
+        transaction: E,
+    ) -> anyhow::Result<Vec<UserAssignmentGradesHistory>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            SELECT
+              ua.id,
+              COALESCE(ua.grades_history, '[]'::jsonb) as grades_history
+            FROM user_assignment ua
+            WHERE ua.assignment_id = $1
+            FOR UPDATE
+        ";
+
+        sqlx::query_as::<_, UserAssignmentGradesHistory>(QUERY)
+            .bind(assignment_id)
+            .fetch_all(transaction)
+            .await
+            .context(format!(
+                "[sql] find_grades_histories_transact(assignment_id={assignment_id:?})"
             ))
     }
 
@@ -101,6 +232,12 @@ impl Repository {
               COALESCE(ua.repository_linked, FALSE) as repo_linked,
               u.provider_login as user_provider_login,
               COALESCE(ua.normalized_grade, 0)::real as normalized_grade,
+              COALESCE(ua.raw_normalized_grade, 0)::real as raw_normalized_grade,
+              COALESCE(ua.late_days, 0) as late_days,
+              COALESCE(ua.penalty_percentage, 0) as penalty_percentage,
+              a.late_grace_period_in_secs,
+              a.late_penalty_percentage_per_day,
+              a.late_hard_cutoff,
               COALESCE(ua.grades_history, '[]'::jsonb) as grades_history,
               coalesce(json_agg(to_jsonb(gt.*) ORDER BY gt.created_at asc) FILTER (WHERE gt.id IS NOT NULL), '[]'::json) AS grading_tasks,
               COALESCE(ua.grading_in_progress, FALSE) as grading_in_progress,
@@ -164,4 +301,62 @@ impl Repository {
                 "[sql] update_assignment_current_grading_metadata(user_assignment_id={user_assignment_id:?}, grading_metadata={grading_metadata:?})"
             ))
     }
+
+    /// Records the progress of a running grading, returning the id of the graded user assignment.
+    pub async fn update_running_grading_progress_transact<'e, 'c: 'e, E>(
+        task_uuid: &str,
+        grading_metadata: &GradingMetadata,
+        transaction: E,
+    ) -> anyhow::Result<Option<i32>>
+    where
+        E: 'e + Executor<'c, Database = Postgres>,
+    {
+        const QUERY: &str = "\
+            UPDATE user_assignment ua
+            SET
+              updated_at = NOW(),
+              running_grading_metadata = $2
+            FROM grading_task gt
+            WHERE gt.user_assignment_id = ua.id
+            AND gt.uuid::varchar = $1
+            RETURNING ua.id
+        ";
+
+        sqlx::query_scalar::<_, i32>(QUERY)
+            .bind(task_uuid)
+            .bind(Json(grading_metadata))
+            .fetch_optional(transaction)
+            .await
+            .context(format!(
+                "[sql] update_running_grading_progress_transact(task_uuid={task_uuid:?}, grading_metadata={grading_metadata:?})"
+            ))
+    }
+
+    pub async fn find_grading_event_context(
+        &self,
+        user_assignment_id: i32,
+    ) -> anyhow::Result<Option<GradingEventContext>> {
+        const QUERY: &str = "\
+            SELECT
+              u.uuid::varchar as user_uuid,
+              m.uuid::varchar as module_uuid,
+              a.uuid::varchar as assignment_uuid,
+              u.provider_login,
+              ua.normalized_grade::real as normalized_grade,
+              ua.running_grading_metadata
+            FROM user_assignment ua
+            JOIN assignment a ON a.id = ua.assignment_id
+            JOIN module m ON m.id = a.module_id
+            JOIN \"user\" u ON u.id = ua.user_id
+            WHERE ua.id = $1
+        ";
+
+        sqlx::query_as::<_, GradingEventContext>(QUERY)
+            .bind(user_assignment_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context(format!(
+                "[sql] find_grading_event_context(user_assignment_id={user_assignment_id:?})"
+            ))
+    }
 }
@@ -0,0 +1,155 @@
+use crate::entities::{WebhookDelivery, WebhookDeliveryFilter};
+use crate::repository::Repository;
+use crate::service::webhook_models::WebhookDeliveryOutcome;
+use anyhow::Context;
+
+impl Repository {
+    /// Records the reception of a delivery, returns `false` if it was already received and should not be processed again.
+    ///
+    /// A redelivery is only processed again if the previous one failed or was not authenticated,
+    /// or if it is still being processed after `processing_timeout_in_secs`, its instance having likely died.
+    pub async fn claim_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        event: &str,
+        processing_timeout_in_secs: i32,
+    ) -> anyhow::Result<bool> {
+        const QUERY: &str = "\
+            INSERT INTO webhook_delivery AS wd (delivery_id, event, outcome)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (delivery_id) DO UPDATE
+            SET
+              event = EXCLUDED.event,
+              outcome = EXCLUDED.outcome,
+              error = NULL,
+              received_at = NOW(),
+              processing_time_in_ms = NULL,
+              redeliveries = wd.redeliveries + 1
+            WHERE wd.outcome = ANY($4)
+              OR (wd.outcome = $3 AND wd.received_at < NOW() - make_interval(secs => $5))
+            RETURNING delivery_id
+        ";
+
+        let retryable_outcomes = [
+            WebhookDeliveryOutcome::InvalidSignature,
+            WebhookDeliveryOutcome::Failed,
+        ]
+        .map(|outcome| outcome.to_string());
+        sqlx::query_scalar::<_, String>(QUERY)
+            .bind(delivery_id)
+            .bind(event)
+            .bind(WebhookDeliveryOutcome::Received.to_string())
+            .bind(&retryable_outcomes[..])
+            .bind(f64::from(processing_timeout_in_secs))
+            .fetch_optional(&self.pool)
+            .await
+            .map(|claimed| claimed.is_some())
+            .context(format!(
+                "[sql] claim_webhook_delivery(delivery_id={delivery_id:?}, event={event:?}, processing_timeout_in_secs={processing_timeout_in_secs:?})"
+            ))
+    }
+
+    pub async fn end_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        outcome: WebhookDeliveryOutcome,
+        error: Option<&str>,
+        processing_time_in_ms: i32,
+    ) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            UPDATE webhook_delivery
+            SET outcome = $2, error = $3, processing_time_in_ms = $4
+            WHERE delivery_id = $1
+        ";
+
+        sqlx::query(QUERY)
+            .bind(delivery_id)
+            .bind(outcome.to_string())
+            .bind(error)
+            .bind(processing_time_in_ms)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] end_webhook_delivery(delivery_id={delivery_id:?}, outcome={outcome:?})"
+            ))
+    }
+
+    /// Records a delivery with an invalid signature, unless a delivery with the same id was already received.
+    ///
+    /// As anyone can forge its id, such a delivery never overrides the record of an authenticated one.
+    pub async fn insert_unauthenticated_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        event: &str,
+        error: &str,
+    ) -> anyhow::Result<()> {
+        const QUERY: &str = "\
+            INSERT INTO webhook_delivery (delivery_id, event, outcome, error)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (delivery_id) DO NOTHING
+        ";
+
+        sqlx::query(QUERY)
+            .bind(delivery_id)
+            .bind(event)
+            .bind(WebhookDeliveryOutcome::InvalidSignature.to_string())
+            .bind(error)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context(format!(
+                "[sql] insert_unauthenticated_webhook_delivery(delivery_id={delivery_id:?}, event={event:?})"
+            ))
+    }
+
+    /// Lists the deliveries matching the given filter, most recent first.
+    pub async fn get_webhook_deliveries(
+        &self,
+        filter: &WebhookDeliveryFilter,
+        page: i32,
+        per_page: i32,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        const QUERY: &str = "\
+            SELECT *, (count(*) OVER ())::integer as total_count
+            FROM webhook_delivery
+            WHERE ($1::varchar IS NULL OR event = $1)
+              AND ($2::varchar IS NULL OR outcome = $2)
+              AND ($3::timestamptz IS NULL OR received_at >= $3)
+              AND ($4::timestamptz IS NULL OR received_at < $4)
+            ORDER BY received_at DESC
+            LIMIT $5
+            OFFSET $6
+        ";
+
+        let offset = if page == 1 { 0 } else { (page - 1) * per_page };
+
+        sqlx::query_as::<_, WebhookDelivery>(QUERY)
+            .bind(&filter.event)
+            .bind(&filter.outcome)
+            .bind(filter.from)
+            .bind(filter.to)
+            .bind(per_page)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .context(format!(
+                "[sql] get_webhook_deliveries(filter={filter:?}, page={page:?}, per_page={per_page:?})"
+            ))
+    }
+
+    /// Returns the number of deleted deliveries.
+    pub async fn purge_webhook_deliveries(&self, older_than_days: i32) -> anyhow::Result<u64> {
+        const QUERY: &str =
+            "DELETE FROM webhook_delivery WHERE received_at < NOW() - make_interval(days => $1)";
+
+        sqlx::query(QUERY)
+            .bind(older_than_days)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+            .context(format!(
+                "[sql] purge_webhook_deliveries(older_than_days={older_than_days:?})"
+            ))
+    }
+}
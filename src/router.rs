@@ -14,6 +14,7 @@ mod auth;
 mod error;
 mod fapi;
 mod spa;
+mod sse;
 pub mod state;
 mod webhook;
 
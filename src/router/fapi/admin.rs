@@ -1,20 +1,24 @@
 use axum::extract::{Path, Query};
 use axum::{
     extract::State,
-    routing::{delete, get, patch},
+    routing::{delete, get, patch, post},
     Json, Router,
 };
 use http::StatusCode;
 use octocrab::models::InstallationId;
+use std::str::FromStr;
 use tracing::{error, warn};
 use validator::Validate;
 
 use crate::github::runner;
+use crate::scheduler::job::Job;
 use crate::service::dtos::{
-    GradingTaskResponse, Page, PaginationQuery, UnparseableWebhookResponse, UserForAdminResponse,
+    GradingRunResponse, GradingRunStatsResponse, GradingTaskResponse, Page, PaginationQuery,
+    ScheduledJobResponse, SchedulerLeaseResponse, UnparseableWebhookResponse, UserForAdminResponse,
+    VecInto, WebhookDeliveryResponse, WebhookReplayResponse,
 };
 use crate::{
-    entities::Table,
+    entities::{GradingRunFilter, GradingTaskScope, Table, WebhookDeliveryFilter},
     router::{auth::AdminUser, state::AppState},
 };
 
@@ -36,24 +40,51 @@ pub fn router() -> Router<AppState> {
             "/unparseable_webhooks",
             get(get_unparseable_webhooks).delete(delete_unparseable_webhooks),
         )
+        .route(
+            "/unparseable_webhooks/replay",
+            post(replay_unparseable_webhooks),
+        )
+        .route(
+            "/unparseable_webhooks/:id/replay",
+            post(replay_unparseable_webhook),
+        )
+        .route("/webhook_deliveries", get(get_webhook_deliveries))
         .route("/grading_tasks", get(get_grading_tasks))
+        .route("/grading_tasks/:task_id", delete(cancel_grading_task))
+        .route("/grading_runs", get(get_grading_runs))
+        .route("/grading_runs/stats", get(get_grading_run_stats))
+        .route("/jobs", get(get_jobs))
+        .route("/jobs/:name", patch(update_job))
+        .route("/jobs/:name/run", post(run_job))
 }
 
 async fn get_metadata(
     _user: AdminUser,
     State(state): State<AppState>,
 ) -> Result<Json<AdminMetadata>, (StatusCode, String)> {
-    let runner = state.gh_runner.metadata().await.map_err(|err| {
+    let runners = state.gh_runners.metadata().await.map_err(|err| {
         warn!("{err:?}");
         (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}"))
     })?;
+    let scheduler = state
+        .service
+        .repo
+        .find_scheduler_lease()
+        .await
+        .map_err(|err| {
+            warn!("{err:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{err}"))
+        })?
+        .map(Into::into);
 
-    Ok(Json(AdminMetadata { runner }))
+    Ok(Json(AdminMetadata { runners, scheduler }))
 }
 
 #[derive(serde::Serialize, Debug, Clone)]
 struct AdminMetadata {
-    runner: runner::Metadata,
+    runners: Vec<runner::Metadata>,
+    /// Instance leading the scheduling, none if the scheduler never ran
+    scheduler: Option<SchedulerLeaseResponse>,
 }
 
 async fn get_tables(
@@ -238,6 +269,65 @@ async fn delete_unparseable_webhooks(
     Ok(())
 }
 
+async fn replay_unparseable_webhooks(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WebhookReplayResponse>>, StatusCode> {
+    let results = state
+        .service
+        .replay_unparseable_webhooks(None)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, "[http] replay_unparseable_webhooks");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(results))
+}
+
+async fn replay_unparseable_webhook(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<WebhookReplayResponse>, StatusCode> {
+    let results = state
+        .service
+        .replay_unparseable_webhooks(Some(id))
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, id, "[http] replay_unparseable_webhook");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    results
+        .into_iter()
+        .next()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_webhook_deliveries(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(filter): Query<WebhookDeliveryFilter>,
+) -> Result<Json<Page<WebhookDeliveryResponse>>, (StatusCode, Json<String>)> {
+    pagination
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    Ok(Json(
+        state
+            .service
+            .get_webhook_deliveries(&filter, &pagination)
+            .await
+            .map_err(|err| {
+                error!(error = ?err, %user, ?pagination, ?filter, "[http] get_webhook_deliveries");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+                )
+            })?,
+    ))
+}
+
 async fn get_grading_tasks(
     AdminUser(user): AdminUser,
     State(state): State<AppState>,
@@ -261,6 +351,71 @@ async fn get_grading_tasks(
     ))
 }
 
+async fn cancel_grading_task(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<(), StatusCode> {
+    let scope = GradingTaskScope {
+        task_uuid: Some(task_id.clone()),
+        ..GradingTaskScope::default()
+    };
+    let cancelled = state
+        .service
+        .cancel_gradings(&scope, None, state.grading_backend.as_ref())
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, task_id, "[http] cancel_grading_task");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if cancelled == 0 {
+        Err(StatusCode::NOT_FOUND)
+    } else {
+        Ok(())
+    }
+}
+
+async fn get_grading_runs(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(filter): Query<GradingRunFilter>,
+) -> Result<Json<Page<GradingRunResponse>>, (StatusCode, Json<String>)> {
+    pagination
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    Ok(Json(
+        state
+            .service
+            .get_grading_runs(&filter, None, &pagination)
+            .await
+            .map_err(|err| {
+                error!(error = ?err, %user, ?pagination, ?filter, "[http] get_grading_runs");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+                )
+            })?,
+    ))
+}
+
+async fn get_grading_run_stats(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Query(filter): Query<GradingRunFilter>,
+) -> Result<Json<Vec<GradingRunStatsResponse>>, StatusCode> {
+    let run_stats = state
+        .service
+        .repo
+        .get_grading_run_stats(&filter)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, ?filter, "[http] get_grading_run_stats");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(run_stats.vec_into()))
+}
+
 async fn trigger_error(
     AdminUser(user): AdminUser,
     State(state): State<AppState>,
@@ -291,3 +446,73 @@ async fn resync_github(
         })?;
     Ok(())
 }
+
+async fn get_jobs(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ScheduledJobResponse>>, StatusCode> {
+    let mut saved_jobs = state
+        .service
+        .repo
+        .find_scheduled_jobs()
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, "[http] get_jobs");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let mut jobs = vec![];
+    for job in Job::ALL {
+        let schedule = job.schedule().map_err(|err| {
+            error!(error = ?err, %user, %job, "[http] get_jobs");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let job_state = saved_jobs
+            .iter()
+            .position(|saved_job| saved_job.name == job.name())
+            .map(|index| saved_jobs.swap_remove(index));
+        jobs.push(ScheduledJobResponse::new(job, &schedule, job_state));
+    }
+    Ok(Json(jobs))
+}
+
+async fn run_job(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let job = Job::from_str(&name).map_err(|_| StatusCode::NOT_FOUND)?;
+    state
+        .service
+        .repo
+        .request_scheduled_job_run(job.name())
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, %job, "[http] run_job");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct UpdateJobRequest {
+    enabled: bool,
+}
+
+async fn update_job(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateJobRequest>,
+) -> Result<(), StatusCode> {
+    let job = Job::from_str(&name).map_err(|_| StatusCode::NOT_FOUND)?;
+    state
+        .service
+        .repo
+        .set_scheduled_job_enabled(job.name(), request.enabled)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, %job, ?request, "[http] update_job");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
@@ -1,19 +1,34 @@
-use axum::extract::Path;
+use axum::extract::{Path, Query};
+use axum::response::sse::{Event, Sse};
+use axum::response::IntoResponse;
 use axum::{
     extract::State,
-    routing::{get, post},
+    routing::{get, post, put},
     Json, Router,
 };
-use http::StatusCode;
+use futures_util::Stream;
+use http::{header, StatusCode};
+use std::convert::Infallible;
 use tracing::error;
+use uuid::Uuid;
+use validator::Validate;
 
 use crate::service::dtos::{
-    ModuleGradesResponse, TeacherAssignmentResponse, TeacherModuleDescResponse,
-    TeacherModuleResponse, VecInto,
+    AssignmentFeedbackResponse, GradeOverrideResponse, GradingRunResponse, MassGradingRequest,
+    ModuleGradesResponse, Page, PaginationQuery, TeacherAssignmentResponse,
+    TeacherModuleDescResponse, TeacherModuleResponse, VecInto,
 };
+use crate::service::grade_export::GradeExportQuery;
 use crate::{
-    entities::{NewAssignment, NewModule},
-    router::{auth::TeacherUser, state::AppState},
+    entities::{
+        GradingRunFilter, GradingTaskScope, NewAssignment, NewAssignmentFeedback, NewGradeOverride,
+        NewModule,
+    },
+    router::{
+        auth::TeacherUser,
+        sse::{self, GradingEventScope},
+        state::AppState,
+    },
 };
 
 pub fn router() -> Router<AppState> {
@@ -28,15 +43,30 @@ pub fn router() -> Router<AppState> {
                 .put(update_module)
                 .delete(delete_assignments),
         )
+        .route("/module/:module_id/events", get(get_module_grading_events))
         .route("/module/:module_id/assignment", post(create_assignment))
         .route("/module/:module_id/grade", get(get_grades))
+        .route("/module/:module_id/grade/export", get(export_grades))
+        .route("/module/:module_id/grading_run", get(get_grading_runs))
         .route(
             "/module/:module_id/assignment/:assignment_id",
             get(get_assignment).put(update_assignment),
         )
         .route(
             "/module/:module_id/assignment/:assignment_id/grade",
-            post(trigger_mass_grading_for_assignment),
+            post(trigger_mass_grading_for_assignment).delete(cancel_mass_grading_for_assignment),
+        )
+        .route(
+            "/module/:module_id/assignment/:assignment_id/student/:provider_login/grade_override",
+            get(get_grade_overrides).post(override_grade),
+        )
+        .route(
+            "/module/:module_id/assignment/:assignment_id/student/:provider_login/feedback",
+            get(get_assignment_feedback).post(create_assignment_feedback),
+        )
+        .route(
+            "/module/:module_id/assignment/:assignment_id/feedback/:feedback_id",
+            put(update_assignment_feedback).delete(delete_assignment_feedback),
         )
 }
 
@@ -92,6 +122,27 @@ async fn get_module(
     Ok(Json(module.into()))
 }
 
+async fn get_module_grading_events(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path(module_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    state
+        .service
+        .repo
+        .find_module(&module_id, &user)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, "[http] get_module_grading_events");
+            StatusCode::NOT_FOUND
+        })?;
+
+    Ok(sse::grading_events(
+        &state.service.events,
+        GradingEventScope::Module(module_id),
+    ))
+}
+
 async fn update_module(
     TeacherUser(user): TeacherUser,
     State(state): State<AppState>,
@@ -146,12 +197,59 @@ async fn get_grades(
     ))
 }
 
+async fn export_grades(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path(module_id): Path<String>,
+    Query(query): Query<GradeExportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<String>)> {
+    query
+        .columns()
+        .and_then(|_| query.separator())
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    // Part of the filename sent back in a header
+    let module_uuid = Uuid::parse_str(&module_id).map_err(|err| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(format!("Invalid module id: {err}")),
+        )
+    })?;
+    let body = state
+        .service
+        .export_module_grades(&module_id, &user, &query)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, ?query, "[http] export_grades");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
+        })?;
+    let disposition = format!(
+        "attachment; filename=\"grades-{module_uuid}.{}\"",
+        query.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                query.format.content_type().to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
 async fn create_assignment(
     TeacherUser(user): TeacherUser,
     State(state): State<AppState>,
     Path(module_id): Path<String>,
     Json(assignment): Json<NewAssignment>,
-) -> Result<Json<TeacherAssignmentResponse>, StatusCode> {
+) -> Result<Json<TeacherAssignmentResponse>, (StatusCode, Json<String>)> {
+    assignment
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
     let assignment = state
         .service
         .repo
@@ -159,7 +257,10 @@ async fn create_assignment(
         .await
         .map_err(|err| {
             error!(error = ?err, %user, module_id, "[http] create_assignment");
-            StatusCode::INTERNAL_SERVER_ERROR
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
         })?;
 
     Ok(Json(assignment.into()))
@@ -188,15 +289,20 @@ async fn update_assignment(
     State(state): State<AppState>,
     Path((module_id, assignment_id)): Path<(String, String)>,
     Json(assignment): Json<NewAssignment>,
-) -> Result<Json<TeacherAssignmentResponse>, StatusCode> {
+) -> Result<Json<TeacherAssignmentResponse>, (StatusCode, Json<String>)> {
+    assignment
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
     let assignment = state
         .service
-        .repo
         .update_assignment(&module_id, &assignment_id, &assignment, &user)
         .await
         .map_err(|err| {
             error!(error = ?err, %user, module_id, assignment_id, "[http] update_assignment");
-            StatusCode::INTERNAL_SERVER_ERROR
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
         })?;
 
     Ok(Json(assignment.into()))
@@ -225,15 +331,230 @@ async fn trigger_mass_grading_for_assignment(
     TeacherUser(user): TeacherUser,
     State(state): State<AppState>,
     Path((module_id, assignment_id)): Path<(String, String)>,
-) -> Result<(), StatusCode> {
+    request: Option<Json<MassGradingRequest>>,
+) -> Result<(), (StatusCode, Json<String>)> {
+    let Json(request) = request.unwrap_or_default();
+    request
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
     state
         .service
-        .trigger_mass_grading_for_assignment(&module_id, &assignment_id, &user)
+        .trigger_mass_grading_for_assignment(
+            &module_id,
+            &assignment_id,
+            &request,
+            &user,
+            &state.github_clients,
+        )
         .await
         .map_err(|err| {
             error!(error = ?err, %user, module_id, assignment_id, "[http] trigger_mass_grading_for_assignment");
-            StatusCode::INTERNAL_SERVER_ERROR
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
         })?;
 
     Ok(())
 }
+
+async fn cancel_mass_grading_for_assignment(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id)): Path<(String, String)>,
+) -> Result<Json<usize>, StatusCode> {
+    let scope = GradingTaskScope {
+        module_uuid: Some(module_id.clone()),
+        assignment_uuid: Some(assignment_id.clone()),
+        ..GradingTaskScope::default()
+    };
+    state
+        .service
+        .cancel_gradings(&scope, Some(&user), state.grading_backend.as_ref())
+        .await
+        .map(Json)
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, "[http] cancel_mass_grading_for_assignment");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn get_grading_runs(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path(module_id): Path<String>,
+    Query(pagination): Query<PaginationQuery>,
+    Query(filter): Query<GradingRunFilter>,
+) -> Result<Json<Page<GradingRunResponse>>, (StatusCode, Json<String>)> {
+    pagination
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    let filter = GradingRunFilter {
+        module_id: Some(module_id),
+        ..filter
+    };
+    Ok(Json(
+        state
+            .service
+            .get_grading_runs(&filter, Some(&user), &pagination)
+            .await
+            .map_err(|err| {
+                error!(error = ?err, %user, ?pagination, ?filter, "[http] get_grading_runs");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+                )
+            })?,
+    ))
+}
+
+async fn override_grade(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id, provider_login)): Path<(String, String, String)>,
+    Json(grade_override): Json<NewGradeOverride>,
+) -> Result<Json<GradeOverrideResponse>, (StatusCode, Json<String>)> {
+    grade_override
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    state
+        .service
+        .repo
+        .insert_grade_override(
+            &module_id,
+            &assignment_id,
+            &provider_login,
+            &grade_override,
+            &user,
+        )
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, provider_login, "[http] override_grade");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
+        })?
+        .map(|grade_override| Json(grade_override.into()))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(StatusCode::NOT_FOUND.to_string()),
+            )
+        })
+}
+
+async fn get_grade_overrides(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id, provider_login)): Path<(String, String, String)>,
+) -> Result<Json<Vec<GradeOverrideResponse>>, StatusCode> {
+    let grade_overrides = state
+        .service
+        .repo
+        .find_grade_overrides(&module_id, &assignment_id, &provider_login, &user)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, provider_login, "[http] get_grade_overrides");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(grade_overrides.vec_into()))
+}
+
+async fn create_assignment_feedback(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id, provider_login)): Path<(String, String, String)>,
+    Json(feedback): Json<NewAssignmentFeedback>,
+) -> Result<Json<AssignmentFeedbackResponse>, (StatusCode, Json<String>)> {
+    feedback
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    state
+        .service
+        .repo
+        .insert_assignment_feedback(&module_id, &assignment_id, &provider_login, &feedback, &user)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, provider_login, "[http] create_assignment_feedback");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
+        })?
+        .map(|feedback| Json(feedback.into()))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(StatusCode::NOT_FOUND.to_string()),
+            )
+        })
+}
+
+async fn get_assignment_feedback(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id, provider_login)): Path<(String, String, String)>,
+) -> Result<Json<Vec<AssignmentFeedbackResponse>>, StatusCode> {
+    let feedback = state
+        .service
+        .repo
+        .find_assignment_feedback(&module_id, &assignment_id, &provider_login, &user)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, provider_login, "[http] get_assignment_feedback");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(feedback.vec_into()))
+}
+
+async fn update_assignment_feedback(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id, feedback_id)): Path<(String, String, String)>,
+    Json(feedback): Json<NewAssignmentFeedback>,
+) -> Result<Json<AssignmentFeedbackResponse>, (StatusCode, Json<String>)> {
+    feedback
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
+    state
+        .service
+        .repo
+        .update_assignment_feedback(&module_id, &assignment_id, &feedback_id, &feedback, &user)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, feedback_id, "[http] update_assignment_feedback");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
+            )
+        })?
+        .map(|feedback| Json(feedback.into()))
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(StatusCode::NOT_FOUND.to_string()),
+            )
+        })
+}
+
+async fn delete_assignment_feedback(
+    TeacherUser(user): TeacherUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id, feedback_id)): Path<(String, String, String)>,
+) -> Result<(), StatusCode> {
+    let deleted = state
+        .service
+        .repo
+        .delete_assignment_feedback(&module_id, &assignment_id, &feedback_id, &user)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, assignment_id, feedback_id, "[http] delete_assignment_feedback");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if deleted == 0 {
+        Err(StatusCode::NOT_FOUND)
+    } else {
+        Ok(())
+    }
+}
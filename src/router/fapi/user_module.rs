@@ -1,5 +1,7 @@
-use crate::entities::NewGradingTask;
+use crate::entities::{GradingTaskScope, NewGradingTask};
+use crate::repository::grading_task::GradingTrigger;
 use axum::extract::{Path, Query};
+use axum::response::sse::{Event, Sse};
 use axum::response::Redirect;
 use axum::{
     extract::State,
@@ -7,14 +9,19 @@ use axum::{
     Json, Router,
 };
 use axum_extra::either::Either;
+use futures_util::Stream;
 use http::StatusCode;
+use std::convert::Infallible;
 use time::OffsetDateTime;
 use tracing::{error, info, warn};
+use validator::Validate;
 
 use crate::router::auth::AuthenticatedUser;
+use crate::router::sse::{self, GradingEventScope};
 use crate::router::state::AppState;
 use crate::service::dtos::{
-    UserAssignmentResponse, UserModuleDescResponse, UserModuleResponse, VecInto,
+    AssignmentFeedbackResponse, GradingRequest, UserAssignmentResponse, UserModuleDescResponse,
+    UserModuleResponse, VecInto,
 };
 use crate::service::{ObfuscatedStr, SyncError};
 
@@ -22,12 +29,21 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_modules))
         .route("/redeem", get(redeem_module))
+        .route("/events", get(grading_events))
         .route("/:module_id", get(get_module))
         .route("/:module_id/assignment/:assignment_id", get(get_assignment))
+        .route(
+            "/:module_id/assignment/:assignment_id/feedback",
+            get(get_assignment_feedback),
+        )
         .route(
             "/:module_id/assignment/:assignment_id/trigger-grading",
             post(trigger_grading),
         )
+        .route(
+            "/:module_id/assignment/:assignment_id/cancel-grading",
+            post(cancel_grading),
+        )
         .route(
             "/:module_id/assignment/:assignment_id/sync-repo",
             post(sync_repo),
@@ -56,6 +72,23 @@ async fn get_assignment(
     Ok(Json(assignment))
 }
 
+async fn get_assignment_feedback(
+    AuthenticatedUser(user): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id)): Path<(String, String)>,
+) -> Result<Json<Vec<AssignmentFeedbackResponse>>, StatusCode> {
+    let feedback = state
+        .service
+        .repo
+        .find_published_assignment_feedback(&user, &module_id, &assignment_id)
+        .await
+        .map_err(|err| {
+            error!(error = ?err, %user, module_id, ?assignment_id, "[http] get_assignment_feedback");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(feedback.vec_into()))
+}
+
 async fn get_module(
     AuthenticatedUser(user): AuthenticatedUser,
     State(state): State<AppState>,
@@ -125,23 +158,60 @@ struct RedeemQuery {
     redirect: Option<bool>,
 }
 
+#[allow(clippy::unused_async)]
+async fn grading_events(
+    AuthenticatedUser(user): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    sse::grading_events(&state.service.events, GradingEventScope::User(user.uuid))
+}
+
 async fn trigger_grading(
     AuthenticatedUser(user): AuthenticatedUser,
     State(state): State<AppState>,
     Path((module_id, assignment_id)): Path<(String, String)>,
-) -> Result<Json<Option<OffsetDateTime>>, StatusCode> {
+    request: Option<Json<GradingRequest>>,
+) -> Result<Json<Option<OffsetDateTime>>, (StatusCode, Json<String>)> {
+    let Json(request) = request.unwrap_or_default();
+    request
+        .validate()
+        .map_err(|err| (StatusCode::BAD_REQUEST, Json(format!("{err}"))))?;
     state
         .service
-        .repo
-        .upsert_grading_task(&NewGradingTask::External {
+        .queue_grading_task(&NewGradingTask::External {
             assignment_uuid: assignment_id.clone(),
             user_uuid: user.uuid.clone(),
+            trigger: GradingTrigger::STUDENT,
+            commit_ref: request.commit_ref,
+            submitted_at: None,
         }, true)
         .await
         .map(Json)
         .map_err(|err| {
             error!(error = ?err, %user, ?module_id, ?assignment_id, "[http] trigger_grading: Unable to trigger grading");
-            StatusCode::FORBIDDEN
+            (StatusCode::FORBIDDEN, Json(StatusCode::FORBIDDEN.to_string()))
+        })
+}
+
+async fn cancel_grading(
+    AuthenticatedUser(user): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path((module_id, assignment_id)): Path<(String, String)>,
+) -> Result<Json<usize>, StatusCode> {
+    let scope = GradingTaskScope {
+        module_uuid: Some(module_id.clone()),
+        assignment_uuid: Some(assignment_id.clone()),
+        user_uuid: Some(user.uuid.clone()),
+        ..GradingTaskScope::default()
+    };
+    state
+        .service
+        .cancel_gradings(&scope, None, state.grading_backend.as_ref())
+        .await
+        .map(Json)
+        .map_err(|err| {
+            error!(error = ?err, %user, ?module_id, ?assignment_id, "[http] cancel_grading: Unable to cancel grading");
+            StatusCode::INTERNAL_SERVER_ERROR
         })
 }
 
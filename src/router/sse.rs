@@ -0,0 +1,146 @@
+use crate::service::events::{GradingEvent, GradingEvents};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use std::future;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+/// Grading events a client is allowed to follow.
+#[derive(Debug, Clone)]
+pub enum GradingEventScope {
+    /// Gradings of a student, by user uuid
+    User(String),
+    /// Gradings of all the students of a module, by module uuid
+    Module(String),
+}
+
+impl GradingEventScope {
+    fn contains(&self, event: &GradingEvent) -> bool {
+        match self {
+            Self::User(user_uuid) => &event.user_uuid == user_uuid,
+            Self::Module(module_uuid) => &event.module_id == module_uuid,
+        }
+    }
+}
+
+/// Streams the grading events within `scope` as Server-Sent Events.
+///
+/// A `lagged` event is sent when some events were dropped, clients are then expected to refresh their state.
+pub fn grading_events(
+    events: &GradingEvents,
+    scope: GradingEventScope,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = scoped_grading_events(events.subscribe(), scope).filter_map(|event| {
+        future::ready(match event {
+            Ok(event) => match Event::default().event("grading").json_data(&event) {
+                Ok(sse_event) => Some(Ok(sse_event)),
+                Err(err) => {
+                    warn!(error = ?err, ?event, "[sse] Unserializable grading event");
+                    None
+                }
+            },
+            Err(skipped) => Some(Ok(Event::default()
+                .event("lagged")
+                .data(skipped.to_string()))),
+        })
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Events within `scope`, or the number of events skipped when lagging behind.
+fn scoped_grading_events(
+    receiver: broadcast::Receiver<GradingEvent>,
+    scope: GradingEventScope,
+) -> impl Stream<Item = Result<GradingEvent, u64>> {
+    stream::unfold((receiver, scope), |(mut receiver, scope)| async move {
+        loop {
+            let item = match receiver.recv().await {
+                Ok(event) if scope.contains(&event) => Ok(event),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => Err(skipped),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((item, (receiver, scope)));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn event(task_id: &str, user_uuid: &str, module_id: &str) -> GradingEvent {
+        GradingEvent {
+            user_uuid: user_uuid.to_string(),
+            module_id: module_id.to_string(),
+            assignment_id: "a1".to_string(),
+            provider_login: user_uuid.to_string(),
+            task_id: task_id.to_string(),
+            status: "QUEUED".to_string(),
+            running_grading_metadata: None,
+            normalized_grade: None,
+            error: None,
+        }
+    }
+
+    async fn streamed_task_ids(scope: GradingEventScope) -> Vec<Result<String, u64>> {
+        let (sender, receiver) = broadcast::channel(8);
+        let stream = scoped_grading_events(receiver, scope);
+        for (task_id, user_uuid, module_id) in [
+            ("t1", "alice", "m1"),
+            ("t2", "bob", "m1"),
+            ("t3", "alice", "m2"),
+            ("t4", "bob", "m2"),
+        ] {
+            sender
+                .send(event(task_id, user_uuid, module_id))
+                .expect("Subscribed");
+        }
+        drop(sender);
+
+        stream
+            .map(|event| event.map(|event| event.task_id))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn students_only_get_their_own_events() {
+        assert_eq!(
+            streamed_task_ids(GradingEventScope::User("alice".to_string())).await,
+            vec![Ok("t1".to_string()), Ok("t3".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn teachers_only_get_the_events_of_their_module() {
+        assert_eq!(
+            streamed_task_ids(GradingEventScope::Module("m2".to_string())).await,
+            vec![Ok("t3".to_string()), Ok("t4".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_are_told_how_many_events_they_missed() {
+        let (sender, receiver) = broadcast::channel(2);
+        let stream = scoped_grading_events(receiver, GradingEventScope::Module("m1".to_string()));
+        for task_id in ["t1", "t2", "t3"] {
+            sender
+                .send(event(task_id, "alice", "m1"))
+                .expect("Subscribed");
+        }
+        drop(sender);
+
+        let task_ids: Vec<Result<String, u64>> = stream
+            .map(|event| event.map(|event| event.task_id))
+            .collect()
+            .await;
+
+        assert_eq!(
+            task_ids,
+            vec![Err(1), Ok("t2".to_string()), Ok("t3".to_string())]
+        );
+    }
+}
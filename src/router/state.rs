@@ -5,10 +5,14 @@ use oauth2::basic::BasicClient;
 use oauth2::{AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
 use sqlx::PgPool;
 use std::num::NonZeroUsize;
-use std::str::FromStr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
-use crate::github::{runner::Runner, GitRepoSlug};
+use crate::github::runner_pool::RunnerPool;
+use crate::grading::signature::RunnerSignatures;
+use crate::grading::{local::LocalExecutor, GradingBackend, GradingBackendKind};
 use crate::service::Service;
 use crate::{config::Config, github, github::client_cache::ClientCache};
 
@@ -21,7 +25,9 @@ pub struct AppState {
     pub github_clients: ClientCache,
     pub service: Service,
     pub instance_secret: String,
-    pub gh_runner: Runner,
+    pub gh_runners: RunnerPool,
+    pub runner_signatures: RunnerSignatures,
+    pub grading_backend: Arc<dyn GradingBackend>,
     _sentry: crate::sentry::Holder,
 }
 
@@ -36,24 +42,13 @@ impl AppState {
         let instance_secret = Uuid::new_v4().to_string();
         tracing::info!("Instance secret: {}", &instance_secret);
 
-        let slug = GitRepoSlug::from_str(&config.github_runner_repo_slug)?;
-
         let gh_runner_app_client = github::create_gh_app_client(
             config.github_runner_app_id,
             &config.github_runner_app_private_key,
         )?;
 
-        let runner_client =
-            gh_runner_app_client.installation(config.github_runner_installation_id.into());
-
-        let gh_runner = Runner::new(
-            slug.org,
-            slug.repo,
-            gh_runner_app_client,
-            runner_client,
-            config.clone(),
-        )
-        .await?;
+        let gh_runners = RunnerPool::new(config, &gh_runner_app_client).await?;
+        let runner_signatures = RunnerSignatures::new(config)?;
 
         let gh_app_client =
             github::create_gh_app_client(config.github_app_id, &config.github_app_private_key)?;
@@ -67,6 +62,19 @@ impl AppState {
             })?,
             config.github_app_id,
         );
+        let service = Service::new(pool);
+        let grading_backend: Arc<dyn GradingBackend> = match config.grading_backend {
+            GradingBackendKind::Github => Arc::new(gh_runners.clone()),
+            GradingBackendKind::Local => Arc::new(LocalExecutor::new(
+                config.local_grader_command.clone().context(
+                    "[config] LOCAL_GRADER_COMMAND is required when GRADING_BACKEND is local",
+                )?,
+                config.local_grader_workdir.as_ref().map(PathBuf::from),
+                Duration::from_secs(config.grading_started_timeout_in_secs.unsigned_abs().into()),
+                service.clone(),
+            )),
+        };
+        tracing::info!("Grading backend: {:?}", config.grading_backend);
         let sentry = crate::sentry::Holder::new(config);
 
         Ok(Self {
@@ -77,9 +85,11 @@ impl AppState {
                 .map_or_else(Key::generate, |src| Key::derive_from(src.as_ref())),
             oauth: OAuth::new(config)?,
             github_clients,
-            service: Service::new(pool),
+            service,
             instance_secret,
-            gh_runner,
+            gh_runners,
+            runner_signatures,
+            grading_backend,
             _sentry: sentry,
         })
     }
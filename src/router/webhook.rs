@@ -1,9 +1,7 @@
 use crate::github::runner::Runner;
-use crate::github::webhook_models::parse_event;
 use crate::router::state::AppState;
-use crate::service::webhook_models::RunnerPayload;
+use crate::service::webhook_models::{RunnerEventOutcome, RunnerPayload};
 use crate::string_header;
-use axum::extract::rejection::JsonRejection;
 use axum::extract::State;
 use axum::{routing::post, Json, Router};
 use axum_extra::TypedHeader;
@@ -13,7 +11,23 @@ use http::StatusCode;
 use tracing::{debug, error};
 
 string_header!(XGithubEvent, X_GITHUB_EVENT_HEADER, "x-github-event");
+string_header!(
+    XGithubDelivery,
+    X_GITHUB_DELIVERY_HEADER,
+    "x-github-delivery"
+);
 string_header!(XHubSignature, X_HUB_SIGNATURE, "x-hub-signature-256");
+string_header!(XKorektoRunner, X_KOREKTO_RUNNER, "x-korekto-runner");
+string_header!(
+    XKorektoTimestamp,
+    X_KOREKTO_TIMESTAMP,
+    "x-korekto-timestamp"
+);
+string_header!(
+    XKorektoSignature,
+    X_KOREKTO_SIGNATURE,
+    "x-korekto-signature"
+);
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -24,6 +38,7 @@ pub fn router() -> Router<AppState> {
 #[allow(clippy::unused_async)]
 async fn on_github_event(
     TypedHeader(XGithubEvent(event_type)): TypedHeader<XGithubEvent>,
+    TypedHeader(XGithubDelivery(delivery_id)): TypedHeader<XGithubDelivery>,
     TypedHeader(XHubSignature(signature)): TypedHeader<XHubSignature>,
     State(state): State<AppState>,
     payload: String,
@@ -33,52 +48,98 @@ async fn on_github_event(
         &state.config.github_app_webhook_secret,
         &signature,
     ) {
-        debug!(error = ?err, ?event_type, "Received webhook with invalid signature");
-    } else {
-        match parse_event(&event_type, &payload) {
-            Ok(result) => {
-                let _res = state.service.on_webhook(result).await;
-            }
-            Err(err) => {
-                if let Err(err) = state
-                    .service
-                    .repo
-                    .insert_unparseable_webhook("github", &event_type, &payload, &err.to_string())
-                    .await
-                {
-                    error!(error = ?err, ?event_type, ?payload, "[http] on_github_event");
-                }
-            }
+        debug!(error = ?err, ?event_type, ?delivery_id, "Received webhook with invalid signature");
+        if let Err(err) = state
+            .service
+            .repo
+            .insert_unauthenticated_webhook_delivery(&delivery_id, &event_type, &err.to_string())
+            .await
+        {
+            error!(error = ?err, ?event_type, ?delivery_id, "[http] on_github_event");
+        }
+    } else if let Err(err) = state
+        .service
+        .on_github_delivery(&delivery_id, &event_type, &payload)
+        .await
+    {
+        error!(error = ?err, ?event_type, ?delivery_id, ?payload, "[http] on_github_event");
+    }
+}
+
+/// Credentials of a runner callback.
+///
+/// GitHub hosted workflows present an OIDC token, self-hosted runners sign the body with their shared secret.
+enum RunnerCredentials {
+    Jwt(String),
+    Signature {
+        runner: String,
+        timestamp: String,
+        signature: String,
+    },
+}
+
+impl RunnerCredentials {
+    async fn verify(&self, state: &AppState, payload: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Jwt(token) => state.gh_runners.verify_jwt(token).await,
+            Self::Signature {
+                runner,
+                timestamp,
+                signature,
+            } => state
+                .runner_signatures
+                .verify(runner, timestamp, signature, payload),
         }
     }
 }
 
 #[allow(clippy::unused_async)]
 async fn on_github_runner_event(
-    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    runner: Option<TypedHeader<XKorektoRunner>>,
+    timestamp: Option<TypedHeader<XKorektoTimestamp>>,
+    signature: Option<TypedHeader<XKorektoSignature>>,
     State(state): State<AppState>,
-    payload_result: Result<Json<RunnerPayload>, JsonRejection>,
-) -> Result<(), (StatusCode, String)> {
-    match payload_result {
-        Ok(Json(payload)) => {
-            state.gh_runner.verify_jwt(bearer.token()).map_err(|err| {
-                debug!(error = ?err, token = bearer.token(), ?payload, "[http] on_github_runner_event: Invalid JWT");
-                (StatusCode::UNAUTHORIZED, format!("{err:?}"))
-            })?;
-
-            state
-                .service
-                .on_runner_webhook(&payload)
-                .await
-                .map_err(|err| {
-                    error!(error = ?err, ?payload, "[http] on_github_runner_event: Unknown error");
-                    (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}"))
-                })?;
+    payload: String,
+) -> Result<Json<RunnerEventOutcome>, (StatusCode, String)> {
+    let credentials = match (bearer, runner, timestamp, signature) {
+        (
+            _,
+            Some(TypedHeader(XKorektoRunner(runner))),
+            Some(TypedHeader(XKorektoTimestamp(timestamp))),
+            Some(TypedHeader(XKorektoSignature(signature))),
+        ) => RunnerCredentials::Signature {
+            runner,
+            timestamp,
+            signature,
+        },
+        (Some(TypedHeader(Authorization(bearer))), _, _, _) => {
+            RunnerCredentials::Jwt(bearer.token().to_string())
         }
-        Err(err) => {
-            error!(error = ?err, payload = err.body_text(), "[http] on_github_runner_event: Invalid JSON");
+        _ => {
+            debug!("[http] on_github_runner_event: Missing credentials");
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "Missing bearer token or signature headers".to_string(),
+            ));
         }
-    }
+    };
+    credentials.verify(&state, &payload).await.map_err(|err| {
+        debug!(error = ?err, ?payload, "[http] on_github_runner_event: Invalid credentials");
+        (StatusCode::UNAUTHORIZED, format!("{err:?}"))
+    })?;
 
-    Ok(())
+    let payload = serde_json::from_str::<RunnerPayload>(&payload).map_err(|err| {
+        error!(error = ?err, %payload, "[http] on_github_runner_event: Invalid JSON");
+        (StatusCode::BAD_REQUEST, format!("{err:?}"))
+    })?;
+    state
+        .service
+        .on_runner_webhook(&payload)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            error!(error = ?err, ?payload, "[http] on_github_runner_event: Unknown error");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}"))
+        })
 }
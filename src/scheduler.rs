@@ -1,47 +1,204 @@
+use crate::entities::ScheduledJobState;
+use crate::repository::Repository;
 use crate::router::state::AppState;
+use crate::scheduler::job::{Job, JobRunStatus, RETENTION_IN_DAYS};
+use anyhow::anyhow;
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
 
+pub mod job;
+
+/// Runs the periodic [jobs](Job) on a single instance at a time.
+///
+/// Each instance tries to take a Postgres advisory lock on every tick, the one holding it being the leader.
+/// The lock is bound to a database connection, so it is released if the leader dies, another instance taking over.
+///
+/// Grading tasks are dispatched on each tick, before the other jobs which run in the background.
 pub struct Scheduler {
     state: AppState,
+    instance_id: String,
+    leader_connection: Mutex<Option<PoolConnection<Postgres>>>,
+    /// Jobs running in the background on this instance
+    running_jobs: Arc<std::sync::Mutex<HashSet<Job>>>,
 }
 
 impl Scheduler {
-    pub const fn new(state: AppState) -> Self {
-        Self { state }
+    #[must_use]
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            instance_id: Uuid::new_v4().to_string(),
+            leader_connection: Mutex::new(None),
+            running_jobs: Arc::new(std::sync::Mutex::new(HashSet::new())),
+        }
     }
 
     pub async fn start(&self) {
         let secs = self.state.config.scheduler_interval_in_secs;
         let mut interval = tokio::time::interval(Duration::from_secs(secs));
-        info!("[scheduler] Starting scheduler every {secs} secs");
+        info!(
+            "[scheduler] Starting scheduler every {secs} secs (instance {})",
+            self.instance_id
+        );
 
         loop {
             interval.tick().await;
-            if let Err(err) = self.tick().await {
-                error!(error = ?err, "[scheduler] unknown error");
+            self.state.gh_runners.jwks().refresh_if_stale().await;
+            match self.is_leader().await {
+                Ok(true) => {
+                    if let Err(err) = self.tick().await {
+                        error!(error = ?err, "[scheduler] unknown error");
+                    }
+                }
+                Ok(false) => {}
+                Err(err) => error!(error = ?err, "[scheduler] Unable to take the lead"),
+            }
+        }
+    }
+
+    /// Takes the lead if free, keeping it as long as the lock connection is alive.
+    async fn is_leader(&self) -> anyhow::Result<bool> {
+        let mut leader_connection = self.leader_connection.lock().await;
+        if let Some(connection) = leader_connection.as_mut() {
+            if Repository::is_scheduler_lock_held(connection).await {
+                return Ok(true);
+            }
+            warn!("[scheduler] Instance {} lost the lead", self.instance_id);
+            // Closed rather than returned to the pool, in case the lock is still held by the session
+            if let Some(connection) = leader_connection.take() {
+                drop(connection.detach());
             }
         }
+        let repo = &self.state.service.repo;
+        *leader_connection = repo.try_acquire_scheduler_lock().await?;
+        if leader_connection.is_some() {
+            info!("[scheduler] Instance {} took the lead", self.instance_id);
+            // Jobs still running according to their state were interrupted by the end of the previous leader
+            let running_jobs: Vec<&str> = lock_running_jobs(&self.running_jobs)?
+                .iter()
+                .map(|job| job.name())
+                .collect();
+            let interrupted = repo.fail_interrupted_scheduled_jobs(&running_jobs).await?;
+            if interrupted > 0 {
+                warn!("[scheduler] Marked {interrupted} interrupted jobs as failed");
+            }
+        }
+        Ok(leader_connection.is_some())
     }
 
+    /// Runs the jobs which are due, or were requested by an admin.
+    ///
+    /// Grading tasks are dispatched inline, other jobs are spawned not to delay the next dispatch,
+    /// a job still running being skipped.
     pub async fn tick(&self) -> anyhow::Result<()> {
-        let stats = self
-            .state
-            .service
-            .schedule_tasks(
-                self.state.config.min_grading_interval_in_secs,
-                self.state.config.grading_ordered_timeout_in_secs,
-                self.state.config.grading_started_timeout_in_secs,
-                self.state.config.max_parallel_gradings,
-                &self.state.gh_runner,
-            )
-            .await?;
-        if stats.total() > 0 {
-            info!(
-                "[scheduler] Ticking, {stats} (min queue time={} sec)",
-                self.state.config.min_grading_interval_in_secs
-            );
+        let repo = &self.state.service.repo;
+        repo.record_scheduler_tick(&self.instance_id).await?;
+        let saved_jobs = repo.find_scheduled_jobs().await?;
+        let now = OffsetDateTime::now_utc();
+        for job in Job::ALL {
+            let job_state = saved_jobs
+                .iter()
+                .find(|saved_job| saved_job.name == job.name());
+            if !is_job_due(job, job_state, now)? {
+                continue;
+            }
+            if job == Job::ScheduleGradingTasks {
+                run_job(&self.state, job).await?;
+            } else if lock_running_jobs(&self.running_jobs)?.insert(job) {
+                let state = self.state.clone();
+                let running_jobs = self.running_jobs.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = run_job(&state, job).await {
+                        error!(error = ?err, "[scheduler] Unable to run job {job}");
+                    }
+                    if let Ok(mut running_jobs) = lock_running_jobs(&running_jobs) {
+                        running_jobs.remove(&job);
+                    }
+                });
+            }
         }
         Ok(())
     }
 }
+
+fn lock_running_jobs(
+    running_jobs: &std::sync::Mutex<HashSet<Job>>,
+) -> anyhow::Result<std::sync::MutexGuard<'_, HashSet<Job>>> {
+    running_jobs
+        .lock()
+        .map_err(|_| anyhow!("Previous thread using the mutex panicked"))
+}
+
+/// Records the outcome of the job, a failure not preventing the next jobs from running.
+async fn run_job(state: &AppState, job: Job) -> anyhow::Result<()> {
+    let repo = &state.service.repo;
+    repo.start_scheduled_job(job.name()).await?;
+    match execute_job(state, job).await {
+        Ok(()) => {
+            repo.end_scheduled_job(job.name(), JobRunStatus::Successful, None)
+                .await
+        }
+        Err(err) => {
+            error!(error = ?err, "[scheduler] Job {job} failed");
+            repo.end_scheduled_job(job.name(), JobRunStatus::Failed, Some(&format!("{err:#}")))
+                .await
+        }
+    }
+}
+
+async fn execute_job(state: &AppState, job: Job) -> anyhow::Result<()> {
+    let service = &state.service;
+    match job {
+        Job::ScheduleGradingTasks => {
+            let task_stats = service
+                .schedule_tasks(&state.config, state.grading_backend.as_ref())
+                .await?;
+            if task_stats.total() > 0 {
+                info!(
+                    "[scheduler] Ticking, {task_stats} (min queue time={} sec)",
+                    state.config.min_grading_interval_in_secs
+                );
+            }
+        }
+        Job::ResyncGithub => service.resync_github(&state.github_clients).await?,
+        Job::PurgeUnparseableWebhooks => {
+            let purged = service
+                .repo
+                .purge_unparseable_webhooks(RETENTION_IN_DAYS)
+                .await?;
+            info!("[scheduler] Purged {purged} unparseable webhooks");
+        }
+        Job::PurgeRunnerEvents => {
+            let purged = service.repo.purge_runner_events(RETENTION_IN_DAYS).await?;
+            info!("[scheduler] Purged {purged} runner events");
+        }
+        Job::PurgeWebhookDeliveries => {
+            let purged = service
+                .repo
+                .purge_webhook_deliveries(RETENTION_IN_DAYS)
+                .await?;
+            info!("[scheduler] Purged {purged} webhook deliveries");
+        }
+    }
+    Ok(())
+}
+
+/// A job without persisted state is enabled and never ran.
+fn is_job_due(
+    job: Job,
+    job_state: Option<&ScheduledJobState>,
+    now: OffsetDateTime,
+) -> anyhow::Result<bool> {
+    let Some(job_state) = job_state else {
+        return Ok(job.schedule()?.is_due(None, now));
+    };
+    Ok(job_state.run_requested
+        || (job_state.enabled && job.schedule()?.is_due(job_state.last_started_at, now)))
+}
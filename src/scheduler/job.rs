@@ -0,0 +1,305 @@
+use anyhow::{anyhow, Context};
+use std::fmt;
+use std::str::FromStr;
+use time::{Duration, OffsetDateTime};
+
+/// Periodic jobs run by the scheduling instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Job {
+    /// Dispatches queued grading tasks and times out the stuck ones
+    ScheduleGradingTasks,
+    /// Links the repositories of all GitHub app installations to their assignments
+    ResyncGithub,
+    /// Deletes webhooks kept for investigation for more than [`RETENTION_IN_DAYS`]
+    PurgeUnparseableWebhooks,
+    /// Deletes runner events, only needed for deduplication, older than [`RETENTION_IN_DAYS`]
+    PurgeRunnerEvents,
+    /// Deletes GitHub webhook deliveries older than [`RETENTION_IN_DAYS`]
+    PurgeWebhookDeliveries,
+}
+
+pub const RETENTION_IN_DAYS: i32 = 30;
+
+impl Job {
+    pub const ALL: [Self; 5] = [
+        Self::ScheduleGradingTasks,
+        Self::ResyncGithub,
+        Self::PurgeUnparseableWebhooks,
+        Self::PurgeRunnerEvents,
+        Self::PurgeWebhookDeliveries,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ScheduleGradingTasks => "schedule_grading_tasks",
+            Self::ResyncGithub => "resync_github",
+            Self::PurgeUnparseableWebhooks => "purge_unparseable_webhooks",
+            Self::PurgeRunnerEvents => "purge_runner_events",
+            Self::PurgeWebhookDeliveries => "purge_webhook_deliveries",
+        }
+    }
+
+    pub fn schedule(self) -> anyhow::Result<JobSchedule> {
+        Ok(match self {
+            Self::ScheduleGradingTasks => JobSchedule::EveryTick,
+            Self::ResyncGithub => JobSchedule::Every(Duration::hours(6)),
+            Self::PurgeUnparseableWebhooks => {
+                JobSchedule::Cron(CronExpression::from_str("0 3 * * *")?)
+            }
+            Self::PurgeRunnerEvents => JobSchedule::Cron(CronExpression::from_str("15 3 * * *")?),
+            Self::PurgeWebhookDeliveries => {
+                JobSchedule::Cron(CronExpression::from_str("30 3 * * *")?)
+            }
+        })
+    }
+}
+
+impl FromStr for Job {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|job| job.name() == name)
+            .ok_or_else(|| anyhow!("Unknown job: {name}"))
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Outcome of the last run of a job, persisted in `scheduled_job.last_status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobRunStatus {
+    Running,
+    Successful,
+    Failed,
+}
+
+impl fmt::Display for JobRunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Successful => write!(f, "successful"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobSchedule {
+    /// On each tick of the scheduler, see `SCHEDULER_INTERVAL_IN_SECS`
+    EveryTick,
+    Every(Duration),
+    Cron(CronExpression),
+}
+
+impl JobSchedule {
+    /// Whether the job should run at `now`, given the start time of its previous run.
+    ///
+    /// A cron job is due if a matching minute went by since its previous run,
+    /// or is the current one if it never ran.
+    #[must_use]
+    pub fn is_due(&self, last_started_at: Option<OffsetDateTime>, now: OffsetDateTime) -> bool {
+        match self {
+            Self::EveryTick => true,
+            Self::Every(interval) => {
+                !matches!(last_started_at, Some(last) if now - last < *interval)
+            }
+            Self::Cron(cron) => {
+                let now = truncate_to_minute(now);
+                let Some(last) = last_started_at.map(truncate_to_minute) else {
+                    return cron.matches(now);
+                };
+                // Missed runs are caught up once, whatever their number, by looking for the first match
+                // after the previous run, within a year as any satisfiable expression matches by then
+                let mut minute = last + Duration::minutes(1);
+                while minute <= now && minute - last <= Duration::days(366) {
+                    if cron.matches(minute) {
+                        return true;
+                    }
+                    minute += Duration::minutes(1);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for JobSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EveryTick => write!(f, "every tick"),
+            Self::Every(interval) => write!(f, "every {interval}"),
+            Self::Cron(cron) => write!(f, "{cron}"),
+        }
+    }
+}
+
+fn truncate_to_minute(time: OffsetDateTime) -> OffsetDateTime {
+    time.replace_second(0)
+        .and_then(|t| t.replace_nanosecond(0))
+        .unwrap_or(time)
+}
+
+/// Subset of the cron syntax, `minute hour day-of-month month day-of-week` (UTC),
+/// each field being `*`, `*/step`, or a comma separated list of values and `start-end` ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpression {
+    expression: String,
+    fields: [Vec<u8>; 5],
+}
+
+const CRON_FIELD_BOUNDS: [(u8, u8); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 6)];
+
+impl CronExpression {
+    #[must_use]
+    pub fn matches(&self, time: OffsetDateTime) -> bool {
+        let values = [
+            time.minute(),
+            time.hour(),
+            time.day(),
+            u8::from(time.month()),
+            time.weekday().number_days_from_sunday(),
+        ];
+        self.fields
+            .iter()
+            .zip(values)
+            .all(|(allowed, value)| allowed.contains(&value))
+    }
+
+    fn parse_field(field: &str, (min, max): (u8, u8)) -> anyhow::Result<Vec<u8>> {
+        if field == "*" {
+            return Ok((min..=max).collect());
+        }
+        if let Some(step) = field.strip_prefix("*/") {
+            let step: usize = step.parse().context("Invalid step")?;
+            if step == 0 {
+                Err(anyhow!("Step must be positive"))?;
+            }
+            return Ok((min..=max).step_by(step).collect());
+        }
+        let mut values = vec![];
+        for part in field.split(',') {
+            let (start, end) = if let Some((start, end)) = part.split_once('-') {
+                (start.parse::<u8>()?, end.parse::<u8>()?)
+            } else {
+                let value = part.parse::<u8>()?;
+                (value, value)
+            };
+            if start < min || end > max || start > end {
+                Err(anyhow!("{part} out of {min}-{max}"))?;
+            }
+            values.extend(start..=end);
+        }
+        Ok(values)
+    }
+}
+
+impl FromStr for CronExpression {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let raw_fields: Vec<&str> = expression.split_whitespace().collect();
+        let raw_fields: [&str; 5] = raw_fields
+            .try_into()
+            .map_err(|_| anyhow!("Cron expression must have 5 fields: {expression}"))?;
+        let mut fields: [Vec<u8>; 5] = Default::default();
+        for (index, raw_field) in raw_fields.iter().enumerate() {
+            fields[index] = Self::parse_field(raw_field, CRON_FIELD_BOUNDS[index])
+                .with_context(|| format!("Invalid cron field {raw_field} in {expression}"))?;
+        }
+        Ok(Self {
+            expression: expression.to_string(),
+            fields,
+        })
+    }
+}
+
+impl fmt::Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use time::{Date, Month};
+
+    /// A time in June 2024, the 3rd being a Monday
+    fn june(day: u8, hour: u8, minute: u8, second: u8) -> OffsetDateTime {
+        Date::from_calendar_date(2024, Month::June, day)
+            .unwrap()
+            .with_hms(hour, minute, second)
+            .unwrap()
+            .assume_utc()
+    }
+
+    #[test]
+    fn job_names_are_parsed_back() {
+        for job in Job::ALL {
+            assert_eq!(Job::from_str(job.name()).unwrap(), job);
+            assert!(job.schedule().is_ok());
+        }
+    }
+
+    #[test]
+    fn cron_matches_listed_ranges_and_steps() {
+        let cron = CronExpression::from_str("*/15 8-9,18 * * 1-5").unwrap();
+
+        assert!(cron.matches(june(3, 8, 30, 0)));
+        assert!(cron.matches(june(3, 18, 45, 0)));
+        assert!(!cron.matches(june(3, 8, 20, 0)));
+        assert!(!cron.matches(june(3, 10, 0, 0)));
+        // Sunday
+        assert!(!cron.matches(june(2, 8, 30, 0)));
+    }
+
+    #[test]
+    fn invalid_cron_is_rejected() {
+        assert!(CronExpression::from_str("* * * *").is_err());
+        assert!(CronExpression::from_str("60 * * * *").is_err());
+        assert!(CronExpression::from_str("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn interval_job_is_due_once_elapsed() {
+        let schedule = JobSchedule::Every(Duration::minutes(5));
+        let now = june(3, 8, 30, 0);
+
+        assert!(schedule.is_due(None, now));
+        assert!(!schedule.is_due(Some(now - Duration::minutes(4)), now));
+        assert!(schedule.is_due(Some(now - Duration::minutes(5)), now));
+    }
+
+    #[test]
+    fn cron_job_is_due_once_per_matching_minute() {
+        let schedule = JobSchedule::Cron(CronExpression::from_str("0 3 * * *").unwrap());
+
+        assert!(schedule.is_due(None, june(3, 3, 0, 20)));
+        assert!(!schedule.is_due(None, june(3, 3, 1, 0)));
+        assert!(!schedule.is_due(Some(june(3, 3, 0, 5)), june(3, 3, 0, 50)));
+        // Missed while no instance was leading
+        assert!(schedule.is_due(Some(june(2, 3, 0, 0)), june(3, 9, 12, 0)));
+        assert!(!schedule.is_due(Some(june(3, 3, 0, 0)), june(3, 9, 12, 0)));
+    }
+
+    #[test]
+    fn monthly_cron_job_is_not_due_before_next_month() {
+        let schedule = JobSchedule::Cron(CronExpression::from_str("0 0 1 * *").unwrap());
+        let now = june(11, 9, 0, 0);
+
+        assert!(!schedule.is_due(Some(now - Duration::days(8)), now));
+        assert!(!schedule.is_due(Some(june(1, 0, 0, 0)), june(30, 23, 59, 0)));
+        assert!(schedule.is_due(
+            Some(june(1, 0, 0, 0)),
+            june(30, 0, 0, 0) + Duration::days(1)
+        ));
+    }
+}
@@ -3,11 +3,16 @@ use sqlx::PgPool;
 use std::fmt;
 
 use crate::repository::Repository;
+use crate::service::events::GradingEvents;
 
 pub mod dtos;
+pub mod events;
 mod find_user_by_id;
 mod github;
+pub mod grade_aggregation;
+pub mod grade_export;
 mod grading_tasks;
+pub mod push_filter;
 mod teacher_assignment;
 mod teacher_module;
 pub(crate) mod trackable;
@@ -19,19 +24,26 @@ mod webhooks;
 #[derive(Clone)]
 pub struct Service {
     pub repo: Repository,
+    pub events: GradingEvents,
 }
 
 impl Service {
     #[must_use]
-    pub const fn new(pool: PgPool) -> Self {
+    pub fn new(pool: PgPool) -> Self {
         let repo = Repository::new(pool);
-        Self { repo }
+        Self {
+            repo,
+            events: GradingEvents::default(),
+        }
     }
 }
 
 impl From<Repository> for Service {
     fn from(repo: Repository) -> Self {
-        Self { repo }
+        Self {
+            repo,
+            events: GradingEvents::default(),
+        }
     }
 }
 
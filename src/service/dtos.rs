@@ -1,13 +1,18 @@
 use crate::entities;
 use crate::entities::{
-    Assignment, AssignmentGrade, Details, EmbeddedAssignmentDesc, GradingTask, InstantGrade,
-    Module, ModuleDesc, StudentGrades, UnparseableWebhook, UserAssignment, UserAssignmentDesc,
-    UserModule, UserModuleDesc,
+    Assignment, AssignmentFeedback, AssignmentGrade, Details, EmbeddedAssignmentDesc,
+    GradeOverride, GradingProgress, GradingRun, GradingRunStats, GradingTask, InstantGrade, Module,
+    ModuleDesc, ScheduledJobState, SchedulerLease, StudentGrades, UnparseableWebhook,
+    UserAssignment, UserAssignmentDesc, UserModule, UserModuleDesc, WebhookDelivery,
 };
+use crate::github::runner::validate_grader_ref;
 use crate::repository::grading_task::GradingStatus;
+use crate::scheduler::job::{Job, JobSchedule};
+use crate::service::push_filter::PushFilter;
 use crate::service::webhook_models::RunnerGradePart;
 use rust_decimal::Decimal;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use time::format_description::well_known::Iso8601;
 use time::serde::rfc3339 as dto_time_serde;
@@ -202,6 +207,16 @@ pub struct TeacherAssignmentResponse {
     pub grader_run_url: String,
     pub hidden_by_teacher: bool,
     pub grader_cli_v2: bool,
+    pub late_grace_period_in_secs: i32,
+    pub late_penalty_percentage_per_day: i32,
+    #[serde(with = "dto_time_serde::option")]
+    pub late_hard_cutoff: Option<OffsetDateTime>,
+    pub grade_aggregation: String,
+    pub grade_aggregation_last_n: i32,
+    pub accept_grades_after_timeout: bool,
+    pub push_filter: PushFilter,
+    pub grader_ref: Option<String>,
+    pub workflow_inputs: BTreeMap<String, String>,
 }
 
 impl From<Assignment> for TeacherAssignmentResponse {
@@ -220,6 +235,15 @@ impl From<Assignment> for TeacherAssignmentResponse {
             grader_run_url: value.grader_run_url,
             hidden_by_teacher: value.hidden_by_teacher,
             grader_cli_v2: value.grader_cli_v2,
+            late_grace_period_in_secs: value.late_grace_period_in_secs,
+            late_penalty_percentage_per_day: value.late_penalty_percentage_per_day,
+            late_hard_cutoff: value.late_hard_cutoff,
+            grade_aggregation: value.grade_aggregation,
+            grade_aggregation_last_n: value.grade_aggregation_last_n,
+            accept_grades_after_timeout: value.accept_grades_after_timeout,
+            push_filter: value.push_filter.0,
+            grader_ref: value.grader_ref,
+            workflow_inputs: value.workflow_inputs.0,
         }
     }
 }
@@ -263,6 +287,7 @@ impl TryFrom<entities::User> for UserForAdminResponse {
 
 #[derive(serde::Serialize, Debug, Clone)]
 pub struct UnparseableWebhookResponse {
+    pub id: i32,
     #[serde(with = "dto_time_serde")]
     pub created_at: OffsetDateTime,
     pub origin: String,
@@ -274,6 +299,7 @@ pub struct UnparseableWebhookResponse {
 impl From<UnparseableWebhook> for UnparseableWebhookResponse {
     fn from(value: UnparseableWebhook) -> Self {
         Self {
+            id: value.id,
             created_at: value.created_at,
             origin: value.origin,
             event: value.event,
@@ -283,6 +309,52 @@ impl From<UnparseableWebhook> for UnparseableWebhookResponse {
     }
 }
 
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct WebhookDeliveryResponse {
+    pub delivery_id: String,
+    pub event: String,
+    pub outcome: String,
+    pub error: Option<String>,
+    #[serde(with = "dto_time_serde")]
+    pub received_at: OffsetDateTime,
+    pub processing_time_in_ms: Option<i32>,
+    /// Number of times GitHub delivered it again after the first time
+    pub redeliveries: i32,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(value: WebhookDelivery) -> Self {
+        Self {
+            delivery_id: value.delivery_id,
+            event: value.event,
+            outcome: value.outcome,
+            error: value.error,
+            received_at: value.received_at,
+            processing_time_in_ms: value.processing_time_in_ms,
+            redeliveries: value.redeliveries,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookReplayOutcome {
+    /// Parsed and handled, the stored webhook is deleted
+    Replayed,
+    /// Still rejected by the current parser
+    Unparseable,
+    /// Parsed but its handling failed, the stored webhook is kept for a later replay
+    Failed,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookReplayResponse {
+    pub id: i32,
+    pub event: String,
+    pub outcome: WebhookReplayOutcome,
+    pub error: Option<String>,
+}
+
 #[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(
     feature = "automatic_test_feature",
@@ -386,6 +458,13 @@ pub struct UserAssignmentResponse {
     pub repository_url: String,
     pub factor_percentage: i32,
     pub normalized_grade: f32,
+    pub raw_normalized_grade: f32,
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub late_days: i32,
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub penalty_percentage: i32,
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub late_policy: LatePolicyResponse,
     #[cfg_attr(feature = "automatic_test_feature", builder(default))]
     pub status: Option<GradingStatus>,
     pub queue_due_to: i32,
@@ -425,6 +504,7 @@ fn compute_ongoing_run(value: &UserAssignment) -> Option<RunInfo> {
         short_commit_id: m.0.short_commit_id,
         commit_url: m.0.commit_url,
         grading_log_url: m.0.full_log_url,
+        progress: m.0.progress.map(Into::into),
     })
 }
 
@@ -467,6 +547,14 @@ impl TryFrom<UserAssignment> for UserAssignmentResponse {
             a_type: value.a_type,
             factor_percentage: value.factor_percentage,
             normalized_grade: value.normalized_grade,
+            raw_normalized_grade: value.raw_normalized_grade,
+            late_days: value.late_days,
+            penalty_percentage: value.penalty_percentage,
+            late_policy: LatePolicyResponse {
+                grace_period_in_secs: value.late_grace_period_in_secs,
+                penalty_percentage_per_day: value.late_penalty_percentage_per_day,
+                hard_cutoff: value.late_hard_cutoff,
+            },
             status,
             queue_due_to: value.queue_due_to,
             repo_linked: value.repo_linked,
@@ -483,11 +571,37 @@ impl TryFrom<UserAssignment> for UserAssignmentResponse {
     }
 }
 
-#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatePolicyResponse {
+    pub grace_period_in_secs: i32,
+    pub penalty_percentage_per_day: i32,
+    #[serde(with = "dto_time_serde::option")]
+    pub hard_cutoff: Option<OffsetDateTime>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
 pub struct RunInfo {
     pub short_commit_id: String,
     pub commit_url: String,
     pub grading_log_url: String,
+    pub progress: Option<RunProgressResponse>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct RunProgressResponse {
+    pub current_part: Option<String>,
+    pub parts: Vec<DetailsResponse>,
+    pub percentage: Option<f32>,
+}
+
+impl From<GradingProgress> for RunProgressResponse {
+    fn from(value: GradingProgress) -> Self {
+        Self {
+            current_part: value.current_part,
+            parts: value.parts.vec_into(),
+            percentage: value.percentage,
+        }
+    }
 }
 
 #[derive(serde::Serialize, Debug, Clone, PartialEq)]
@@ -503,6 +617,13 @@ pub struct CompleteRunInfoResponse {
     #[serde(with = "dto_time_serde")]
     pub time: OffsetDateTime,
     pub details: Vec<DetailsResponse>,
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    #[serde(with = "dto_time_serde::option")]
+    pub submitted_at: Option<OffsetDateTime>,
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub late_days: i32,
+    #[cfg_attr(feature = "automatic_test_feature", builder(default))]
+    pub penalty_percentage: i32,
 }
 
 impl From<InstantGrade> for CompleteRunInfoResponse {
@@ -513,6 +634,9 @@ impl From<InstantGrade> for CompleteRunInfoResponse {
             grading_log_url: value.grading_log_url,
             time: value.time,
             details: value.details.vec_into(),
+            submitted_at: value.submitted_at,
+            late_days: value.late_days,
+            penalty_percentage: value.penalty_percentage,
         }
     }
 }
@@ -543,6 +667,47 @@ impl From<Details> for DetailsResponse {
     }
 }
 
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct SchedulerLeaseResponse {
+    pub leader: String,
+    #[serde(with = "dto_time_serde")]
+    pub leader_since: OffsetDateTime,
+    #[serde(with = "dto_time_serde")]
+    pub last_tick_at: OffsetDateTime,
+}
+
+impl From<SchedulerLease> for SchedulerLeaseResponse {
+    fn from(value: SchedulerLease) -> Self {
+        Self {
+            leader: value.holder,
+            leader_since: value.acquired_at,
+            last_tick_at: value.last_tick_at,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, validator::Validate, Debug, Clone, Default)]
+pub struct GradingRequest {
+    /// Commit SHA or ref to grade, defaults to the head of the default branch
+    #[serde(rename = "ref")]
+    #[validate(custom(function = "validate_grader_ref"))]
+    pub commit_ref: Option<String>,
+}
+
+#[derive(serde::Deserialize, validator::Validate, Debug, Clone, Default)]
+pub struct MassGradingRequest {
+    #[serde(rename = "ref")]
+    #[validate(custom(function = "validate_grader_ref"))]
+    pub commit_ref: Option<String>,
+    /// Grade the last commit of each student whose committer date is before the assignment `stop`, ignoring `ref`,
+    /// which is the default once the assignment is over if no `ref` is given.
+    ///
+    /// Committer dates are set by the students' machines, so a commit pushed after the deadline
+    /// but dated before it is graded as on time.
+    #[serde(default)]
+    pub last_commit_dated_before_deadline: bool,
+}
+
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct NewGradeRequest {
     pub time: Option<OffsetDateTime>,
@@ -550,6 +715,9 @@ pub struct NewGradeRequest {
     pub commit_url: String,
     pub grading_log_url: String,
     pub details: Vec<NewGradeDetailRequest>,
+    /// When the graded work was submitted, used to apply the late policy
+    #[serde(default)]
+    pub submitted_at: Option<OffsetDateTime>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -584,10 +752,18 @@ impl From<NewGradeDetailRequest> for Details {
 
 #[derive(serde::Serialize, Debug, Clone)]
 pub struct GradingTaskResponse {
+    id: String,
     module_id: String,
     assignment_id: String,
     provider_login: String,
     status: String,
+    trigger: String,
+    priority: i32,
+    attempts: i32,
+    last_error: Option<String>,
+    #[serde(with = "dto_time_serde::option")]
+    not_before: Option<OffsetDateTime>,
+    commit_ref: Option<String>,
     #[serde(with = "dto_time_serde")]
     created_at: OffsetDateTime,
     #[serde(with = "dto_time_serde")]
@@ -598,10 +774,17 @@ pub struct GradingTaskResponse {
 impl From<GradingTask> for GradingTaskResponse {
     fn from(value: GradingTask) -> Self {
         Self {
+            id: value.uuid,
             module_id: value.module_uuid,
             assignment_id: value.assignment_uuid,
             provider_login: value.provider_login,
             status: value.status,
+            trigger: value.trigger,
+            priority: value.priority,
+            attempts: value.attempts,
+            last_error: value.last_error,
+            not_before: value.not_before,
+            commit_ref: value.commit_ref,
             created_at: value.created_at,
             updated_at: value.updated_at,
             repository_name: value.repository_name,
@@ -609,6 +792,93 @@ impl From<GradingTask> for GradingTaskResponse {
     }
 }
 
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct GradingRunResponse {
+    id: String,
+    module_id: String,
+    assignment_id: String,
+    provider_login: String,
+    repository_name: String,
+    grader_repository: String,
+    trigger: String,
+    last_status: String,
+    end_status: String,
+    attempts: i32,
+    #[serde(with = "dto_time_serde")]
+    queued_at: OffsetDateTime,
+    #[serde(with = "dto_time_serde::option")]
+    reserved_at: Option<OffsetDateTime>,
+    #[serde(with = "dto_time_serde::option")]
+    ordered_at: Option<OffsetDateTime>,
+    #[serde(with = "dto_time_serde::option")]
+    started_at: Option<OffsetDateTime>,
+    #[serde(with = "dto_time_serde")]
+    ended_at: OffsetDateTime,
+    queue_duration_in_secs: Option<i32>,
+    run_duration_in_secs: Option<i32>,
+    short_commit_id: Option<String>,
+    commit_url: Option<String>,
+    grading_log_url: Option<String>,
+    error: Option<String>,
+}
+
+impl From<GradingRun> for GradingRunResponse {
+    fn from(value: GradingRun) -> Self {
+        Self {
+            id: value.uuid,
+            module_id: value.module_uuid,
+            assignment_id: value.assignment_uuid,
+            provider_login: value.provider_login,
+            repository_name: value.repository,
+            grader_repository: value.grader_repository,
+            trigger: value.trigger,
+            last_status: value.last_status,
+            end_status: value.end_status,
+            attempts: value.attempts,
+            queued_at: value.queued_at,
+            reserved_at: value.reserved_at,
+            ordered_at: value.ordered_at,
+            started_at: value.started_at,
+            ended_at: value.ended_at,
+            queue_duration_in_secs: value.queue_duration_in_secs,
+            run_duration_in_secs: value.run_duration_in_secs,
+            short_commit_id: value.short_commit_id,
+            commit_url: value.commit_url,
+            grading_log_url: value.full_log_url,
+            error: value.error,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct GradingRunStatsResponse {
+    grader_repository: String,
+    count: i32,
+    successful: i32,
+    errored: i32,
+    timed_out: i32,
+    queue_duration_p50_in_secs: Option<f32>,
+    queue_duration_p95_in_secs: Option<f32>,
+    run_duration_p50_in_secs: Option<f32>,
+    run_duration_p95_in_secs: Option<f32>,
+}
+
+impl From<GradingRunStats> for GradingRunStatsResponse {
+    fn from(value: GradingRunStats) -> Self {
+        Self {
+            grader_repository: value.grader_repository,
+            count: value.count,
+            successful: value.successful,
+            errored: value.errored,
+            timed_out: value.timed_out,
+            queue_duration_p50_in_secs: value.queue_duration_p50_in_secs,
+            queue_duration_p95_in_secs: value.queue_duration_p95_in_secs,
+            run_duration_p50_in_secs: value.run_duration_p50_in_secs,
+            run_duration_p95_in_secs: value.run_duration_p95_in_secs,
+        }
+    }
+}
+
 #[derive(serde::Serialize, Debug, Clone)]
 pub struct ModuleGradesResponse {
     pub assignments: Vec<GradeAssignmentResponse>,
@@ -648,9 +918,83 @@ pub struct StudentGradesResponse {
     school_email: String,
     provider_login: String,
     grades: Vec<Decimal>,
+    details: Vec<StudentGradeDetailsResponse>,
     total: Decimal,
 }
 
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct StudentGradeDetailsResponse {
+    raw_grade: Decimal,
+    late_days: i32,
+    penalty_percentage: i32,
+    automatic_grade: Decimal,
+    grade_override: Option<GradeOverrideResponse>,
+}
+
+impl From<&AssignmentGrade> for StudentGradeDetailsResponse {
+    fn from(value: &AssignmentGrade) -> Self {
+        Self {
+            raw_grade: to_decimal(value.raw_grade),
+            late_days: value.late_days,
+            penalty_percentage: value.penalty_percentage,
+            automatic_grade: to_decimal(value.automatic_grade),
+            grade_override: value.grade_override.clone().map(Into::into),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct AssignmentFeedbackResponse {
+    pub id: String,
+    pub body: String,
+    pub short_commit_id: Option<String>,
+    pub teacher: Option<String>,
+    pub published: bool,
+    #[serde(with = "dto_time_serde::option")]
+    pub published_at: Option<OffsetDateTime>,
+    #[serde(with = "dto_time_serde")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "dto_time_serde")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl From<AssignmentFeedback> for AssignmentFeedbackResponse {
+    fn from(value: AssignmentFeedback) -> Self {
+        Self {
+            id: value.uuid,
+            body: value.body,
+            short_commit_id: value.short_commit_id,
+            teacher: value.teacher,
+            published: value.published_at.is_some(),
+            published_at: value.published_at,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct GradeOverrideResponse {
+    pub kind: String,
+    pub value: Option<Decimal>,
+    pub reason: String,
+    pub teacher: Option<String>,
+    #[serde(with = "dto_time_serde")]
+    pub created_at: OffsetDateTime,
+}
+
+impl From<GradeOverride> for GradeOverrideResponse {
+    fn from(value: GradeOverride) -> Self {
+        Self {
+            kind: value.kind,
+            value: value.value.map(to_decimal),
+            reason: value.reason,
+            teacher: value.teacher,
+            created_at: value.created_at,
+        }
+    }
+}
+
 impl From<StudentGrades> for StudentGradesResponse {
     fn from(value: StudentGrades) -> Self {
         Self {
@@ -659,13 +1003,54 @@ impl From<StudentGrades> for StudentGradesResponse {
             school_email: value.school_email,
             provider_login: value.provider_login,
             grades: value.grades.0.iter().map(|g| to_decimal(g.grade)).collect(),
+            details: value.grades.0.iter().map(Into::into).collect(),
             total: to_decimal(value.total),
         }
     }
 }
 
-fn to_decimal(value: f32) -> Decimal {
+pub(crate) fn to_decimal(value: f32) -> Decimal {
     Decimal::from_f32_retain(value)
         .unwrap_or_default()
         .round_dp(2)
 }
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ScheduledJobResponse {
+    pub name: String,
+    pub schedule: String,
+    pub enabled: bool,
+    /// Run requested by an admin, pending until the next tick of the scheduler
+    pub run_requested: bool,
+    #[serde(with = "dto_time_serde::option")]
+    pub last_started_at: Option<OffsetDateTime>,
+    #[serde(with = "dto_time_serde::option")]
+    pub last_ended_at: Option<OffsetDateTime>,
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+}
+
+impl ScheduledJobResponse {
+    #[must_use]
+    pub fn new(job: Job, schedule: &JobSchedule, state: Option<ScheduledJobState>) -> Self {
+        let state = state.unwrap_or_else(|| ScheduledJobState {
+            name: job.name().to_string(),
+            enabled: true,
+            run_requested: false,
+            last_started_at: None,
+            last_ended_at: None,
+            last_status: None,
+            last_error: None,
+        });
+        Self {
+            name: state.name,
+            schedule: schedule.to_string(),
+            enabled: state.enabled,
+            run_requested: state.run_requested,
+            last_started_at: state.last_started_at,
+            last_ended_at: state.last_ended_at,
+            last_status: state.last_status,
+            last_error: state.last_error,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::entities::{GradingEventContext, GradingMetadata};
+use crate::repository::grading_task::GradingStatus;
+use crate::service::Service;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// Events kept for subscribers lagging behind, older ones are dropped.
+const EVENTS_CAPACITY: usize = 256;
+/// Delay before listening again to the grading events once the connection is lost.
+const RELAY_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Longest error message notified along with a grading event, in bytes.
+const MAX_NOTIFIED_ERROR_LEN: usize = 4096;
+
+/// Transition of a grading task, pushed to connected clients.
+///
+/// Published through Postgres `NOTIFY`, so that clients connected to any instance get the events of all of them.
+#[derive(Serialize, Debug, Clone)]
+pub struct GradingEvent {
+    #[serde(skip)]
+    pub user_uuid: String,
+    pub module_id: String,
+    pub assignment_id: String,
+    pub provider_login: String,
+    pub task_id: String,
+    pub status: String,
+    pub running_grading_metadata: Option<GradingMetadata>,
+    /// Set once the grading ended successfully
+    pub normalized_grade: Option<f32>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct GradingEvents(broadcast::Sender<GradingEvent>);
+
+impl Default for GradingEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self(sender)
+    }
+}
+
+impl GradingEvents {
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<GradingEvent> {
+        self.0.subscribe()
+    }
+
+    fn has_subscribers(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    fn send(&self, event: GradingEvent) {
+        // No error as long as there are subscribers, they may all have left in between though
+        let _ = self.0.send(event);
+    }
+}
+
+/// Transition of a grading task as notified through Postgres, enriched into a [`GradingEvent`] by each instance.
+#[derive(Serialize, Deserialize, Debug)]
+struct GradingTransition {
+    user_assignment_id: i32,
+    task_id: String,
+    status: String,
+    error: Option<String>,
+}
+
+impl Service {
+    /// Notifies subscribers of all instances that a task reached the given status, failures are only logged.
+    ///
+    /// To be called once the transition is committed, as the event is enriched from the database.
+    pub async fn publish_grading_event(
+        &self,
+        user_assignment_id: i32,
+        task_uuid: &str,
+        status: &GradingStatus,
+        error: Option<&str>,
+    ) {
+        let transition = GradingTransition {
+            user_assignment_id,
+            task_id: task_uuid.to_string(),
+            status: status.to_string(),
+            error: error.map(truncate_error),
+        };
+        let notified = match serde_json::to_string(&transition) {
+            Ok(payload) => self.repo.notify_grading_event(&payload).await,
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = notified {
+            warn!(error = ?err, task_id = task_uuid, %status, "[service] publish_grading_event");
+        }
+    }
+
+    /// Forwards the grading events published by any instance to the subscribers of this one, never returning.
+    ///
+    /// Events notified while the connection to the database is lost are missed.
+    pub async fn relay_grading_events(&self) {
+        loop {
+            match self.repo.listen_grading_events().await {
+                Ok(mut listener) => loop {
+                    match listener.recv().await {
+                        Ok(notification) => {
+                            match serde_json::from_str::<GradingTransition>(notification.payload())
+                            {
+                                Ok(transition) => self.broadcast_grading_event(transition).await,
+                                Err(err) => {
+                                    warn!(error = ?err, payload = notification.payload(), "[service] relay_grading_events: Invalid grading event");
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            warn!(error = ?err, "[service] relay_grading_events: Connection lost");
+                            break;
+                        }
+                    }
+                },
+                Err(err) => warn!(error = ?err, "[service] relay_grading_events: Unable to listen"),
+            }
+            tokio::time::sleep(RELAY_RETRY_DELAY).await;
+        }
+    }
+
+    async fn broadcast_grading_event(&self, transition: GradingTransition) {
+        if !self.events.has_subscribers() {
+            return;
+        }
+        match self
+            .repo
+            .find_grading_event_context(transition.user_assignment_id)
+            .await
+        {
+            Ok(Some(context)) => self.events.send(GradingEvent::new(context, transition)),
+            Ok(None) => {}
+            Err(err) => {
+                warn!(error = ?err, task_id = transition.task_id, status = transition.status, "[service] broadcast_grading_event");
+            }
+        }
+    }
+}
+
+/// Keeps the notification payload under the 8000 bytes accepted by Postgres.
+fn truncate_error(error: &str) -> String {
+    let mut end = error.len().min(MAX_NOTIFIED_ERROR_LEN);
+    while !error.is_char_boundary(end) {
+        end -= 1;
+    }
+    error[..end].to_string()
+}
+
+impl GradingEvent {
+    fn new(context: GradingEventContext, transition: GradingTransition) -> Self {
+        Self {
+            user_uuid: context.user_uuid,
+            module_id: context.module_uuid,
+            assignment_id: context.assignment_uuid,
+            provider_login: context.provider_login,
+            normalized_grade: (transition.status == GradingStatus::SUCCESSFUL.to_string())
+                .then_some(context.normalized_grade),
+            task_id: transition.task_id,
+            status: transition.status,
+            running_grading_metadata: context.running_grading_metadata.map(|json| json.0),
+            error: transition.error,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! How the successive gradings of a user assignment are combined into its grade.
+
+use crate::entities::InstantGrade;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradeAggregation {
+    /// Best run ever
+    #[default]
+    BEST,
+    /// Most recent run
+    LATEST,
+    /// Most recent run which was not submitted late
+    LATEST_BEFORE_DEADLINE,
+    /// Average of the most recent runs
+    MEAN_OF_LAST_N,
+}
+
+impl FromStr for GradeAggregation {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "BEST" => Ok(Self::BEST),
+            "LATEST" => Ok(Self::LATEST),
+            "LATEST_BEFORE_DEADLINE" => Ok(Self::LATEST_BEFORE_DEADLINE),
+            "MEAN_OF_LAST_N" => Ok(Self::MEAN_OF_LAST_N),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GradeAggregation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Grade retained for a user assignment, normalized on 20
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AggregatedGrade {
+    pub normalized_grade: f32,
+    pub raw_normalized_grade: f32,
+    pub late_days: i32,
+    pub penalty_percentage: i32,
+}
+
+impl From<&InstantGrade> for AggregatedGrade {
+    fn from(grade: &InstantGrade) -> Self {
+        let raw_normalized_grade = if grade.max_grade > 0.0 {
+            grade.grade * 20.0 / grade.max_grade
+        } else {
+            0.0
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let normalized_grade =
+            raw_normalized_grade * (100 - grade.penalty_percentage) as f32 / 100.0;
+        Self {
+            normalized_grade,
+            raw_normalized_grade,
+            late_days: grade.late_days,
+            penalty_percentage: grade.penalty_percentage,
+        }
+    }
+}
+
+impl GradeAggregation {
+    /// `history` is expected in chronological order, `last_n`, at least 1 once validated,
+    /// is only used by [`Self::MEAN_OF_LAST_N`].
+    #[must_use]
+    pub fn aggregate(&self, history: &[InstantGrade], last_n: i32) -> AggregatedGrade {
+        let mut grades = history.iter().map(AggregatedGrade::from);
+        match self {
+            Self::BEST => grades
+                .reduce(|best, g| {
+                    if g.normalized_grade >= best.normalized_grade {
+                        g
+                    } else {
+                        best
+                    }
+                })
+                .unwrap_or_default(),
+            Self::LATEST => grades.next_back().unwrap_or_default(),
+            Self::LATEST_BEFORE_DEADLINE => grades.rfind(|g| g.late_days == 0).unwrap_or_default(),
+            Self::MEAN_OF_LAST_N => {
+                let n = usize::try_from(last_n).unwrap_or_default();
+                let last_grades: Vec<AggregatedGrade> =
+                    grades.skip(history.len().saturating_sub(n)).collect();
+                let Some(latest) = last_grades.last() else {
+                    return AggregatedGrade::default();
+                };
+                #[allow(clippy::cast_precision_loss)]
+                let count = last_grades.len() as f32;
+                AggregatedGrade {
+                    normalized_grade: last_grades.iter().map(|g| g.normalized_grade).sum::<f32>()
+                        / count,
+                    raw_normalized_grade: last_grades
+                        .iter()
+                        .map(|g| g.raw_normalized_grade)
+                        .sum::<f32>()
+                        / count,
+                    late_days: latest.late_days,
+                    penalty_percentage: latest.penalty_percentage,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use time::OffsetDateTime;
+
+    fn grade(grade: f32, late_days: i32, penalty_percentage: i32) -> InstantGrade {
+        InstantGrade {
+            grade,
+            max_grade: 10.0,
+            time: OffsetDateTime::UNIX_EPOCH,
+            short_commit_id: String::new(),
+            commit_url: String::new(),
+            grading_log_url: String::new(),
+            details: vec![],
+            submitted_at: None,
+            late_days,
+            penalty_percentage,
+        }
+    }
+
+    fn history() -> Vec<InstantGrade> {
+        vec![
+            grade(8.0, 0, 0),
+            grade(4.0, 0, 0),
+            grade(6.0, 0, 0),
+            grade(10.0, 2, 50),
+        ]
+    }
+
+    #[test]
+    fn best_keeps_highest_penalized_grade() {
+        let aggregated = GradeAggregation::BEST.aggregate(&history(), 0);
+        assert_eq!(aggregated.normalized_grade, 16.0);
+        assert_eq!(aggregated.late_days, 0);
+    }
+
+    #[test]
+    fn latest_keeps_last_grade() {
+        assert_eq!(
+            GradeAggregation::LATEST.aggregate(&history(), 0),
+            AggregatedGrade {
+                normalized_grade: 10.0,
+                raw_normalized_grade: 20.0,
+                late_days: 2,
+                penalty_percentage: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn latest_before_deadline_ignores_late_grades() {
+        assert_eq!(
+            GradeAggregation::LATEST_BEFORE_DEADLINE
+                .aggregate(&history(), 0)
+                .normalized_grade,
+            12.0
+        );
+    }
+
+    #[test]
+    fn mean_of_last_n_averages_most_recent_grades() {
+        assert_eq!(
+            GradeAggregation::MEAN_OF_LAST_N
+                .aggregate(&history(), 2)
+                .normalized_grade,
+            11.0
+        );
+        assert_eq!(
+            GradeAggregation::MEAN_OF_LAST_N
+                .aggregate(&history(), 10)
+                .normalized_grade,
+            11.5
+        );
+    }
+
+    #[test]
+    fn empty_history_is_graded_zero() {
+        assert_eq!(
+            GradeAggregation::MEAN_OF_LAST_N.aggregate(&[], 3),
+            AggregatedGrade::default()
+        );
+    }
+}
@@ -0,0 +1,596 @@
+//! Export of the grades of a module for the registrar, as CSV, XLSX or JSON.
+
+use crate::entities::{AssignmentGrade, StudentGrades};
+use crate::service::dtos::to_decimal;
+use anyhow::anyhow;
+use rust_decimal::Decimal;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Version of the JSON export schema, to be increased on any breaking change
+pub const JSON_SCHEMA_VERSION: i32 = 1;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GradeExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+    Json,
+}
+
+impl GradeExportFormat {
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::Json => "application/json",
+        }
+    }
+
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Group of columns of a CSV or XLSX export, the per-assignment ones expanding to a column per assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportColumn {
+    LastName,
+    FirstName,
+    SchoolEmail,
+    SchoolGroup,
+    ProviderLogin,
+    /// Grade of each assignment, overrides included
+    Grades,
+    /// Grade of each assignment computed from its runs
+    AutomaticGrades,
+    /// Late days and penalty of each assignment
+    Lateness,
+    /// Latest override of each assignment and its reason
+    Overrides,
+    Total,
+}
+
+impl ExportColumn {
+    pub const DEFAULT: [Self; 7] = [
+        Self::LastName,
+        Self::FirstName,
+        Self::SchoolEmail,
+        Self::SchoolGroup,
+        Self::ProviderLogin,
+        Self::Grades,
+        Self::Total,
+    ];
+
+    const ALL: [Self; 10] = [
+        Self::LastName,
+        Self::FirstName,
+        Self::SchoolEmail,
+        Self::SchoolGroup,
+        Self::ProviderLogin,
+        Self::Grades,
+        Self::AutomaticGrades,
+        Self::Lateness,
+        Self::Overrides,
+        Self::Total,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::LastName => "last_name",
+            Self::FirstName => "first_name",
+            Self::SchoolEmail => "school_email",
+            Self::SchoolGroup => "school_group",
+            Self::ProviderLogin => "provider_login",
+            Self::Grades => "grades",
+            Self::AutomaticGrades => "automatic_grades",
+            Self::Lateness => "lateness",
+            Self::Overrides => "overrides",
+            Self::Total => "total",
+        }
+    }
+}
+
+impl FromStr for ExportColumn {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|column| column.name() == name)
+            .ok_or_else(|| anyhow!("Unknown column: {name}"))
+    }
+}
+
+impl fmt::Display for ExportColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Options of `GET /fapi/teacher/module/:module_id/grade/export`, all optional
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct GradeExportQuery {
+    pub format: GradeExportFormat,
+    /// Comma separated columns, in order, see [`ExportColumn`]
+    pub columns: Option<String>,
+    /// CSV field separator, `,` by default
+    pub separator: Option<char>,
+    /// Whether CSV numbers use a decimal comma, requiring another separator
+    pub decimal_comma: bool,
+}
+
+impl GradeExportQuery {
+    pub fn columns(&self) -> anyhow::Result<Vec<ExportColumn>> {
+        self.columns.as_deref().map_or_else(
+            || Ok(ExportColumn::DEFAULT.to_vec()),
+            |columns| {
+                columns
+                    .split(',')
+                    .map(|column| ExportColumn::from_str(column.trim()))
+                    .collect()
+            },
+        )
+    }
+
+    pub fn separator(&self) -> anyhow::Result<char> {
+        let separator = self.separator.unwrap_or(',');
+        if matches!(separator, '"' | '\n' | '\r') {
+            Err(anyhow!("Invalid separator: {separator:?}"))?;
+        }
+        if self.decimal_comma && separator == ',' {
+            Err(anyhow!(
+                "A decimal comma requires another separator, like ;"
+            ))?;
+        }
+        Ok(separator)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cell {
+    Text(String),
+    Number(Decimal),
+    Empty,
+}
+
+/// Grades laid out as rows of cells, one per student sorted by name, under a header row
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl GradeTable {
+    #[must_use]
+    pub fn new(students: &[StudentGrades], columns: &[ExportColumn]) -> Self {
+        let assignments: &[AssignmentGrade] = students.first().map_or(&[], |s| &s.grades.0);
+        let mut headers = vec![];
+        for column in columns {
+            match column {
+                ExportColumn::Grades => {
+                    headers.extend(assignments.iter().map(|a| a.name.clone()));
+                }
+                ExportColumn::AutomaticGrades => {
+                    headers.extend(
+                        assignments
+                            .iter()
+                            .map(|a| format!("{} (automatic)", a.name)),
+                    );
+                }
+                ExportColumn::Lateness => {
+                    for assignment in assignments {
+                        headers.push(format!("{} late days", assignment.name));
+                        headers.push(format!("{} penalty %", assignment.name));
+                    }
+                }
+                ExportColumn::Overrides => {
+                    for assignment in assignments {
+                        headers.push(format!("{} override", assignment.name));
+                        headers.push(format!("{} override reason", assignment.name));
+                    }
+                }
+                _ => headers.push(column.to_string()),
+            }
+        }
+
+        let mut students: Vec<&StudentGrades> = students.iter().collect();
+        students.sort_by(|a, b| {
+            (&a.last_name, &a.first_name, &a.provider_login).cmp(&(
+                &b.last_name,
+                &b.first_name,
+                &b.provider_login,
+            ))
+        });
+        let rows = students
+            .into_iter()
+            .map(|student| {
+                let mut row = vec![];
+                for column in columns {
+                    Self::push_cells(&mut row, student, *column);
+                }
+                row
+            })
+            .collect();
+        Self { headers, rows }
+    }
+
+    fn push_cells(row: &mut Vec<Cell>, student: &StudentGrades, column: ExportColumn) {
+        let grades = &student.grades.0;
+        match column {
+            ExportColumn::LastName => row.push(Cell::Text(student.last_name.clone())),
+            ExportColumn::FirstName => row.push(Cell::Text(student.first_name.clone())),
+            ExportColumn::SchoolEmail => row.push(Cell::Text(student.school_email.clone())),
+            ExportColumn::SchoolGroup => row.push(Cell::Text(student.school_group.clone())),
+            ExportColumn::ProviderLogin => row.push(Cell::Text(student.provider_login.clone())),
+            ExportColumn::Grades => {
+                row.extend(grades.iter().map(|g| Cell::Number(to_decimal(g.grade))));
+            }
+            ExportColumn::AutomaticGrades => {
+                row.extend(
+                    grades
+                        .iter()
+                        .map(|g| Cell::Number(to_decimal(g.automatic_grade))),
+                );
+            }
+            ExportColumn::Lateness => {
+                for grade in grades {
+                    row.push(Cell::Number(grade.late_days.into()));
+                    row.push(Cell::Number(grade.penalty_percentage.into()));
+                }
+            }
+            ExportColumn::Overrides => {
+                for grade in grades {
+                    match grade.grade_override.as_ref().filter(|o| o.kind != "reset") {
+                        Some(grade_override) => {
+                            let value = grade_override
+                                .value
+                                .map(|v| format!(" {}", to_decimal(v)))
+                                .unwrap_or_default();
+                            row.push(Cell::Text(format!("{}{value}", grade_override.kind)));
+                            row.push(Cell::Text(grade_override.reason.clone()));
+                        }
+                        None => row.extend([Cell::Empty, Cell::Empty]),
+                    }
+                }
+            }
+            ExportColumn::Total => row.push(Cell::Number(to_decimal(student.total))),
+        }
+    }
+
+    /// Fields containing the separator, quotes or line breaks are quoted, lines end with CRLF.
+    #[must_use]
+    pub fn to_csv(&self, separator: char, decimal_comma: bool) -> String {
+        let mut csv = String::new();
+        let header = self.headers.iter().map(|h| Cell::Text(h.clone()));
+        for cells in std::iter::once(header.collect::<Vec<_>>()).chain(self.rows.iter().cloned()) {
+            let line: Vec<String> = cells
+                .iter()
+                .map(|cell| match cell {
+                    Cell::Text(text) => quote_csv_field(text, separator),
+                    Cell::Number(number) if decimal_comma => number.to_string().replace('.', ","),
+                    Cell::Number(number) => number.to_string(),
+                    Cell::Empty => String::new(),
+                })
+                .collect();
+            csv.push_str(&line.join(&separator.to_string()));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    pub fn to_xlsx(&self) -> anyhow::Result<Vec<u8>> {
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name("Grades")?;
+        let bold = Format::new().set_bold();
+        for (col, header) in (0u16..).zip(&self.headers) {
+            worksheet.write_string_with_format(0, col, header, &bold)?;
+        }
+        for (row, cells) in (1u32..).zip(&self.rows) {
+            for (col, cell) in (0u16..).zip(cells) {
+                match cell {
+                    Cell::Text(text) => {
+                        worksheet.write_string(row, col, text)?;
+                    }
+                    Cell::Number(number) => {
+                        worksheet.write_number(row, col, f64::try_from(*number)?)?;
+                    }
+                    Cell::Empty => {}
+                }
+            }
+        }
+        Ok(workbook.save_to_buffer()?)
+    }
+}
+
+/// Quotes the field if needed, text read as a formula by spreadsheets being prefixed with `'` (CSV injection).
+fn quote_csv_field(text: &str, separator: char) -> String {
+    let text = if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{text}")
+    } else {
+        text.to_string()
+    };
+    if text.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// Stable JSON export, independent of the API responses which follow the UI needs
+#[derive(Serialize, Debug, Clone)]
+pub struct GradeExportJson {
+    pub schema_version: i32,
+    pub assignments: Vec<GradeExportAssignment>,
+    pub students: Vec<GradeExportStudent>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GradeExportAssignment {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub a_type: String,
+    pub factor_percentage: i32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GradeExportStudent {
+    pub last_name: String,
+    pub first_name: String,
+    pub school_email: String,
+    pub school_group: String,
+    pub provider_login: String,
+    pub grades: Vec<GradeExportGrade>,
+    pub total: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GradeExportGrade {
+    pub assignment_id: String,
+    pub grade: Decimal,
+    pub automatic_grade: Decimal,
+    pub raw_grade: Decimal,
+    pub late_days: i32,
+    pub penalty_percentage: i32,
+    pub override_kind: Option<String>,
+    pub override_value: Option<Decimal>,
+    pub override_reason: Option<String>,
+}
+
+impl GradeExportJson {
+    #[must_use]
+    pub fn new(students: &[StudentGrades]) -> Self {
+        let assignments = students
+            .first()
+            .map(|s| {
+                s.grades
+                    .0
+                    .iter()
+                    .map(|a| GradeExportAssignment {
+                        id: a.id.clone(),
+                        name: a.name.clone(),
+                        a_type: a.a_type.clone(),
+                        factor_percentage: a.factor_percentage,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut students: Vec<GradeExportStudent> = students
+            .iter()
+            .map(|student| GradeExportStudent {
+                last_name: student.last_name.clone(),
+                first_name: student.first_name.clone(),
+                school_email: student.school_email.clone(),
+                school_group: student.school_group.clone(),
+                provider_login: student.provider_login.clone(),
+                grades: student.grades.0.iter().map(Into::into).collect(),
+                total: to_decimal(student.total),
+            })
+            .collect();
+        students.sort_by(|a, b| {
+            (&a.last_name, &a.first_name, &a.provider_login).cmp(&(
+                &b.last_name,
+                &b.first_name,
+                &b.provider_login,
+            ))
+        });
+        Self {
+            schema_version: JSON_SCHEMA_VERSION,
+            assignments,
+            students,
+        }
+    }
+}
+
+impl From<&AssignmentGrade> for GradeExportGrade {
+    fn from(value: &AssignmentGrade) -> Self {
+        let grade_override = value.grade_override.as_ref().filter(|o| o.kind != "reset");
+        Self {
+            assignment_id: value.id.clone(),
+            grade: to_decimal(value.grade),
+            automatic_grade: to_decimal(value.automatic_grade),
+            raw_grade: to_decimal(value.raw_grade),
+            late_days: value.late_days,
+            penalty_percentage: value.penalty_percentage,
+            override_kind: grade_override.map(|o| o.kind.clone()),
+            override_value: grade_override.and_then(|o| o.value).map(to_decimal),
+            override_reason: grade_override.map(|o| o.reason.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::GradeOverride;
+    use pretty_assertions::assert_eq;
+    use sqlx::types::Json;
+    use time::OffsetDateTime;
+
+    fn assignment_grade(name: &str, grade: f32) -> AssignmentGrade {
+        AssignmentGrade {
+            id: format!("{name}-id"),
+            a_type: "EXERCISE".to_string(),
+            name: name.to_string(),
+            description: String::new(),
+            grade,
+            factor_percentage: 50,
+            raw_grade: grade,
+            late_days: 0,
+            penalty_percentage: 0,
+            automatic_grade: grade,
+            grade_override: None,
+        }
+    }
+
+    fn student(last_name: &str, grades: Vec<AssignmentGrade>) -> StudentGrades {
+        let total = grades.iter().map(|g| g.grade / 2.0).sum();
+        StudentGrades {
+            id: 1,
+            uuid: String::new(),
+            first_name: "Jean".to_string(),
+            last_name: last_name.to_string(),
+            school_email: format!("{last_name}@school.fr"),
+            school_group: "G1".to_string(),
+            provider_login: last_name.to_lowercase(),
+            grades: Json(grades),
+            total,
+        }
+    }
+
+    #[test]
+    fn columns_are_parsed_in_order() {
+        let query = GradeExportQuery {
+            columns: Some("school_email, total,grades".to_string()),
+            ..GradeExportQuery::default()
+        };
+
+        assert_eq!(
+            query.columns().unwrap(),
+            vec![
+                ExportColumn::SchoolEmail,
+                ExportColumn::Total,
+                ExportColumn::Grades
+            ]
+        );
+        assert!(GradeExportQuery {
+            columns: Some("grade".to_string()),
+            ..GradeExportQuery::default()
+        }
+        .columns()
+        .is_err());
+    }
+
+    #[test]
+    fn decimal_comma_requires_another_separator() {
+        let query = GradeExportQuery {
+            decimal_comma: true,
+            ..GradeExportQuery::default()
+        };
+
+        assert!(query.separator().is_err());
+        assert_eq!(
+            GradeExportQuery {
+                separator: Some(';'),
+                ..query
+            }
+            .separator()
+            .unwrap(),
+            ';'
+        );
+    }
+
+    #[test]
+    fn csv_lists_students_by_name_with_a_column_per_assignment() {
+        let students = vec![
+            student(
+                "Martin",
+                vec![assignment_grade("a1", 12.5), assignment_grade("a2", 8.0)],
+            ),
+            student(
+                "Dupont, Jr",
+                vec![assignment_grade("a1", 20.0), assignment_grade("a2", 0.0)],
+            ),
+        ];
+        let table = GradeTable::new(
+            &students,
+            &[
+                ExportColumn::LastName,
+                ExportColumn::Grades,
+                ExportColumn::Total,
+            ],
+        );
+
+        assert_eq!(
+            table.to_csv(',', false),
+            "last_name,a1,a2,total\r\n\"Dupont, Jr\",20,0,10\r\nMartin,12.5,8,10.25\r\n"
+        );
+        assert_eq!(
+            table.to_csv(';', true),
+            "last_name;a1;a2;total\r\nDupont, Jr;20;0;10\r\nMartin;12,5;8;10,25\r\n"
+        );
+    }
+
+    #[test]
+    fn overrides_are_exported_with_their_reason() {
+        let mut overridden = assignment_grade("a1", 15.0);
+        overridden.automatic_grade = 9.0;
+        overridden.grade_override = Some(GradeOverride {
+            id: 1,
+            kind: "set".to_string(),
+            value: Some(15.0),
+            reason: "Oral defence".to_string(),
+            teacher: Some("teacher".to_string()),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+        });
+        let students = vec![student("Martin", vec![overridden])];
+
+        let table = GradeTable::new(
+            &students,
+            &[ExportColumn::AutomaticGrades, ExportColumn::Overrides],
+        );
+
+        assert_eq!(
+            table.headers,
+            vec!["a1 (automatic)", "a1 override", "a1 override reason"]
+        );
+        assert_eq!(
+            table.rows[0],
+            vec![
+                Cell::Number(Decimal::from(9)),
+                Cell::Text("set 15".to_string()),
+                Cell::Text("Oral defence".to_string())
+            ]
+        );
+        let json = GradeExportJson::new(&students);
+        assert_eq!(
+            json.students[0].grades[0].override_kind.as_deref(),
+            Some("set")
+        );
+    }
+
+    #[test]
+    fn formulas_are_not_evaluated_by_spreadsheets() {
+        assert_eq!(
+            quote_csv_field("=HYPERLINK(\"http://evil\")", ','),
+            "\"'=HYPERLINK(\"\"http://evil\"\")\""
+        );
+        assert_eq!(quote_csv_field("@SUM(A1)", ','), "'@SUM(A1)");
+        assert_eq!(quote_csv_field("-2+3", ','), "'-2+3");
+        assert_eq!(quote_csv_field("\tcmd", ','), "'\tcmd");
+        assert_eq!(quote_csv_field("\rcmd", ','), "\"'\rcmd\"");
+        assert_eq!(quote_csv_field("Martin", ','), "Martin");
+    }
+}
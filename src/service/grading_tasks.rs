@@ -1,20 +1,27 @@
-use crate::github;
-use crate::repository::{grading_task::GradingStatus, Repository};
+use crate::config::Config;
+use crate::entities::{GitHubGradingTask, GradingTaskScope, NewGradingTask, User};
+use crate::grading::{DispatchError, Dispatched, GradingBackend, RunnerLoad};
+use crate::repository::{grading_task::GradingStatus, PgTransaction, Repository};
 use crate::service::Service;
 use std::fmt;
+use time::OffsetDateTime;
 use tracing::warn;
 
+/// Upper bound of the delay between two dispatch attempts of the same task.
+const MAX_DISPATCH_BACKOFF_IN_SECS: i32 = 60 * 60;
+
 #[derive(Default)]
 pub struct TaskStats {
     pub ordered: i32,
     pub errored: i32,
+    pub retried: i32,
     pub ordered_timeout: usize,
     pub started_timeout: usize,
 }
 
 impl TaskStats {
     pub const fn total(&self) -> i32 {
-        self.ordered + self.errored
+        self.ordered + self.errored + self.retried
     }
 }
 
@@ -22,90 +29,295 @@ impl fmt::Display for TaskStats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Tasks: 🚀 ordered={}, ❌ errored={}, ⏱️ ordered_timeout={}, ⏱️ started_timeout={}",
-            self.ordered, self.errored, self.ordered_timeout, self.started_timeout
+            "Tasks: 🚀 ordered={}, ❌ errored={}, 🔁 retried={}, ⏱️ ordered_timeout={}, ⏱️ started_timeout={}",
+            self.ordered, self.errored, self.retried, self.ordered_timeout, self.started_timeout
         )
     }
 }
 
+/// Exponential backoff: `base`, `2 * base`, `4 * base`, ... capped to [`MAX_DISPATCH_BACKOFF_IN_SECS`].
+fn dispatch_backoff_in_secs(base_in_secs: i32, previous_attempts: i32) -> i32 {
+    let factor = 2_i32.saturating_pow(previous_attempts.clamp(0, 30).unsigned_abs());
+    base_in_secs
+        .saturating_mul(factor)
+        .min(MAX_DISPATCH_BACKOFF_IN_SECS)
+}
+
 impl Service {
     pub async fn schedule_tasks(
         &self,
-        min_execution_interval_in_secs: i32,
-        ordered_timeout_in_secs: i32,
-        started_timeout_in_secs: i32,
-        max_tasks: i32,
-        runner: &github::runner::Runner,
+        config: &Config,
+        backend: &dyn GradingBackend,
     ) -> anyhow::Result<TaskStats> {
         let mut stats = TaskStats::default();
 
-        self.launch_grading_tasks(
-            min_execution_interval_in_secs,
-            max_tasks,
-            runner,
-            &mut stats,
-        )
-        .await?;
+        self.launch_grading_tasks(config, backend, &mut stats)
+            .await?;
 
         stats.ordered_timeout += self
-            .repo
-            .timeout_grading_tasks(&GradingStatus::ORDERED, ordered_timeout_in_secs)
+            .timeout_grading_tasks(
+                &GradingStatus::ORDERED,
+                config.grading_ordered_timeout_in_secs,
+            )
             .await?;
         stats.started_timeout += self
-            .repo
-            .timeout_grading_tasks(&GradingStatus::STARTED, started_timeout_in_secs)
+            .timeout_grading_tasks(
+                &GradingStatus::STARTED,
+                config.grading_started_timeout_in_secs,
+            )
             .await?;
 
         Ok(stats)
     }
 
+    async fn timeout_grading_tasks(
+        &self,
+        status: &GradingStatus,
+        timeout_in_secs: i32,
+    ) -> anyhow::Result<usize> {
+        let timed_out = self
+            .repo
+            .timeout_grading_tasks(status, timeout_in_secs)
+            .await?;
+        let error = format!("Status {status} timed out after {timeout_in_secs} secs");
+        for task in &timed_out {
+            self.publish_grading_event(
+                task.user_assignment_id,
+                &task.uuid,
+                &GradingStatus::TIMEOUT,
+                Some(&error),
+            )
+            .await;
+        }
+        Ok(timed_out.len())
+    }
+
+    /// Queues a grading, merged with the one already queued for the same user assignment if any,
+    /// returning when it was queued, or `None` if out of the assignment time window when enforced.
+    pub async fn queue_grading_task(
+        &self,
+        task: &NewGradingTask,
+        enforce_time_window: bool,
+    ) -> anyhow::Result<Option<OffsetDateTime>> {
+        let queued = self
+            .repo
+            .upsert_grading_task(task, enforce_time_window)
+            .await?;
+        if let Some(queued) = &queued {
+            self.publish_grading_event(
+                queued.user_assignment_id,
+                &queued.uuid,
+                &GradingStatus::QUEUED,
+                None,
+            )
+            .await;
+        }
+        Ok(queued.map(|queued| queued.updated_at))
+    }
+
+    /// Cancels the tasks in the given scope, also stopping their gradings if already dispatched.
+    ///
+    /// If a `teacher` is given, only tasks of modules they own are cancelled.
+    pub async fn cancel_gradings(
+        &self,
+        scope: &GradingTaskScope,
+        teacher: Option<&User>,
+        backend: &dyn GradingBackend,
+    ) -> anyhow::Result<usize> {
+        let cancelled = self.repo.cancel_grading_tasks(scope, teacher).await?;
+        let dispatched_statuses = [
+            GradingStatus::ORDERED.to_string(),
+            GradingStatus::STARTED.to_string(),
+        ];
+        for task in cancelled
+            .iter()
+            .filter(|task| dispatched_statuses.contains(&task.status))
+        {
+            if let Err(err) = backend.cancel(task).await {
+                warn!(error = ?err, task_id = task.uuid, "[service] cancel_gradings: Unable to cancel the grading run");
+            }
+        }
+        for task in &cancelled {
+            self.publish_grading_event(
+                task.user_assignment_id,
+                &task.uuid,
+                &GradingStatus::CANCELLED,
+                None,
+            )
+            .await;
+        }
+        Ok(cancelled.len())
+    }
+
     async fn launch_grading_tasks(
         &self,
-        min_execution_interval_in_secs: i32,
-        max_tasks: i32,
-        runner: &github::runner::Runner,
+        config: &Config,
+        backend: &dyn GradingBackend,
         stats: &mut TaskStats,
     ) -> anyhow::Result<()> {
         let mut transaction = self.repo.start_transaction().await?;
 
+        let mut load: RunnerLoad =
+            Repository::count_in_flight_grading_tasks_by_runner_transact(&mut *transaction)
+                .await?
+                .into_iter()
+                .map(|in_flight| (in_flight.runner, in_flight.count))
+                .collect();
+
         let tasks = Repository::reserve_grading_tasks_to_execute_transact(
-            min_execution_interval_in_secs,
-            max_tasks,
+            config.min_grading_interval_in_secs,
+            backend.capacity().unwrap_or(config.max_parallel_gradings),
+            config.deadline_boost_window_in_secs,
             &mut *transaction,
         )
         .await?;
 
+        // Published once committed
+        let mut transitions = vec![];
+        // Started once committed, so that their status updates find the task ordered
+        let mut works = vec![];
         for task in &tasks {
-            let send_result = runner
-                .send_grading_command(task)
-                .await
-                .map_err(|err| format!("not ordered: {err:?}"));
-
-            match send_result {
-                Ok(()) => {
+            match backend.dispatch(task, &load).await {
+                Ok(Dispatched { runner, work }) => {
                     Repository::update_grading_task_non_terminal_status_transact(
                         &task.uuid,
                         &GradingStatus::ORDERED,
                         &mut *transaction,
                     )
                     .await?;
+                    if let Some(runner) = runner {
+                        Repository::set_grading_task_runner_transact(
+                            &task.uuid,
+                            &runner,
+                            &mut *transaction,
+                        )
+                        .await?;
+                        *load.entry(runner).or_default() += 1;
+                    }
+                    works.extend(work);
+                    transitions.push((task, GradingStatus::ORDERED, None));
                     stats.ordered += 1;
                 }
+                Err(DispatchError::Saturated) => {
+                    let released =
+                        Repository::release_grading_task_transact(&task.uuid, &mut *transaction)
+                            .await?;
+                    if released.is_none() {
+                        // A newer task is already queued for this assignment, it supersedes this one
+                        let err = format!("not ordered: {}", DispatchError::Saturated);
+                        Repository::delete_grading_task_transact(
+                            &task.uuid,
+                            Some(err.clone()),
+                            None,
+                            &mut *transaction,
+                        )
+                        .await?;
+                        transitions.push((task, GradingStatus::ERROR, Some(err)));
+                    }
+                }
+                Err(DispatchError::Transient(err))
+                    if task.attempts + 1 < config.grading_dispatch_max_attempts =>
+                {
+                    let (outcome, error_message) =
+                        Self::requeue_grading_task_transact(config, task, &err, &mut transaction)
+                            .await?;
+                    if outcome == GradingStatus::QUEUED {
+                        stats.retried += 1;
+                    } else {
+                        stats.errored += 1;
+                    }
+                    transitions.push((task, outcome, Some(error_message)));
+                }
                 Err(err) => {
+                    let err = format!("not ordered: {err}");
                     warn!("Grading task errored: {err}");
                     stats.errored += 1;
                     Repository::delete_grading_task_transact(
                         &task.uuid,
-                        Some(err),
+                        Some(err.clone()),
+                        None,
                         &mut *transaction,
                     )
                     .await?;
+                    transitions.push((task, GradingStatus::ERROR, Some(err)));
                 }
             }
         }
 
         transaction.commit().await?;
 
+        for work in works {
+            tokio::spawn(work);
+        }
+        for (task, status, error) in transitions {
+            self.publish_grading_event(
+                task.user_assignment_id,
+                &task.uuid,
+                &status,
+                error.as_deref(),
+            )
+            .await;
+        }
+
         Ok(())
     }
+
+    /// Queues again a task whose dispatch failed transiently, or ends it if a newer one is already queued.
+    async fn requeue_grading_task_transact(
+        config: &Config,
+        task: &GitHubGradingTask,
+        err: &anyhow::Error,
+        transaction: &mut PgTransaction<'_>,
+    ) -> anyhow::Result<(GradingStatus, String)> {
+        let backoff_in_secs =
+            dispatch_backoff_in_secs(config.grading_dispatch_backoff_in_secs, task.attempts);
+        warn!(
+            "Grading task {} not ordered (attempt {}), retrying in {backoff_in_secs} secs: {err:?}",
+            task.uuid,
+            task.attempts + 1
+        );
+        let error_message = format!("{err:?}");
+        let requeued = Repository::requeue_grading_task_transact(
+            &task.uuid,
+            &error_message,
+            backoff_in_secs,
+            &mut **transaction,
+        )
+        .await?;
+        if requeued.is_some() {
+            Ok((GradingStatus::QUEUED, error_message))
+        } else {
+            // A newer task is already queued for this assignment, it supersedes this one
+            let err = format!("not ordered: {error_message}");
+            Repository::delete_grading_task_transact(
+                &task.uuid,
+                Some(err.clone()),
+                None,
+                &mut **transaction,
+            )
+            .await?;
+            Ok((GradingStatus::ERROR, err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn dispatch_backoff_doubles_until_capped() {
+        assert_eq!(dispatch_backoff_in_secs(30, 0), 30);
+        assert_eq!(dispatch_backoff_in_secs(30, 1), 60);
+        assert_eq!(dispatch_backoff_in_secs(30, 3), 240);
+        assert_eq!(
+            dispatch_backoff_in_secs(30, 10),
+            MAX_DISPATCH_BACKOFF_IN_SECS
+        );
+        assert_eq!(
+            dispatch_backoff_in_secs(30, i32::MAX),
+            MAX_DISPATCH_BACKOFF_IN_SECS
+        );
+    }
 }
@@ -0,0 +1,252 @@
+//! Which pushes to a student repository trigger the grading of an assignment.
+
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PushFilter {
+    pub refs: RefFilter,
+    /// Globs of the files whose change triggers a grading, any file if empty
+    pub paths: Vec<String>,
+    /// Globs of the files whose change alone does not trigger a grading, like `**/*.md`
+    pub paths_ignore: Vec<String>,
+}
+
+/// Globs match the branch or tag name, `*` not crossing `/` unlike `**`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "patterns", rename_all = "snake_case")]
+pub enum RefFilter {
+    #[default]
+    DefaultBranch,
+    Branches(Vec<String>),
+    /// All tags if no pattern is given
+    Tags(Vec<String>),
+    All,
+}
+
+impl PushFilter {
+    /// Whether a push should be graded.
+    ///
+    /// Pushes without known changed files (new tags, forced pushes, etc.) are not filtered on paths.
+    ///
+    /// # Errors
+    /// If one of the globs is invalid, which [`PushFilter::validate`] prevents.
+    pub fn accepts(
+        &self,
+        git_ref: &str,
+        default_branch: Option<&str>,
+        changed_files: &[&str],
+    ) -> Result<bool, regex::Error> {
+        if !self.refs.accepts(git_ref, default_branch)? {
+            return Ok(false);
+        }
+        if changed_files.is_empty() {
+            return Ok(true);
+        }
+        let paths = compile_globs(&self.paths)?;
+        let paths_ignore = compile_globs(&self.paths_ignore)?;
+        let touches_paths =
+            self.paths.is_empty() || changed_files.iter().any(|file| paths.is_match(file));
+        let touches_only_ignored_paths =
+            changed_files.iter().all(|file| paths_ignore.is_match(file));
+        Ok(touches_paths && !touches_only_ignored_paths)
+    }
+
+    /// Checks that all the globs compile.
+    pub fn validate(&self) -> Result<(), regex::Error> {
+        match &self.refs {
+            RefFilter::Branches(patterns) | RefFilter::Tags(patterns) => {
+                compile_globs(patterns)?;
+            }
+            RefFilter::DefaultBranch | RefFilter::All => {}
+        }
+        compile_globs(&self.paths)?;
+        compile_globs(&self.paths_ignore)?;
+        Ok(())
+    }
+}
+
+impl RefFilter {
+    fn accepts(&self, git_ref: &str, default_branch: Option<&str>) -> Result<bool, regex::Error> {
+        Ok(match self {
+            // Accepted if the default branch is unknown
+            Self::DefaultBranch => {
+                default_branch.is_none() || git_ref.strip_prefix("refs/heads/") == default_branch
+            }
+            Self::Branches(patterns) => match git_ref.strip_prefix("refs/heads/") {
+                Some(branch) => compile_globs(patterns)?.is_match(branch),
+                None => false,
+            },
+            Self::Tags(patterns) => match git_ref.strip_prefix("refs/tags/") {
+                Some(tag) => patterns.is_empty() || compile_globs(patterns)?.is_match(tag),
+                None => false,
+            },
+            Self::All => true,
+        })
+    }
+}
+
+/// Compiles the globs at once, the set matching nothing if empty.
+fn compile_globs(globs: &[String]) -> Result<RegexSet, regex::Error> {
+    RegexSet::new(globs.iter().map(|glob| glob_to_regex(glob)))
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            // Any directory, including none
+            pattern.push_str("(?:.*/)?");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("**") {
+            pattern.push_str(".*");
+            rest = after;
+        } else {
+            match c {
+                '*' => pattern.push_str("[^/]*"),
+                '?' => pattern.push_str("[^/]"),
+                _ => pattern.push_str(&regex::escape(&c.to_string())),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn accepts(
+        filter: &PushFilter,
+        git_ref: &str,
+        default_branch: Option<&str>,
+        changed_files: &[&str],
+    ) -> bool {
+        filter
+            .accepts(git_ref, default_branch, changed_files)
+            .unwrap()
+    }
+
+    fn filter(refs: RefFilter) -> PushFilter {
+        PushFilter {
+            refs,
+            ..PushFilter::default()
+        }
+    }
+
+    #[test]
+    fn default_branch_only_by_default() {
+        let filter = PushFilter::default();
+
+        assert!(accepts(&filter, "refs/heads/main", Some("main"), &[]));
+        assert!(!accepts(&filter, "refs/heads/scratch", Some("main"), &[]));
+        assert!(!accepts(&filter, "refs/tags/main", Some("main"), &[]));
+    }
+
+    #[test]
+    fn branches_match_globs() {
+        let filter = filter(RefFilter::Branches(vec![
+            "main".to_string(),
+            "release/*".to_string(),
+        ]));
+
+        assert!(accepts(
+            &filter,
+            "refs/heads/release/1.0",
+            Some("main"),
+            &[]
+        ));
+        assert!(!accepts(
+            &filter,
+            "refs/heads/release/1.0/fix",
+            Some("main"),
+            &[]
+        ));
+        assert!(!accepts(&filter, "refs/heads/feature", Some("main"), &[]));
+    }
+
+    #[test]
+    fn tags_without_pattern_match_all_tags() {
+        let filter = filter(RefFilter::Tags(vec![]));
+
+        assert!(accepts(&filter, "refs/tags/v1", Some("main"), &[]));
+        assert!(!accepts(&filter, "refs/heads/main", Some("main"), &[]));
+    }
+
+    #[test]
+    fn push_touching_only_ignored_paths_is_skipped() {
+        let filter = PushFilter {
+            paths_ignore: vec!["**/*.md".to_string()],
+            ..PushFilter::default()
+        };
+
+        assert!(!accepts(
+            &filter,
+            "refs/heads/main",
+            Some("main"),
+            &["README.md", "doc/a.md"]
+        ));
+        assert!(accepts(
+            &filter,
+            "refs/heads/main",
+            Some("main"),
+            &["README.md", "src/main.rs"]
+        ));
+    }
+
+    #[test]
+    fn push_must_touch_one_of_the_paths() {
+        let filter = PushFilter {
+            paths: vec!["src/**".to_string(), "pom.xml".to_string()],
+            ..PushFilter::default()
+        };
+
+        assert!(accepts(
+            &filter,
+            "refs/heads/main",
+            Some("main"),
+            &["src/main/App.java"]
+        ));
+        assert!(!accepts(
+            &filter,
+            "refs/heads/main",
+            Some("main"),
+            &[".gitignore"]
+        ));
+    }
+
+    #[test]
+    fn filter_is_parsed_from_json() {
+        let filter: PushFilter =
+            serde_json::from_str(r#"{"refs": {"type": "branches", "patterns": ["main"]}}"#)
+                .unwrap();
+
+        assert_eq!(
+            filter,
+            self::filter(RefFilter::Branches(vec!["main".to_string()]))
+        );
+        assert_eq!(
+            serde_json::from_str::<PushFilter>("{}").unwrap(),
+            PushFilter::default()
+        );
+    }
+
+    #[test]
+    fn oversized_glob_is_invalid() {
+        let filter = PushFilter {
+            paths: vec!["?".repeat(100_000)],
+            ..PushFilter::default()
+        };
+
+        assert!(filter.validate().is_err());
+        assert!(filter
+            .accepts("refs/heads/main", Some("main"), &["a"])
+            .is_err());
+        assert!(PushFilter::default().validate().is_ok());
+    }
+}
@@ -1,32 +1,150 @@
-use crate::entities::{NewGradingTask, User};
+use crate::entities::{Assignment, NewAssignment, NewGradingTask, StudentGrades, User};
+use crate::github::client_cache::ClientCache;
+use crate::repository::grading_task::GradingTrigger;
+use crate::repository::{PgTransaction, Repository};
+use crate::service::dtos::MassGradingRequest;
+use crate::service::grade_aggregation::GradeAggregation;
 use crate::service::Service;
-use anyhow::Context;
-use tracing::info;
+use anyhow::{anyhow, Context};
+use std::str::FromStr;
+use time::OffsetDateTime;
+use tracing::{info, warn};
 
 impl Service {
+    /// Grades of all students are recomputed if the aggregation strategy changed.
+    pub async fn update_assignment(
+        &self,
+        module_uuid: &str,
+        uuid: &str,
+        assignment: &NewAssignment,
+        teacher: &User,
+    ) -> anyhow::Result<Assignment> {
+        let previous = self
+            .repo
+            .find_assignment(module_uuid, uuid, teacher)
+            .await?;
+        let mut transaction = self.repo.start_transaction().await?;
+        let updated = Repository::update_assignment_transact(
+            module_uuid,
+            uuid,
+            assignment,
+            teacher,
+            &mut *transaction,
+        )
+        .await?;
+
+        if previous.grade_aggregation != updated.grade_aggregation
+            || previous.grade_aggregation_last_n != updated.grade_aggregation_last_n
+        {
+            Self::recompute_assignment_grades_transact(&updated, &mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(updated)
+    }
+
+    async fn recompute_assignment_grades_transact(
+        assignment: &Assignment,
+        transaction: &mut PgTransaction<'_>,
+    ) -> anyhow::Result<()> {
+        let aggregation =
+            GradeAggregation::from_str(&assignment.grade_aggregation).unwrap_or_default();
+
+        let histories =
+            Repository::find_grades_histories_transact(assignment.id, &mut **transaction).await?;
+        let size = histories.len();
+        for history in histories {
+            let aggregated =
+                aggregation.aggregate(&history.grades_history, assignment.grade_aggregation_last_n);
+            Repository::set_aggregated_grade_transact(history.id, &aggregated, &mut **transaction)
+                .await?;
+        }
+
+        info!(
+            "[service] recompute_assignment_grades_transact(assignment_uuid={}, aggregation={aggregation}): {size} students",
+            assignment.uuid
+        );
+        Ok(())
+    }
+
+    /// In `last_commit_dated_before_deadline` mode, the default once the assignment is over and no `ref` is given,
+    /// the commit graded is on time, and students without any commit dated before the deadline are skipped.
+    /// The committer dates are trusted, see [`MassGradingRequest::last_commit_dated_before_deadline`].
+    ///
+    /// Otherwise the graded commits are considered submitted when queued, being late after the deadline.
     pub async fn trigger_mass_grading_for_assignment(
         &self,
         module_uuid: &str,
         assignment_uuid: &str,
+        request: &MassGradingRequest,
         user: &User,
+        github_clients: &ClientCache,
     ) -> anyhow::Result<()> {
+        let assignment = self
+            .repo
+            .find_assignment(module_uuid, assignment_uuid, user)
+            .await?;
+        let deadline_mode = request.last_commit_dated_before_deadline
+            || (request.commit_ref.is_none() && assignment.stop < OffsetDateTime::now_utc());
         let students = self.repo.get_module_grades(module_uuid, user).await?;
         let size = students.len();
         for student in students {
-            self.repo
-                .upsert_grading_task(
-                    &NewGradingTask::External {
-                        assignment_uuid: assignment_uuid.to_string(),
-                        user_uuid: student.uuid.to_string(),
-                    },
-                    false,
-                )
-                .await
-                .context(format!(
-                    "[service] trigger_mass_grading_for_assignment(student={student})"
-                ))?;
+            let (commit_ref, submitted_at) = if deadline_mode {
+                match self
+                    .last_commit_dated_before_deadline(&student, &assignment, github_clients)
+                    .await
+                {
+                    Ok(Some(sha)) => (Some(sha), Some(assignment.stop)),
+                    Ok(None) => {
+                        info!("[service] trigger_mass_grading_for_assignment(assignment_uuid={assignment_uuid}): no commit dated before deadline for {student}, skipping");
+                        continue;
+                    }
+                    Err(err) => {
+                        warn!(error = ?err, "[service] trigger_mass_grading_for_assignment(assignment_uuid={assignment_uuid}): unable to resolve last commit before deadline for {student}, skipping");
+                        continue;
+                    }
+                }
+            } else {
+                (request.commit_ref.clone(), None)
+            };
+            self.queue_grading_task(
+                &NewGradingTask::External {
+                    assignment_uuid: assignment_uuid.to_string(),
+                    user_uuid: student.uuid.to_string(),
+                    trigger: GradingTrigger::TEACHER,
+                    commit_ref,
+                    submitted_at,
+                },
+                false,
+            )
+            .await
+            .context(format!(
+                "[service] trigger_mass_grading_for_assignment(student={student})"
+            ))?;
         }
-        info!("[service] trigger_mass_grading_for_assignment(assignment_uuid={assignment_uuid}): {size} students");
+        info!("[service] trigger_mass_grading_for_assignment(assignment_uuid={assignment_uuid}, deadline_mode={deadline_mode}): {size} students");
         Ok(())
     }
+
+    async fn last_commit_dated_before_deadline(
+        &self,
+        student: &StudentGrades,
+        assignment: &Assignment,
+        github_clients: &ClientCache,
+    ) -> anyhow::Result<Option<String>> {
+        let installation_id = self
+            .repo
+            .find_user_by_id(&student.id)
+            .await?
+            .installation_id
+            .ok_or_else(|| anyhow!("Unknown installation"))?
+            .parse::<u64>()?;
+        github_clients
+            .get_for_installation(installation_id)?
+            .last_commit_dated_before(
+                &student.provider_login,
+                &assignment.repository_name,
+                assignment.stop,
+            )
+            .await
+    }
 }
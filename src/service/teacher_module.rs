@@ -1,5 +1,8 @@
 use crate::entities::User;
 use crate::service::dtos::{GradeAssignmentResponse, ModuleGradesResponse, StudentGradesResponse};
+use crate::service::grade_export::{
+    GradeExportFormat, GradeExportJson, GradeExportQuery, GradeTable,
+};
 use crate::service::Service;
 
 impl Service {
@@ -19,4 +22,21 @@ impl Service {
             students,
         })
     }
+
+    /// Renders the grades of a module in the requested format, options having been checked beforehand.
+    pub async fn export_module_grades(
+        &self,
+        uuid: &str,
+        teacher: &User,
+        query: &GradeExportQuery,
+    ) -> anyhow::Result<Vec<u8>> {
+        let entities = self.repo.get_module_grades(uuid, teacher).await?;
+        match query.format {
+            GradeExportFormat::Csv => Ok(GradeTable::new(&entities, &query.columns()?)
+                .to_csv(query.separator()?, query.decimal_comma)
+                .into_bytes()),
+            GradeExportFormat::Xlsx => GradeTable::new(&entities, &query.columns()?).to_xlsx(),
+            GradeExportFormat::Json => Ok(serde_json::to_vec(&GradeExportJson::new(&entities))?),
+        }
+    }
 }
@@ -1,5 +1,7 @@
+use crate::entities::{GradingRunFilter, User, WebhookDeliveryFilter};
 use crate::service::dtos::{
-    GradingTaskResponse, Page, PaginationQuery, UnparseableWebhookResponse, VecInto,
+    GradingRunResponse, GradingTaskResponse, Page, PaginationQuery, UnparseableWebhookResponse,
+    VecInto, WebhookDeliveryResponse,
 };
 use crate::service::Service;
 use anyhow::anyhow;
@@ -16,6 +18,17 @@ impl Service {
         .await
     }
 
+    pub async fn get_webhook_deliveries(
+        &self,
+        filter: &WebhookDeliveryFilter,
+        pagination: &PaginationQuery,
+    ) -> anyhow::Result<Page<WebhookDeliveryResponse>> {
+        self.get_trackable(pagination, |i1, i2| {
+            self.repo.get_webhook_deliveries(filter, i1, i2)
+        })
+        .await
+    }
+
     pub async fn get_grading_tasks(
         &self,
         pagination: &PaginationQuery,
@@ -24,6 +37,18 @@ impl Service {
             .await
     }
 
+    pub async fn get_grading_runs(
+        &self,
+        filter: &GradingRunFilter,
+        teacher: Option<&User>,
+        pagination: &PaginationQuery,
+    ) -> anyhow::Result<Page<GradingRunResponse>> {
+        self.get_trackable(pagination, |i1, i2| {
+            self.repo.get_grading_runs(filter, teacher, i1, i2)
+        })
+        .await
+    }
+
     async fn get_trackable<F, Fut, E, D>(
         &self,
         pagination: &PaginationQuery,
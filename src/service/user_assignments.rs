@@ -2,10 +2,12 @@ use crate::entities::{InstantGrade, User};
 use crate::github::client_cache::ClientCache;
 use crate::repository::Repository;
 use crate::service::dtos::{NewGradeRequest, UserAssignmentResponse, VecInto};
+use crate::service::grade_aggregation::GradeAggregation;
 use crate::service::{Service, SyncError};
 use http::StatusCode;
 use octocrab::Error;
-use sqlx::{Executor, Postgres};
+use sqlx::PgConnection;
+use std::str::FromStr;
 use time::OffsetDateTime;
 use tracing::info;
 
@@ -15,17 +17,28 @@ impl Service {
         user_assignment_id: i32,
         new_grade: NewGradeRequest,
     ) -> anyhow::Result<()> {
-        Self::update_assignment_grade_transact(user_assignment_id, new_grade, &self.repo.pool).await
+        let mut transaction = self.repo.start_transaction().await?;
+        Self::update_assignment_grade_transact(user_assignment_id, new_grade, &mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
     }
 
-    pub async fn update_assignment_grade_transact<'e, 'c: 'e, E>(
+    /// Records a new grade, penalized according to the assignment late policy
+    /// if the graded submission came after its `stop` date,
+    /// then recomputes the assignment grade with its aggregation strategy.
+    pub async fn update_assignment_grade_transact(
         user_assignment_id: i32,
         new_grade: NewGradeRequest,
-        transaction: E,
-    ) -> anyhow::Result<()>
-    where
-        E: 'e + Executor<'c, Database = Postgres>,
-    {
+        transaction: &mut PgConnection,
+    ) -> anyhow::Result<()> {
+        let rules =
+            Repository::find_grading_rules_transact(user_assignment_id, &mut *transaction).await?;
+        let lateness = new_grade
+            .submitted_at
+            .map(|submitted_at| rules.late_policy.lateness(submitted_at))
+            .unwrap_or_default();
+
         let grade: f32 = new_grade.details.iter().map(|d| d.grade).sum();
         let max_grade: f32 = new_grade
             .details
@@ -41,9 +54,24 @@ impl Service {
             commit_url: new_grade.commit_url,
             grading_log_url: new_grade.grading_log_url,
             details: new_grade.details.vec_into(),
+            submitted_at: new_grade.submitted_at,
+            late_days: lateness.late_days,
+            penalty_percentage: lateness.penalty_percentage,
         };
-        Repository::update_assignment_grade_transact(user_assignment_id, &grade_entity, transaction)
-            .await
+
+        let mut history = rules.grades_history.0;
+        history.push(grade_entity.clone());
+        let aggregated = GradeAggregation::from_str(&rules.grade_aggregation)
+            .unwrap_or_default()
+            .aggregate(&history, rules.grade_aggregation_last_n);
+
+        Repository::update_assignment_grade_transact(
+            user_assignment_id,
+            &grade_entity,
+            &aggregated,
+            transaction,
+        )
+        .await
     }
 
     pub async fn sync_repo(
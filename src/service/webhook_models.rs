@@ -1,13 +1,20 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct RunnerPayload {
+    /// Unique id of the event, deduplicating retried deliveries. Defaults to `{task_id}:{status}`
+    #[serde(default)]
+    pub event_id: Option<String>,
     pub status: RunnerStatus,
     pub student_login: String,
     pub grader_repo: String,
     pub task_id: String,
     pub full_log_url: String,
     pub details: Option<RunnerGradeDetails>,
+    /// Only sent along with [`RunnerStatus::Progress`] events
+    #[serde(default)]
+    pub progress: Option<RunnerProgress>,
     pub metadata: RunnerMetadata,
 }
 
@@ -15,10 +22,78 @@ pub struct RunnerPayload {
 #[serde(rename_all = "snake_case")]
 pub enum RunnerStatus {
     Started,
+    Progress,
     Completed,
     Failure,
 }
 
+impl fmt::Display for RunnerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self {
+            Self::Started => "started",
+            Self::Progress => "progress",
+            Self::Completed => "completed",
+            Self::Failure => "failure",
+        };
+        write!(f, "{status}")
+    }
+}
+
+impl RunnerPayload {
+    #[must_use]
+    pub fn dedupe_key(&self) -> String {
+        self.event_id
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", self.task_id, self.status))
+    }
+}
+
+/// What became of a runner event
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunnerEventOutcome {
+    Applied,
+    /// Already received, nothing done
+    Duplicate,
+    /// Not matching the current state of the grading (already ended, not started yet, etc.), nothing done
+    Stale,
+    UnknownTask,
+}
+
+impl fmt::Display for RunnerEventOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// What became of a GitHub webhook delivery, identified by its `x-github-delivery` header
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryOutcome {
+    /// Being processed, or the processing instance died
+    Received,
+    Processed,
+    /// Already received, nothing done
+    Duplicate,
+    InvalidSignature,
+    /// Stored as an unparseable webhook, see `/fapi/admin/unparseable_webhooks`
+    Unparseable,
+    Failed,
+}
+
+impl fmt::Display for WebhookDeliveryOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Received => write!(f, "received"),
+            Self::Processed => write!(f, "processed"),
+            Self::Duplicate => write!(f, "duplicate"),
+            Self::InvalidSignature => write!(f, "invalid_signature"),
+            Self::Unparseable => write!(f, "unparseable"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, PartialEq)]
 pub struct RunnerGradeDetails {
     pub grade: f32,
@@ -36,6 +111,17 @@ pub struct RunnerGradePart {
     pub comments: Vec<String>,
 }
 
+/// Partial results of a grading still running.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RunnerProgress {
+    /// Id of the part being graded
+    pub current_part: Option<String>,
+    /// Parts graded so far
+    #[serde(default)]
+    pub parts: Vec<RunnerGradePart>,
+    pub percentage: Option<f32>,
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 pub struct RunnerMetadata {
     pub commit_id: Option<String>,
@@ -45,7 +131,9 @@ pub struct RunnerMetadata {
 
 #[cfg(test)]
 mod tests {
-    use crate::service::webhook_models::{RunnerMetadata, RunnerPayload, RunnerStatus};
+    use crate::service::webhook_models::{
+        RunnerGradePart, RunnerMetadata, RunnerPayload, RunnerProgress, RunnerStatus,
+    };
     use pretty_assertions::assert_eq;
     use std::fs;
 
@@ -56,12 +144,14 @@ mod tests {
         let result: RunnerPayload = serde_json::from_str(&payload).unwrap();
 
         assert_eq!(result, RunnerPayload {
+            event_id: None,
             status: RunnerStatus::Failure,
             student_login: "ledoyen".to_string(),
             grader_repo: "lernejo/korekto-java-basics-grader".to_string(),
             task_id: "518e2eba-8d12-4833-ab5c-460b0e4a9fa6".to_string(),
             full_log_url: "https://github.com/lernejo/korekto-runner/actions/runs/9255315322".to_string(),
             details: None,
+            progress: None,
             metadata: RunnerMetadata {
                 commit_id: Some("cb2fa5425250371d70a9d06f4fb7202cd62b7738".to_string()),
                 short_commit_id: Some("cb2fa54".to_string()),
@@ -69,4 +159,26 @@ mod tests {
             },
         })
     }
+
+    #[test]
+    #[cfg_attr(not(feature = "tests-with-resources"), ignore)]
+    fn parse_progress_payload() {
+        let payload = fs::read_to_string("test_files/runner_webhook_progress.json").unwrap();
+        let result: RunnerPayload = serde_json::from_str(&payload).unwrap();
+
+        assert_eq!(result.status, RunnerStatus::Progress);
+        assert_eq!(
+            result.progress,
+            Some(RunnerProgress {
+                current_part: Some("Part 2 - Compilation".to_string()),
+                parts: vec![RunnerGradePart {
+                    id: "Part 1 - Git".to_string(),
+                    grade: 2.0,
+                    max_grade: Some(2.0),
+                    comments: vec![],
+                }],
+                percentage: Some(40.0),
+            })
+        );
+    }
 }
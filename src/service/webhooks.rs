@@ -1,18 +1,85 @@
-use crate::entities::{GradingMetadata, NewGradingTask};
-use crate::github::webhook_models::GhWebhookEvent;
-use crate::repository::grading_task::GradingStatus;
+use crate::entities::{
+    Assignment, EndedGradingRun, GradingMetadata, GradingProgress, GradingTaskScope, NewGradingTask,
+};
+use crate::github::webhook_models::{
+    parse_event, GhWebhookEvent, Push, RepositoryAction, WorkflowJobEvent,
+};
+use crate::repository::grading_task::{GradingStatus, GradingTrigger};
 use crate::repository::Repository;
-use crate::service::dtos::{NewGradeRequest, VecInto};
-use crate::service::webhook_models::{RunnerPayload, RunnerStatus};
+use crate::service::dtos::{
+    NewGradeDetailRequest, NewGradeRequest, VecInto, WebhookReplayOutcome, WebhookReplayResponse,
+};
+use crate::service::webhook_models::{
+    RunnerEventOutcome, RunnerGradeDetails, RunnerPayload, RunnerStatus, WebhookDeliveryOutcome,
+};
 use crate::service::Service;
+use sqlx::PgConnection;
+use std::time::Instant;
 use time::OffsetDateTime;
-use tracing::{debug, warn};
+use tracing::{debug, error, info, warn};
+
+/// Delay after which a delivery still being processed can be claimed again by a redelivery
+const DELIVERY_PROCESSING_TIMEOUT_IN_SECS: i32 = 10 * 60;
 
 impl Service {
+    /// Processes an authenticated GitHub delivery once, recording its outcome in the delivery log.
+    ///
+    /// Unparseable payloads are kept aside to be replayed once the models are fixed.
+    pub async fn on_github_delivery(
+        &self,
+        delivery_id: &str,
+        event_type: &str,
+        payload: &str,
+    ) -> anyhow::Result<WebhookDeliveryOutcome> {
+        if !self
+            .repo
+            .claim_webhook_delivery(delivery_id, event_type, DELIVERY_PROCESSING_TIMEOUT_IN_SECS)
+            .await?
+        {
+            info!("Ignoring duplicate GitHub delivery {delivery_id} ({event_type})");
+            return Ok(WebhookDeliveryOutcome::Duplicate);
+        }
+        let started_at = Instant::now();
+        let (outcome, error) = match parse_event(event_type, payload) {
+            Ok(event) => match self.on_webhook(event).await {
+                Ok(()) => (WebhookDeliveryOutcome::Processed, None),
+                Err(err) => {
+                    error!(error = ?err, delivery_id, event_type, "Unable to handle GitHub delivery");
+                    (WebhookDeliveryOutcome::Failed, Some(format!("{err:#}")))
+                }
+            },
+            Err(err) => match self
+                .repo
+                .insert_unparseable_webhook("github", event_type, payload, &err.to_string())
+                .await
+            {
+                Ok(()) => (WebhookDeliveryOutcome::Unparseable, Some(err.to_string())),
+                Err(insert_err) => {
+                    error!(error = ?insert_err, delivery_id, event_type, "Unable to keep unparseable GitHub delivery");
+                    (
+                        WebhookDeliveryOutcome::Failed,
+                        Some(format!("{insert_err:#}")),
+                    )
+                }
+            },
+        };
+        let processing_time_in_ms =
+            i32::try_from(started_at.elapsed().as_millis()).unwrap_or(i32::MAX);
+        self.repo
+            .end_webhook_delivery(
+                delivery_id,
+                outcome,
+                error.as_deref(),
+                processing_time_in_ms,
+            )
+            .await?;
+        Ok(outcome)
+    }
+
     pub async fn on_webhook(&self, event: GhWebhookEvent) -> anyhow::Result<()> {
         match event {
-            GhWebhookEvent::InstallationRepositories(ir) => {
-                if ir.action == crate::github::webhook_models::RepositoryAction::Added {
+            GhWebhookEvent::InstallationRepositories(ir) => match ir.action {
+                RepositoryAction::Added => {
                     let repo_names = ir
                         .repositories_added
                         .iter()
@@ -20,41 +87,213 @@ impl Service {
                         .collect::<Vec<&str>>();
                     self.link_repos(&ir.installation.account.login, repo_names)
                         .await?;
-                } else {
-                    warn!(
-                        "Unhandled webhook InstallationRepositories action: {:?}",
-                        ir.action
-                    );
                 }
-            }
-            GhWebhookEvent::Installation(i) => {
-                if i.action == crate::github::webhook_models::RepositoryAction::Created {
-                    let repo_names = i
-                        .repositories
+                RepositoryAction::Removed => {
+                    let repo_names = ir
+                        .repositories_removed
                         .iter()
-                        .map(|repo| repo.name.as_str())
+                        .map(Into::into)
                         .collect::<Vec<&str>>();
-                    self.link_repos(&i.installation.account.login, repo_names)
+                    self.unlink_repos(&ir.installation.account.login, Some(repo_names.as_slice()))
                         .await?;
-                } else {
-                    warn!("Unhandled webhook Installation action: {:?}", i.action);
+                }
+                _ => warn!(
+                    "Unhandled webhook InstallationRepositories action: {:?}",
+                    ir.action
+                ),
+            },
+            GhWebhookEvent::Installation(i) => {
+                let installation_id = i.installation.id.to_string();
+                match i.action {
+                    RepositoryAction::Created => {
+                        let repo_names = i
+                            .repositories
+                            .iter()
+                            .map(|repo| repo.name.as_str())
+                            .collect::<Vec<&str>>();
+                        self.link_repos(&i.installation.account.login, repo_names)
+                            .await?;
+                    }
+                    RepositoryAction::Deleted => {
+                        if let Some(login) =
+                            self.repo.clear_installation_id(&installation_id).await?
+                        {
+                            info!("GitHub app uninstalled by {login}");
+                        }
+                        self.unlink_repos(&i.installation.account.login, None)
+                            .await?;
+                    }
+                    RepositoryAction::Suspend | RepositoryAction::Unsuspend => {
+                        let suspended = i.action == RepositoryAction::Suspend;
+                        self.repo
+                            .set_installation_suspended(&installation_id, suspended)
+                            .await?;
+                    }
+                    _ => warn!("Unhandled webhook Installation action: {:?}", i.action),
                 }
             }
-            GhWebhookEvent::Push(p) => {
-                self.link_repos(&p.repository.owner.login, vec![&p.repository.name])
-                    .await?;
-            }
+            GhWebhookEvent::Push(p) => self.on_push(&p).await?,
             GhWebhookEvent::Repository(r) => {
-                if r.action == crate::github::webhook_models::RepositoryAction::Created {
-                    self.link_repos(&r.repository.owner.login, vec![&r.repository.name])
-                        .await?;
-                } else {
-                    warn!("Unhandled webhook Repository action: {:?}", r.action);
+                let owner = &r.repository.owner.login;
+                match r.action {
+                    RepositoryAction::Created => {
+                        self.link_repos(owner, vec![&r.repository.name]).await?;
+                    }
+                    RepositoryAction::Deleted => {
+                        self.unlink_repos(owner, Some(&[r.repository.name.as_str()]))
+                            .await?;
+                    }
+                    RepositoryAction::Renamed => {
+                        if let Some(previous_name) = r.previous_name() {
+                            self.unlink_repos(owner, Some(&[previous_name])).await?;
+                        }
+                        self.link_repos(owner, vec![&r.repository.name]).await?;
+                    }
+                    RepositoryAction::Transferred => {
+                        if let Some(previous_owner) = r.previous_owner() {
+                            self.unlink_repos(previous_owner, Some(&[r.repository.name.as_str()]))
+                                .await?;
+                        }
+                        self.link_repos(owner, vec![&r.repository.name]).await?;
+                    }
+                    _ => warn!("Unhandled webhook Repository action: {:?}", r.action),
                 }
             }
-            // Ignore these events as they carry no correlation information about the student or the assignment
-            GhWebhookEvent::WorkflowJob(_) => {}
+            GhWebhookEvent::WorkflowJob(wj) => self.on_workflow_job(&wj).await?,
+        }
+        Ok(())
+    }
+
+    /// Parses again stored webhooks with the current models, handling and deleting the ones now parseable.
+    ///
+    /// Replays all of them if no `id` is given, from the oldest to the newest so that events are applied in order.
+    pub async fn replay_unparseable_webhooks(
+        &self,
+        id: Option<i32>,
+    ) -> anyhow::Result<Vec<WebhookReplayResponse>> {
+        let webhooks = self.repo.find_unparseable_webhooks(id).await?;
+        let mut results = Vec::with_capacity(webhooks.len());
+        for webhook in webhooks {
+            let (outcome, error) = if webhook.origin == "github" {
+                match parse_event(&webhook.event, &webhook.payload) {
+                    Ok(event) => match self.on_webhook(event).await {
+                        Ok(()) => {
+                            self.repo.delete_unparseable_webhook(webhook.id).await?;
+                            (WebhookReplayOutcome::Replayed, None)
+                        }
+                        Err(err) => {
+                            error!(error = ?err, id = webhook.id, "Unable to handle replayed webhook");
+                            (WebhookReplayOutcome::Failed, Some(format!("{err:#}")))
+                        }
+                    },
+                    Err(err) => (WebhookReplayOutcome::Unparseable, Some(err.to_string())),
+                }
+            } else {
+                (
+                    WebhookReplayOutcome::Unparseable,
+                    Some(format!("Unsupported origin: {}", webhook.origin)),
+                )
+            };
+            results.push(WebhookReplayResponse {
+                id: webhook.id,
+                event: webhook.event,
+                outcome,
+                error,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Ends the grading task of a runner job completed without success, as its runner may never call back.
+    ///
+    /// Jobs of other repositories than runners, or not matching any dispatched task, are ignored.
+    async fn on_workflow_job(&self, event: &WorkflowJobEvent) -> anyhow::Result<()> {
+        let job = &event.workflow_job;
+        let Some(error_message) = job.failure() else {
+            return Ok(());
+        };
+        let run_url = format!(
+            "https://github.com/{}/actions/runs/{}",
+            event.repository.full_name, job.run_id
+        );
+        let mut transaction = self.repo.start_transaction().await?;
+        let Some(task) = Repository::find_dispatched_grading_task_transact(
+            &event.repository.full_name,
+            job.task_id(),
+            &run_url,
+            &mut *transaction,
+        )
+        .await?
+        else {
+            debug!("No grading task matching workflow job {}", job.html_url);
+            return Ok(());
+        };
+        let metadata = task.running_grading_metadata.map_or_else(
+            || GradingMetadata {
+                short_commit_id: "none".to_string(),
+                commit_url: "none".to_string(),
+                full_log_url: job.html_url.clone(),
+                progress: None,
+            },
+            |running| GradingMetadata {
+                full_log_url: job.html_url.clone(),
+                ..running.0
+            },
+        );
+        let ended = Repository::delete_grading_task_transact(
+            &task.uuid,
+            Some(error_message.to_string()),
+            Some(&metadata),
+            &mut *transaction,
+        )
+        .await?;
+        transaction.commit().await?;
+
+        info!(
+            "Grading task {} ended by workflow job {}: {error_message}",
+            task.uuid, job.html_url
+        );
+        self.publish_grading_event(
+            ended.user_assignment_id,
+            &task.uuid,
+            &GradingStatus::ERROR,
+            Some(error_message),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Unlinks repositories no longer reachable (deleted, renamed, etc.), all the ones of the user if none are given.
+    ///
+    /// Their gradings are cancelled, the ones already started being left to fail or time out.
+    pub async fn unlink_repos(
+        &self,
+        user_provider_login: &str,
+        repo_names: Option<&[&str]>,
+    ) -> anyhow::Result<()> {
+        let unlinked = self
+            .repo
+            .unlink_user_assignments(user_provider_login, repo_names)
+            .await?;
+        let scope = GradingTaskScope {
+            provider_login: Some(user_provider_login.to_string()),
+            repositories: repo_names.map(|names| names.iter().map(ToString::to_string).collect()),
+            ..GradingTaskScope::default()
         };
+        let cancelled = self.repo.cancel_grading_tasks(&scope, None).await?;
+        info!(
+            "Unlinked {unlinked} repositories of {user_provider_login} ({repo_names:?}), cancelling {} gradings",
+            cancelled.len()
+        );
+        for task in &cancelled {
+            self.publish_grading_event(
+                task.user_assignment_id,
+                &task.uuid,
+                &GradingStatus::CANCELLED,
+                None,
+            )
+            .await;
+        }
         Ok(())
     }
 
@@ -68,77 +307,199 @@ impl Service {
             .upsert_user_assignments(user_provider_login, &repo_names, true)
             .await?;
         for retained_assignment in retained_repos {
-            self.repo
-                .upsert_grading_task(
-                    &NewGradingTask::Internal {
-                        user_assignment_id: retained_assignment.id,
-                        user_provider_name: user_provider_login.to_string(),
-                        repository: retained_assignment.repository_name,
-                        grader_repository: retained_assignment.grader_url,
-                    },
-                    true,
-                )
+            self.queue_push_grading(user_provider_login, retained_assignment, None)
                 .await?;
         }
         Ok(())
     }
 
-    pub async fn on_runner_webhook(&self, event: &RunnerPayload) -> anyhow::Result<()> {
-        debug!("Received runner event: {event:?}");
-        match event.status {
-            RunnerStatus::Started => {
-                self.on_runner_event_started(event).await?;
-            }
-            RunnerStatus::Completed => {
-                self.on_runner_event_completed(event).await?;
-            }
-            RunnerStatus::Failure => {
-                self.repo
-                    .delete_grading_task(
-                        &event.task_id,
-                        Some("GitHub runner job failed".to_string()),
-                    )
-                    .await?;
-            }
+    /// Links the pushed repository, grading it for the assignments whose [push filter](crate::service::push_filter::PushFilter) accepts the push.
+    async fn on_push(&self, push: &Push) -> anyhow::Result<()> {
+        let user_provider_login = &push.repository.owner.login;
+        let retained_repos = self
+            .repo
+            .upsert_user_assignments(user_provider_login, &[push.repository.name.as_str()], true)
+            .await?;
+        if push.deleted {
+            return Ok(());
+        }
+        let changed_files = push.changed_files();
+        // The grader checks out the default branch unless told otherwise
+        let commit_ref = if push.is_to_default_branch() {
+            None
+        } else {
+            push.after.clone()
         };
+        for retained_assignment in retained_repos {
+            // Pushes are graded rather than lost if the filter, validated when saved, is broken
+            let accepted = retained_assignment
+                .push_filter
+                .accepts(
+                    &push.git_ref,
+                    push.repository.default_branch.as_deref(),
+                    &changed_files,
+                )
+                .unwrap_or_else(|err| {
+                    error!(error = ?err, assignment_uuid = %retained_assignment.uuid, "Invalid push filter");
+                    true
+                });
+            if accepted {
+                self.queue_push_grading(
+                    user_provider_login,
+                    retained_assignment,
+                    commit_ref.clone(),
+                )
+                .await?;
+            } else {
+                info!(
+                    "Ignoring push of {user_provider_login} to {} ({}), filtered out by assignment {}",
+                    push.repository.name, push.git_ref, retained_assignment.uuid
+                );
+            }
+        }
         Ok(())
     }
 
-    async fn on_runner_event_started(&self, event: &RunnerPayload) -> anyhow::Result<()> {
+    async fn queue_push_grading(
+        &self,
+        user_provider_login: &str,
+        assignment: Assignment,
+        commit_ref: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.queue_grading_task(
+            &NewGradingTask::Internal {
+                user_assignment_id: assignment.id,
+                user_provider_name: user_provider_login.to_string(),
+                repository: assignment.repository_name,
+                grader_repository: assignment.grader_url,
+                trigger: GradingTrigger::PUSH,
+                commit_ref,
+            },
+            true,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Applies a runner event if it matches the current state of its grading task.
+    ///
+    /// Events are recorded along with their outcome, so that a delivery received twice is not applied twice.
+    pub async fn on_runner_webhook(
+        &self,
+        event: &RunnerPayload,
+    ) -> anyhow::Result<RunnerEventOutcome> {
+        debug!("Received runner event: {event:?}");
         let mut transaction = self.repo.start_transaction().await?;
 
-        let raw_grading_task = Repository::update_grading_task_non_terminal_status_transact(
-            &event.task_id,
-            &GradingStatus::STARTED,
-            &mut *transaction,
-        )
-        .await?;
-        let metadata = GradingMetadata {
-            short_commit_id: event
-                .metadata
-                .short_commit_id
-                .clone()
-                .unwrap_or_else(|| "none".to_string()),
-            commit_url: event
-                .metadata
-                .commit_url
-                .clone()
-                .unwrap_or_else(|| "none".to_string()),
-            full_log_url: event.full_log_url.clone(),
+        let task_status =
+            Repository::find_grading_task_status_transact(&event.task_id, &mut *transaction)
+                .await?;
+        let (outcome, transition) = match task_status {
+            Some(task_status) if is_applicable(&event.status, &task_status) => (
+                RunnerEventOutcome::Applied,
+                Self::apply_runner_event_transact(event, &mut transaction).await?,
+            ),
+            Some(_) => (RunnerEventOutcome::Stale, None),
+            None => {
+                match Repository::find_ended_grading_run_transact(&event.task_id, &mut *transaction)
+                    .await?
+                {
+                    Some(run) if is_late_grade_accepted(event, &run) => (
+                        RunnerEventOutcome::Applied,
+                        Some(Self::apply_late_grade_transact(event, &run, &mut transaction).await?),
+                    ),
+                    Some(_) => (RunnerEventOutcome::Stale, None),
+                    None => (RunnerEventOutcome::UnknownTask, None),
+                }
+            }
         };
-        Repository::update_assignment_current_grading_metadata(
-            raw_grading_task.user_assignment_id,
-            &metadata,
-            &mut *transaction,
-        )
-        .await?;
 
+        // Progress events are not deduplicated, each one superseding the previous one anyway
+        if event.status != RunnerStatus::Progress
+            && !Repository::record_runner_event_transact(event, outcome, &mut *transaction).await?
+        {
+            // Dropping the transaction rolls back what was applied
+            info!("Ignoring duplicate runner event {}", event.dedupe_key());
+            return Ok(RunnerEventOutcome::Duplicate);
+        }
         transaction.commit().await?;
-        Ok(())
+
+        if let Some(transition) = transition {
+            self.publish_grading_event(
+                transition.user_assignment_id,
+                &event.task_id,
+                &transition.status,
+                transition.error.as_deref(),
+            )
+            .await;
+        } else {
+            info!(
+                "Ignoring runner event {} of grading task {}: {outcome}",
+                event.status, event.task_id
+            );
+        }
+        Ok(outcome)
     }
 
-    async fn on_runner_event_completed(&self, event: &RunnerPayload) -> anyhow::Result<()> {
-        let mut transaction = self.repo.start_transaction().await?;
+    async fn apply_runner_event_transact(
+        event: &RunnerPayload,
+        transaction: &mut PgConnection,
+    ) -> anyhow::Result<Option<Transition>> {
+        match event.status {
+            RunnerStatus::Started => {
+                let raw_grading_task =
+                    Repository::update_grading_task_non_terminal_status_transact(
+                        &event.task_id,
+                        &GradingStatus::STARTED,
+                        &mut *transaction,
+                    )
+                    .await?;
+                Repository::update_assignment_current_grading_metadata(
+                    raw_grading_task.user_assignment_id,
+                    &grading_metadata(event),
+                    &mut *transaction,
+                )
+                .await?;
+                Ok(Some(Transition::new(
+                    raw_grading_task.user_assignment_id,
+                    GradingStatus::STARTED,
+                    None,
+                )))
+            }
+            RunnerStatus::Progress => {
+                let user_assignment_id = Repository::update_running_grading_progress_transact(
+                    &event.task_id,
+                    &grading_metadata(event),
+                    &mut *transaction,
+                )
+                .await?;
+                Ok(user_assignment_id.map(|id| Transition::new(id, GradingStatus::STARTED, None)))
+            }
+            RunnerStatus::Completed => Self::on_runner_event_completed_transact(event, transaction)
+                .await
+                .map(Some),
+            RunnerStatus::Failure => {
+                let error_message = "GitHub runner job failed";
+                let task = Repository::delete_grading_task_transact(
+                    &event.task_id,
+                    Some(error_message.to_string()),
+                    Some(&grading_metadata(event)),
+                    &mut *transaction,
+                )
+                .await?;
+                Ok(Some(Transition::new(
+                    task.user_assignment_id,
+                    GradingStatus::ERROR,
+                    Some(error_message.to_string()),
+                )))
+            }
+        }
+    }
+
+    async fn on_runner_event_completed_transact(
+        event: &RunnerPayload,
+        transaction: &mut PgConnection,
+    ) -> anyhow::Result<Transition> {
         let error_message = if event.details.is_none() {
             Some("GitHub runner job completed without grading details".to_string())
         } else {
@@ -146,27 +507,14 @@ impl Service {
         };
         let task = Repository::delete_grading_task_transact(
             &event.task_id,
-            error_message,
+            error_message.clone(),
+            Some(&grading_metadata(event)),
             &mut *transaction,
         )
         .await?;
 
         if let Some(details) = &event.details {
-            let grade = NewGradeRequest {
-                time: Some(OffsetDateTime::now_utc()),
-                short_commit_id: event
-                    .metadata
-                    .short_commit_id
-                    .clone()
-                    .unwrap_or_else(|| "none".to_string()),
-                commit_url: event
-                    .metadata
-                    .commit_url
-                    .clone()
-                    .unwrap_or_else(|| "none".to_string()),
-                grading_log_url: event.full_log_url.clone(),
-                details: details.parts.clone().vec_into(),
-            };
+            let grade = new_grade(event, details, task.submitted_at.or(task.created_at));
             Self::update_assignment_grade_transact(
                 task.user_assignment_id,
                 grade,
@@ -175,7 +523,134 @@ impl Service {
             .await?;
         }
 
-        transaction.commit().await?;
-        Ok(())
+        let status = if error_message.is_none() {
+            GradingStatus::SUCCESSFUL
+        } else {
+            GradingStatus::ERROR
+        };
+        Ok(Transition::new(
+            task.user_assignment_id,
+            status,
+            error_message,
+        ))
+    }
+
+    /// Records the grade of a grading which timed out before its result was received.
+    async fn apply_late_grade_transact(
+        event: &RunnerPayload,
+        run: &EndedGradingRun,
+        transaction: &mut PgConnection,
+    ) -> anyhow::Result<Transition> {
+        if let Some(details) = &event.details {
+            let grade = new_grade(
+                event,
+                details,
+                Some(run.submitted_at.unwrap_or(run.queued_at)),
+            );
+            Self::update_assignment_grade_transact(
+                run.user_assignment_id,
+                grade,
+                &mut *transaction,
+            )
+            .await?;
+        }
+        Repository::record_late_grading_success_transact(
+            &event.task_id,
+            &grading_metadata(event),
+            &mut *transaction,
+        )
+        .await?;
+        Ok(Transition::new(
+            run.user_assignment_id,
+            GradingStatus::SUCCESSFUL,
+            None,
+        ))
+    }
+}
+
+/// Status reached by a grading once a runner event is applied, notified to subscribers after commit
+struct Transition {
+    user_assignment_id: i32,
+    status: GradingStatus,
+    error: Option<String>,
+}
+
+impl Transition {
+    const fn new(user_assignment_id: i32, status: GradingStatus, error: Option<String>) -> Self {
+        Self {
+            user_assignment_id,
+            status,
+            error,
+        }
+    }
+}
+
+/// Whether a runner event follows from the current status of its grading task
+const fn is_applicable(event_status: &RunnerStatus, task_status: &GradingStatus) -> bool {
+    matches!(
+        (event_status, task_status),
+        (
+            RunnerStatus::Started,
+            GradingStatus::RESERVED | GradingStatus::ORDERED
+        ) | (
+            RunnerStatus::Progress | RunnerStatus::Completed | RunnerStatus::Failure,
+            GradingStatus::RESERVED | GradingStatus::ORDERED | GradingStatus::STARTED
+        )
+    )
+}
+
+fn is_late_grade_accepted(event: &RunnerPayload, run: &EndedGradingRun) -> bool {
+    event.status == RunnerStatus::Completed
+        && event.details.is_some()
+        && run.accept_grades_after_timeout
+        && run.end_status == GradingStatus::TIMEOUT.to_string()
+}
+
+/// Whatever its trigger, a grading is late if the graded commit was submitted after the assignment deadline
+fn new_grade(
+    event: &RunnerPayload,
+    details: &RunnerGradeDetails,
+    submitted_at: Option<OffsetDateTime>,
+) -> NewGradeRequest {
+    NewGradeRequest {
+        time: Some(OffsetDateTime::now_utc()),
+        short_commit_id: event
+            .metadata
+            .short_commit_id
+            .clone()
+            .unwrap_or_else(|| "none".to_string()),
+        commit_url: event
+            .metadata
+            .commit_url
+            .clone()
+            .unwrap_or_else(|| "none".to_string()),
+        grading_log_url: event.full_log_url.clone(),
+        details: details.parts.clone().vec_into(),
+        submitted_at,
+    }
+}
+
+fn grading_metadata(event: &RunnerPayload) -> GradingMetadata {
+    GradingMetadata {
+        short_commit_id: event
+            .metadata
+            .short_commit_id
+            .clone()
+            .unwrap_or_else(|| "none".to_string()),
+        commit_url: event
+            .metadata
+            .commit_url
+            .clone()
+            .unwrap_or_else(|| "none".to_string()),
+        full_log_url: event.full_log_url.clone(),
+        progress: event.progress.clone().map(|progress| GradingProgress {
+            current_part: progress.current_part,
+            parts: progress
+                .parts
+                .into_iter()
+                .map(|part| NewGradeDetailRequest::from(part).into())
+                .collect(),
+            percentage: progress.percentage,
+        }),
     }
 }
@@ -1,14 +1,24 @@
 use crate::scheduler::Scheduler;
+use crate::service::Service;
 use axum::Router;
 
 pub struct KorektoService {
     router: Router,
     scheduler: Scheduler,
+    service: Service,
 }
 
 impl KorektoService {
-    pub const fn new(router: Router, scheduler: Scheduler) -> Result<Self, shuttle_runtime::Error> {
-        Ok(Self { router, scheduler })
+    pub const fn new(
+        router: Router,
+        scheduler: Scheduler,
+        service: Service,
+    ) -> Result<Self, shuttle_runtime::Error> {
+        Ok(Self {
+            router,
+            scheduler,
+            service,
+        })
     }
 }
 
@@ -23,7 +33,11 @@ impl shuttle_runtime::Service for KorektoService {
             .await
         };
 
-        let (_scheduler_hdl, _axum_hdl) = tokio::join!(self.scheduler.start(), server);
+        let (_scheduler_hdl, (), _axum_hdl) = tokio::join!(
+            self.scheduler.start(),
+            self.service.relay_grading_events(),
+            server
+        );
 
         Ok(())
     }
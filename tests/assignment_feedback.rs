@@ -0,0 +1,89 @@
+use korekto::entities::{NewAssignmentBuilder, NewAssignmentFeedback};
+use korekto::service::Service;
+
+mod common;
+mod fixtures;
+
+fn feedback(body: &str, published: bool) -> NewAssignmentFeedback {
+    NewAssignmentFeedback {
+        body: body.to_string(),
+        short_commit_id: Some("toto123".to_string()),
+        published,
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn draft_feedback_is_hidden_from_students() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let service = Service::from(repo.clone());
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let student = &students[0];
+    let module = &repo.find_modules(&teacher).await?[0];
+
+    let draft = repo
+        .insert_assignment_feedback(
+            &module.uuid,
+            &assignment.uuid,
+            "student",
+            &feedback("**Draft**", false),
+            &teacher,
+        )
+        .await?
+        .expect("Feedback recorded");
+    let hidden = repo
+        .find_published_assignment_feedback(student, &module.uuid, &assignment.uuid)
+        .await?;
+    let published = repo
+        .update_assignment_feedback(
+            &module.uuid,
+            &assignment.uuid,
+            &draft.uuid,
+            &feedback("Well done", true),
+            &teacher,
+        )
+        .await?
+        .expect("Feedback updated");
+    let visible = repo
+        .find_published_assignment_feedback(student, &module.uuid, &assignment.uuid)
+        .await?;
+
+    pretty_assertions::assert_eq!(hidden.len(), 0, "Visible drafts");
+    assert!(published.published_at.is_some());
+    pretty_assertions::assert_eq!(
+        visible.iter().map(|f| f.body.as_str()).collect::<Vec<_>>(),
+        vec!["Well done"]
+    );
+    pretty_assertions::assert_eq!(visible[0].teacher.as_deref(), Some("teacher-login"));
+    pretty_assertions::assert_eq!(
+        repo.delete_assignment_feedback(&module.uuid, &assignment.uuid, &draft.uuid, student)
+            .await?,
+        0,
+        "Deleted by a student"
+    );
+
+    service
+        .update_assignment(
+            &module.uuid,
+            &assignment.uuid,
+            &NewAssignmentBuilder::default()
+                .name("a1")
+                .factor_percentage(100)
+                .repository_name("a1")
+                .hidden_by_teacher(true)
+                .build()?,
+            &teacher,
+        )
+        .await?;
+    let hidden_assignment = repo
+        .find_published_assignment_feedback(student, &module.uuid, &assignment.uuid)
+        .await?;
+    pretty_assertions::assert_eq!(
+        hidden_assignment.len(),
+        0,
+        "Visible feedback on a hidden assignment"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use korekto::entities::{Assignment, NewAssignmentBuilder, NewModuleBuilder, NewUserBuilder, User};
+use korekto::repository::Repository;
+use time::OffsetDateTime;
+
+/// Creates a module with an assignment `a1`, its students being members of the module with a linked repository.
+pub async fn create_assignment_with_students(
+    repo: &Repository,
+    logins: &[&str],
+) -> anyhow::Result<(User, Assignment, Vec<User>)> {
+    let teacher = repo
+        .upsert_user(
+            &NewUserBuilder::default()
+                .provider_name("Teacher")
+                .provider_login("teacher-login")
+                .provider_email("teacher@test.com")
+                .avatar_url("")
+                .build()?,
+        )
+        .await?;
+    let module = repo
+        .create_module(
+            &NewModuleBuilder::default()
+                .name("test")
+                .description("test")
+                .start(OffsetDateTime::UNIX_EPOCH)
+                .stop(OffsetDateTime::UNIX_EPOCH)
+                .unlock_key("test")
+                .source_url("test")
+                .build()?,
+            &teacher,
+        )
+        .await?;
+    let assignment = repo
+        .create_assignment(
+            &module.uuid,
+            &NewAssignmentBuilder::default()
+                .name("a1")
+                .factor_percentage(100)
+                .repository_name("a1")
+                .build()?,
+            &teacher,
+        )
+        .await?;
+
+    let mut students = vec![];
+    for login in logins {
+        let student = repo
+            .upsert_user(
+                &NewUserBuilder::default()
+                    .provider_name(*login)
+                    .provider_login(*login)
+                    .provider_email(format!("{login}@test.com"))
+                    .avatar_url("")
+                    .build()?,
+            )
+            .await?;
+        repo.create_user_module(&student, module.id).await?;
+        repo.update_installation_id(&student.id, "42").await?;
+        repo.upsert_user_assignments(login, &["a1"], true).await?;
+        students.push(student);
+    }
+
+    Ok((teacher, assignment, students))
+}
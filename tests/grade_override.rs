@@ -0,0 +1,154 @@
+use korekto::entities::{GradeChange, NewGradeOverride, User};
+use korekto::repository::Repository;
+use korekto::service::dtos::{NewGradeDetailRequest, NewGradeRequest};
+use korekto::service::Service;
+use time::OffsetDateTime;
+
+mod common;
+mod fixtures;
+
+fn grade_request(grade: f32) -> NewGradeRequest {
+    NewGradeRequest {
+        time: Some(OffsetDateTime::UNIX_EPOCH),
+        short_commit_id: "toto123".to_string(),
+        commit_url: "githubmachin/commits/toto123".to_string(),
+        grading_log_url: "githubmachin/job/log".to_string(),
+        details: vec![NewGradeDetailRequest {
+            name: "step 1".to_string(),
+            grade,
+            max_grade: Some(20.0),
+            messages: vec![],
+        }],
+        submitted_at: None,
+    }
+}
+
+/// Grade and automatic grade of the only student of the module
+async fn student_grades(
+    repo: &Repository,
+    module_uuid: &str,
+    teacher: &User,
+) -> anyhow::Result<(f32, f32, Option<String>)> {
+    let grades = repo.get_module_grades(module_uuid, teacher).await?;
+    let grade = &grades[0].grades.0[0];
+    Ok((
+        grade.grade,
+        grade.automatic_grade,
+        grade.grade_override.as_ref().map(|o| o.kind.clone()),
+    ))
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn grade_overrides_survive_automatic_gradings() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let service = Service::from(repo.clone());
+    let (teacher, assignment, _) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let module = &repo.find_modules(&teacher).await?[0];
+    let user_assignments = repo
+        .upsert_user_assignments("student", &["a1"], true)
+        .await?;
+    service
+        .update_assignment_grade(user_assignments[0].id, grade_request(10.0))
+        .await?;
+
+    let mut observed = vec![];
+    for change in [
+        GradeChange::Adjust { points: 2.0 },
+        GradeChange::Set { grade: 15.0 },
+    ] {
+        let recorded = repo
+            .insert_grade_override(
+                &module.uuid,
+                &assignment.uuid,
+                "student",
+                &NewGradeOverride {
+                    change,
+                    reason: "Oral defence".to_string(),
+                },
+                &teacher,
+            )
+            .await?;
+        pretty_assertions::assert_eq!(
+            recorded.and_then(|o| o.teacher),
+            Some("teacher-login".to_string())
+        );
+        observed.push(student_grades(&repo, &module.uuid, &teacher).await?);
+    }
+    service
+        .update_assignment_grade(user_assignments[0].id, grade_request(12.0))
+        .await?;
+    observed.push(student_grades(&repo, &module.uuid, &teacher).await?);
+    repo.insert_grade_override(
+        &module.uuid,
+        &assignment.uuid,
+        "student",
+        &NewGradeOverride {
+            change: GradeChange::Adjust { points: -1.0 },
+            reason: "Late oral defence".to_string(),
+        },
+        &teacher,
+    )
+    .await?;
+    observed.push(student_grades(&repo, &module.uuid, &teacher).await?);
+    repo.insert_grade_override(
+        &module.uuid,
+        &assignment.uuid,
+        "student",
+        &NewGradeOverride {
+            change: GradeChange::Adjust { points: 0.5 },
+            reason: "Extra exercise".to_string(),
+        },
+        &teacher,
+    )
+    .await?;
+    observed.push(student_grades(&repo, &module.uuid, &teacher).await?);
+    repo.insert_grade_override(
+        &module.uuid,
+        &assignment.uuid,
+        "student",
+        &NewGradeOverride {
+            change: GradeChange::Reset,
+            reason: "Grader fixed".to_string(),
+        },
+        &teacher,
+    )
+    .await?;
+    observed.push(student_grades(&repo, &module.uuid, &teacher).await?);
+
+    pretty_assertions::assert_eq!(
+        observed,
+        vec![
+            (12.0, 10.0, Some("adjust".to_string())),
+            (15.0, 10.0, Some("set".to_string())),
+            (15.0, 12.0, Some("set".to_string())),
+            // Applied on top of the grade set, not of the automatic one
+            (14.0, 12.0, Some("adjust".to_string())),
+            // Consecutive adjustments add up
+            (14.5, 12.0, Some("adjust".to_string())),
+            (12.0, 12.0, Some("reset".to_string())),
+        ]
+    );
+    pretty_assertions::assert_eq!(
+        repo.find_grade_overrides(&module.uuid, &assignment.uuid, "student", &teacher)
+            .await?
+            .len(),
+        5
+    );
+    let unknown_student = repo
+        .insert_grade_override(
+            &module.uuid,
+            &assignment.uuid,
+            "unknown",
+            &NewGradeOverride {
+                change: GradeChange::Reset,
+                reason: "Unknown student".to_string(),
+            },
+            &teacher,
+        )
+        .await?;
+    assert!(unknown_student.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,626 @@
+use korekto::entities::{GradingRunFilter, GradingTaskScope, NewAssignmentBuilder, NewGradingTask};
+use korekto::github::webhook_models::{
+    Account, GhWebhookEvent, RepositoryWithOwner, WorkflowJob, WorkflowJobConclusion,
+    WorkflowJobEvent, WorkflowJobStatus,
+};
+use korekto::repository::grading_task::{GradingStatus, GradingTrigger};
+use korekto::repository::Repository;
+use korekto::service::webhook_models::{
+    RunnerEventOutcome, RunnerGradeDetails, RunnerGradePart, RunnerMetadata, RunnerPayload,
+    RunnerStatus,
+};
+use korekto::service::Service;
+use time::{Duration, OffsetDateTime};
+
+mod common;
+mod fixtures;
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn reserve_student_task_before_teacher_bulk_task() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (_, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["bulk-graded", "self-graded"]).await?;
+
+    for (student, trigger) in students
+        .iter()
+        .zip([GradingTrigger::TEACHER, GradingTrigger::STUDENT])
+    {
+        repo.upsert_grading_task(
+            &NewGradingTask::External {
+                assignment_uuid: assignment.uuid.clone(),
+                user_uuid: student.uuid.clone(),
+                trigger,
+                commit_ref: None,
+                submitted_at: None,
+            },
+            false,
+        )
+        .await?;
+    }
+
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction).await?;
+    transaction.commit().await?;
+
+    pretty_assertions::assert_eq!(reserved.len(), 1, "Number of reserved tasks");
+    pretty_assertions::assert_eq!(reserved[0].provider_login, "self-graded");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn ended_task_is_recorded_as_grading_run() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+
+    repo.upsert_grading_task(
+        &NewGradingTask::External {
+            assignment_uuid: assignment.uuid.clone(),
+            user_uuid: students[0].uuid.clone(),
+            trigger: GradingTrigger::STUDENT,
+            commit_ref: None,
+            submitted_at: None,
+        },
+        false,
+    )
+    .await?;
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction).await?;
+    transaction.commit().await?;
+    repo.delete_grading_task(&reserved[0].uuid, Some("boom".to_string()), None)
+        .await?;
+
+    let runs = repo
+        .get_grading_runs(&GradingRunFilter::default(), Some(&teacher), 1, 10)
+        .await?;
+
+    pretty_assertions::assert_eq!(runs.len(), 1, "Number of runs");
+    pretty_assertions::assert_eq!(runs[0].uuid, reserved[0].uuid);
+    pretty_assertions::assert_eq!(runs[0].trigger, "STUDENT");
+    pretty_assertions::assert_eq!(runs[0].last_status, "RESERVED");
+    pretty_assertions::assert_eq!(runs[0].end_status, "ERROR");
+    pretty_assertions::assert_eq!(runs[0].error.as_deref(), Some("boom"));
+    assert!(runs[0].reserved_at.is_some(), "reserved_at is recorded");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn cancelled_tasks_are_recorded_as_cancelled_runs() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+
+    repo.upsert_grading_task(
+        &NewGradingTask::External {
+            assignment_uuid: assignment.uuid.clone(),
+            user_uuid: students[0].uuid.clone(),
+            trigger: GradingTrigger::TEACHER,
+            commit_ref: None,
+            submitted_at: None,
+        },
+        false,
+    )
+    .await?;
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction).await?;
+    transaction.commit().await?;
+
+    let scope = GradingTaskScope {
+        assignment_uuid: Some(assignment.uuid.clone()),
+        ..GradingTaskScope::default()
+    };
+    let cancelled = repo.cancel_grading_tasks(&scope, Some(&teacher)).await?;
+
+    pretty_assertions::assert_eq!(cancelled.len(), 1, "Number of cancelled tasks");
+    pretty_assertions::assert_eq!(cancelled[0].uuid, reserved[0].uuid);
+    pretty_assertions::assert_eq!(cancelled[0].status, "RESERVED");
+    assert!(
+        repo.is_grading_cancelled(&reserved[0].uuid).await?,
+        "Late runner events are to be ignored"
+    );
+    let runs = repo
+        .get_grading_runs(&GradingRunFilter::default(), Some(&teacher), 1, 10)
+        .await?;
+    pretty_assertions::assert_eq!(runs[0].end_status, "CANCELLED");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn unlinked_repository_gradings_are_cancelled() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["deleting", "keeping"]).await?;
+    for student in &students {
+        repo.upsert_grading_task(
+            &NewGradingTask::External {
+                assignment_uuid: assignment.uuid.clone(),
+                user_uuid: student.uuid.clone(),
+                trigger: GradingTrigger::STUDENT,
+                commit_ref: None,
+                submitted_at: None,
+            },
+            false,
+        )
+        .await?;
+    }
+    let service: Service = repo.clone().into();
+
+    service.unlink_repos("deleting", Some(&["a1"])).await?;
+
+    let runs = repo
+        .get_grading_runs(&GradingRunFilter::default(), Some(&teacher), 1, 10)
+        .await?;
+    pretty_assertions::assert_eq!(runs.len(), 1, "Number of runs");
+    pretty_assertions::assert_eq!(runs[0].provider_login, "deleting");
+    pretty_assertions::assert_eq!(runs[0].end_status, "CANCELLED");
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 10, 0, &mut *transaction).await?;
+    transaction.commit().await?;
+    pretty_assertions::assert_eq!(reserved.len(), 1, "Number of reserved tasks");
+    pretty_assertions::assert_eq!(reserved[0].provider_login, "keeping");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn gradings_of_suspended_installation_wait() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (_, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    repo.upsert_grading_task(
+        &NewGradingTask::External {
+            assignment_uuid: assignment.uuid.clone(),
+            user_uuid: students[0].uuid.clone(),
+            trigger: GradingTrigger::STUDENT,
+            commit_ref: None,
+            submitted_at: None,
+        },
+        false,
+    )
+    .await?;
+
+    let mut reserved_counts = vec![];
+    for suspended in [true, false] {
+        repo.set_installation_suspended("42", suspended).await?;
+        let mut transaction = repo.start_transaction().await?;
+        let reserved =
+            Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction)
+                .await?;
+        transaction.commit().await?;
+        reserved_counts.push(reserved.len());
+    }
+
+    pretty_assertions::assert_eq!(reserved_counts, vec![0, 1]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn commit_ref_of_most_important_request_is_kept() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (_, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+
+    for (trigger, commit_ref) in [
+        (GradingTrigger::STUDENT, "student-sha"),
+        (GradingTrigger::TEACHER, "teacher-sha"),
+    ] {
+        repo.upsert_grading_task(
+            &NewGradingTask::External {
+                assignment_uuid: assignment.uuid.clone(),
+                user_uuid: students[0].uuid.clone(),
+                trigger,
+                commit_ref: Some(commit_ref.to_string()),
+                submitted_at: None,
+            },
+            false,
+        )
+        .await?;
+    }
+
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction).await?;
+    transaction.commit().await?;
+
+    pretty_assertions::assert_eq!(reserved.len(), 1, "Number of reserved tasks");
+    pretty_assertions::assert_eq!(reserved[0].commit_ref.as_deref(), Some("student-sha"));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn released_task_is_queued_again_without_counting_an_attempt() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (_, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    repo.upsert_grading_task(
+        &NewGradingTask::External {
+            assignment_uuid: assignment.uuid.clone(),
+            user_uuid: students[0].uuid.clone(),
+            trigger: GradingTrigger::STUDENT,
+            commit_ref: None,
+            submitted_at: None,
+        },
+        false,
+    )
+    .await?;
+
+    for _ in 0..2 {
+        let mut transaction = repo.start_transaction().await?;
+        let reserved =
+            Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction)
+                .await?;
+        pretty_assertions::assert_eq!(reserved.len(), 1, "Number of reserved tasks");
+        pretty_assertions::assert_eq!(reserved[0].attempts, 0);
+        let released =
+            Repository::release_grading_task_transact(&reserved[0].uuid, &mut *transaction).await?;
+        transaction.commit().await?;
+
+        pretty_assertions::assert_eq!(released.map(|task| task.status), Some("QUEUED".to_string()));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn queued_grading_is_relayed_to_subscribers() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (_, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let service = Service::from(repo.clone());
+    let mut events = service.events.subscribe();
+    let relay = tokio::spawn({
+        let service = service.clone();
+        async move { service.relay_grading_events().await }
+    });
+
+    // Queued again until the relay listens, the task being the same
+    let mut event = None;
+    for _ in 0..50 {
+        service
+            .queue_grading_task(
+                &NewGradingTask::External {
+                    assignment_uuid: assignment.uuid.clone(),
+                    user_uuid: students[0].uuid.clone(),
+                    trigger: GradingTrigger::STUDENT,
+                    commit_ref: None,
+                    submitted_at: None,
+                },
+                false,
+            )
+            .await?;
+        if let Ok(received) =
+            tokio::time::timeout(std::time::Duration::from_millis(100), events.recv()).await
+        {
+            event = Some(received?);
+            break;
+        }
+    }
+    relay.abort();
+
+    let event = event.expect("Relayed event");
+    pretty_assertions::assert_eq!(event.status, "QUEUED");
+    pretty_assertions::assert_eq!(event.assignment_id, assignment.uuid);
+    pretty_assertions::assert_eq!(event.user_uuid, students[0].uuid);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn runner_events_are_applied_once() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (_, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let service = Service::from(repo.clone());
+
+    repo.upsert_grading_task(
+        &NewGradingTask::External {
+            assignment_uuid: assignment.uuid.clone(),
+            user_uuid: students[0].uuid.clone(),
+            trigger: GradingTrigger::STUDENT,
+            commit_ref: None,
+            submitted_at: None,
+        },
+        false,
+    )
+    .await?;
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction).await?;
+    transaction.commit().await?;
+    let task_id = &reserved[0].uuid;
+
+    let completed = runner_payload(task_id, RunnerStatus::Completed);
+    pretty_assertions::assert_eq!(
+        service.on_runner_webhook(&completed).await?,
+        RunnerEventOutcome::Applied
+    );
+    pretty_assertions::assert_eq!(
+        service.on_runner_webhook(&completed).await?,
+        RunnerEventOutcome::Duplicate
+    );
+    pretty_assertions::assert_eq!(
+        service
+            .on_runner_webhook(&runner_payload(task_id, RunnerStatus::Started))
+            .await?,
+        RunnerEventOutcome::Stale
+    );
+    pretty_assertions::assert_eq!(
+        service
+            .on_runner_webhook(&runner_payload(
+                "00000000-0000-0000-0000-000000000000",
+                RunnerStatus::Failure
+            ))
+            .await?,
+        RunnerEventOutcome::UnknownTask
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn failed_workflow_job_ends_its_grading() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let service = Service::from(repo.clone());
+
+    repo.upsert_grading_task(
+        &NewGradingTask::External {
+            assignment_uuid: assignment.uuid.clone(),
+            user_uuid: students[0].uuid.clone(),
+            trigger: GradingTrigger::STUDENT,
+            commit_ref: None,
+            submitted_at: None,
+        },
+        false,
+    )
+    .await?;
+    let mut transaction = repo.start_transaction().await?;
+    let reserved =
+        Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction).await?;
+    Repository::set_grading_task_runner_transact(
+        &reserved[0].uuid,
+        "org/runner",
+        &mut *transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    let job_name = format!("grade {}", reserved[0].uuid);
+
+    // Same job name in another repository than the runner one
+    service
+        .on_webhook(workflow_job_event("other", &job_name))
+        .await?;
+    let runs = repo
+        .get_grading_runs(&GradingRunFilter::default(), Some(&teacher), 1, 10)
+        .await?;
+    pretty_assertions::assert_eq!(runs.len(), 0, "Number of runs");
+
+    service
+        .on_webhook(workflow_job_event("runner", &job_name))
+        .await?;
+    let runs = repo
+        .get_grading_runs(&GradingRunFilter::default(), Some(&teacher), 1, 10)
+        .await?;
+    pretty_assertions::assert_eq!(runs.len(), 1, "Number of runs");
+    pretty_assertions::assert_eq!(runs[0].end_status, "ERROR");
+    pretty_assertions::assert_eq!(runs[0].error.as_deref(), Some("GitHub runner job failed"));
+    pretty_assertions::assert_eq!(
+        runs[0].full_log_url.as_deref(),
+        Some("https://github.com/org/runner/actions/runs/1/job/2")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn teacher_grading_after_deadline_is_late_unless_submitted_before() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let service = Service::from(repo.clone());
+    let module = &repo.find_modules(&teacher).await?[0];
+    let stop = OffsetDateTime::now_utc() - Duration::hours(50);
+    service
+        .update_assignment(
+            &module.uuid,
+            &assignment.uuid,
+            &NewAssignmentBuilder::default()
+                .name("a1")
+                .factor_percentage(100)
+                .repository_name("a1")
+                .stop(stop)
+                .late_penalty_percentage_per_day(10)
+                .build()?,
+            &teacher,
+        )
+        .await?;
+
+    let mut penalties = vec![];
+    for submitted_at in [None, Some(stop)] {
+        repo.upsert_grading_task(
+            &NewGradingTask::External {
+                assignment_uuid: assignment.uuid.clone(),
+                user_uuid: students[0].uuid.clone(),
+                trigger: GradingTrigger::TEACHER,
+                commit_ref: None,
+                submitted_at,
+            },
+            false,
+        )
+        .await?;
+        let mut transaction = repo.start_transaction().await?;
+        let reserved =
+            Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction)
+                .await?;
+        transaction.commit().await?;
+        service
+            .on_runner_webhook(&runner_payload(&reserved[0].uuid, RunnerStatus::Completed))
+            .await?;
+        let mut transaction = repo.start_transaction().await?;
+        let rules = Repository::find_grading_rules_transact(
+            reserved[0].user_assignment_id,
+            &mut *transaction,
+        )
+        .await?;
+        transaction.commit().await?;
+        penalties.extend(rules.grades_history.0.last().map(|g| g.penalty_percentage));
+    }
+
+    pretty_assertions::assert_eq!(penalties, vec![30, 0]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn grade_received_after_timeout_is_recorded_only_if_accepted() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+    let (teacher, assignment, students) =
+        fixtures::create_assignment_with_students(&repo, &["student"]).await?;
+    let service = Service::from(repo.clone());
+    let module = &repo.find_modules(&teacher).await?[0];
+
+    let mut results = vec![];
+    for accept_grades_after_timeout in [false, true] {
+        service
+            .update_assignment(
+                &module.uuid,
+                &assignment.uuid,
+                &NewAssignmentBuilder::default()
+                    .name("a1")
+                    .factor_percentage(100)
+                    .repository_name("a1")
+                    .accept_grades_after_timeout(accept_grades_after_timeout)
+                    .build()?,
+                &teacher,
+            )
+            .await?;
+        repo.upsert_grading_task(
+            &NewGradingTask::External {
+                assignment_uuid: assignment.uuid.clone(),
+                user_uuid: students[0].uuid.clone(),
+                trigger: GradingTrigger::STUDENT,
+                commit_ref: None,
+                submitted_at: None,
+            },
+            false,
+        )
+        .await?;
+        let mut transaction = repo.start_transaction().await?;
+        let reserved =
+            Repository::reserve_grading_tasks_to_execute_transact(0, 1, 0, &mut *transaction)
+                .await?;
+        transaction.commit().await?;
+        let timed_out = repo
+            .timeout_grading_tasks(&GradingStatus::RESERVED, 0)
+            .await?;
+        pretty_assertions::assert_eq!(timed_out.len(), 1, "Number of timed out tasks");
+
+        let outcome = service
+            .on_runner_webhook(&runner_payload(&reserved[0].uuid, RunnerStatus::Completed))
+            .await?;
+
+        let runs = repo
+            .get_grading_runs(&GradingRunFilter::default(), Some(&teacher), 1, 10)
+            .await?;
+        let run = runs
+            .iter()
+            .find(|run| run.uuid == reserved[0].uuid)
+            .expect("Timed out run");
+        let mut transaction = repo.start_transaction().await?;
+        let rules = Repository::find_grading_rules_transact(
+            reserved[0].user_assignment_id,
+            &mut *transaction,
+        )
+        .await?;
+        transaction.commit().await?;
+        results.push((
+            outcome,
+            run.end_status.clone(),
+            rules.grades_history.0.len(),
+        ));
+    }
+
+    pretty_assertions::assert_eq!(
+        results,
+        vec![
+            (RunnerEventOutcome::Stale, "TIMEOUT".to_string(), 0),
+            (RunnerEventOutcome::Applied, "SUCCESSFUL".to_string(), 1),
+        ]
+    );
+
+    Ok(())
+}
+
+fn workflow_job_event(name: &str, job_name: &str) -> GhWebhookEvent {
+    let repository = format!("org/{name}");
+    GhWebhookEvent::WorkflowJob(WorkflowJobEvent {
+        workflow_job: WorkflowJob {
+            run_id: 1,
+            name: job_name.to_string(),
+            html_url: format!("https://github.com/{repository}/actions/runs/1/job/2"),
+            status: WorkflowJobStatus::Completed,
+            conclusion: Some(WorkflowJobConclusion::Failure),
+            created_at: OffsetDateTime::now_utc(),
+            completed_at: Some(OffsetDateTime::now_utc()),
+        },
+        repository: RepositoryWithOwner {
+            name: name.to_string(),
+            full_name: repository,
+            private: false,
+            owner: Account {
+                login: "org".to_string(),
+            },
+            default_branch: Some("main".to_string()),
+        },
+    })
+}
+
+fn runner_payload(task_id: &str, status: RunnerStatus) -> RunnerPayload {
+    let details = (status == RunnerStatus::Completed).then(|| RunnerGradeDetails {
+        grade: 2.0,
+        max_grade: 2.0,
+        parts: vec![RunnerGradePart {
+            id: "Part 1".to_string(),
+            grade: 2.0,
+            max_grade: Some(2.0),
+            comments: vec![],
+        }],
+    });
+    RunnerPayload {
+        event_id: None,
+        status,
+        student_login: "student".to_string(),
+        grader_repo: "org/grader".to_string(),
+        task_id: task_id.to_string(),
+        full_log_url: "https://github.com/org/runner/actions/runs/1".to_string(),
+        details,
+        progress: None,
+        metadata: RunnerMetadata {
+            commit_id: None,
+            short_commit_id: None,
+            commit_url: None,
+        },
+    }
+}
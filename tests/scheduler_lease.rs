@@ -0,0 +1,77 @@
+use korekto::repository::Repository;
+use korekto::scheduler::job::JobRunStatus;
+
+mod common;
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn only_one_instance_holds_the_scheduler_lock() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+
+    let mut leader = repo
+        .try_acquire_scheduler_lock()
+        .await?
+        .expect("Free lock is acquired");
+    assert!(Repository::is_scheduler_lock_held(&mut leader).await);
+    assert!(
+        repo.try_acquire_scheduler_lock().await?.is_none(),
+        "Lock already held by another connection"
+    );
+
+    // A dead leader releases the lock along with its connection
+    drop(leader.detach());
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let new_leader = repo
+        .try_acquire_scheduler_lock()
+        .await?
+        .expect("Released lock is acquired");
+    // Not returned to the pool, other tests would otherwise reuse a connection holding the lock
+    drop(new_leader.detach());
+
+    repo.record_scheduler_tick("instance-a").await?;
+    repo.record_scheduler_tick("instance-b").await?;
+    let lease = repo.find_scheduler_lease().await?.expect("Lease recorded");
+    pretty_assertions::assert_eq!(lease.holder, "instance-b");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn jobs_left_running_by_a_previous_leader_are_failed() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+
+    repo.start_scheduled_job("resync_github").await?;
+    repo.start_scheduled_job("purge_runner_events").await?;
+    repo.start_scheduled_job("purge_webhook_deliveries").await?;
+    repo.end_scheduled_job("purge_webhook_deliveries", JobRunStatus::Successful, None)
+        .await?;
+
+    let interrupted = repo
+        .fail_interrupted_scheduled_jobs(&["purge_runner_events"])
+        .await?;
+
+    pretty_assertions::assert_eq!(interrupted, 1);
+    let statuses: Vec<(String, Option<String>)> = repo
+        .find_scheduled_jobs()
+        .await?
+        .into_iter()
+        .map(|job| (job.name, job.last_status))
+        .collect();
+    pretty_assertions::assert_eq!(
+        statuses,
+        vec![
+            (
+                "purge_runner_events".to_string(),
+                Some("running".to_string())
+            ),
+            (
+                "purge_webhook_deliveries".to_string(),
+                Some("successful".to_string())
+            ),
+            ("resync_github".to_string(), Some("failed".to_string())),
+        ]
+    );
+
+    Ok(())
+}
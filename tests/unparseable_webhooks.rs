@@ -1,5 +1,5 @@
 use assert_matches2::assert_matches;
-use korekto::service::dtos::{Page, PaginationQuery};
+use korekto::service::dtos::{Page, PaginationQuery, WebhookReplayOutcome};
 use korekto::service::Service;
 
 mod common;
@@ -32,3 +32,36 @@ async fn upsert_user_insert_it_when_missing() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn replay_deletes_only_webhooks_now_parseable() -> anyhow::Result<()> {
+    let service: Service = common::init_repo().await?.into();
+    let push_payload = std::fs::read_to_string("test_files/webhook_push_payload.json")?;
+    service
+        .repo
+        .insert_unparseable_webhook("github", "push", &push_payload, "missing field")
+        .await?;
+    service
+        .repo
+        .insert_unparseable_webhook("github", "push", "{}", "missing field `repository`")
+        .await?;
+
+    let results = service.replay_unparseable_webhooks(None).await?;
+
+    let outcomes: Vec<WebhookReplayOutcome> = results.iter().map(|result| result.outcome).collect();
+    pretty_assertions::assert_eq!(
+        outcomes,
+        vec![
+            WebhookReplayOutcome::Replayed,
+            WebhookReplayOutcome::Unparseable
+        ]
+    );
+    let page = service
+        .get_unparseable_webhooks(&PaginationQuery::new(1, 10))
+        .await?;
+    pretty_assertions::assert_eq!(page.data.len(), 1, "Remaining webhooks");
+    pretty_assertions::assert_eq!(page.data[0].id, results[1].id);
+
+    Ok(())
+}
@@ -112,6 +112,7 @@ async fn setup_data(service: &Service) -> anyhow::Result<(User, Module)> {
                     messages: vec![],
                 })
                 .collect(),
+            submitted_at: None,
         };
 
         service
@@ -265,6 +266,7 @@ async fn get_user_assignment_query() -> anyhow::Result<()> {
             .repository_url("https://github.com/test-login/a1")
             .factor_percentage(ASSIGNMENT_STATES[0].factor)
             .normalized_grade(17.17)
+            .raw_normalized_grade(17.17)
             .locked(false)
             .queue_due_to(0)
             .latest_run(
@@ -0,0 +1,82 @@
+use korekto::entities::WebhookDeliveryFilter;
+use korekto::service::dtos::PaginationQuery;
+use korekto::service::webhook_models::WebhookDeliveryOutcome;
+use korekto::service::Service;
+use pretty_assertions::assert_eq;
+
+mod common;
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn redelivered_webhooks_are_processed_once() -> anyhow::Result<()> {
+    let service: Service = common::init_repo().await?.into();
+    let push_payload = std::fs::read_to_string("test_files/webhook_push_payload.json")?;
+
+    let first = service
+        .on_github_delivery("delivery-1", "push", &push_payload)
+        .await?;
+    let redelivery = service
+        .on_github_delivery("delivery-1", "push", &push_payload)
+        .await?;
+    let unparseable = service
+        .on_github_delivery("delivery-2", "push", "{}")
+        .await?;
+
+    assert_eq!(first, WebhookDeliveryOutcome::Processed);
+    assert_eq!(redelivery, WebhookDeliveryOutcome::Duplicate);
+    assert_eq!(unparseable, WebhookDeliveryOutcome::Unparseable);
+    let processed = service
+        .get_webhook_deliveries(
+            &WebhookDeliveryFilter {
+                outcome: Some("processed".to_string()),
+                ..WebhookDeliveryFilter::default()
+            },
+            &PaginationQuery::new(1, 10),
+        )
+        .await?;
+    assert_eq!(processed.total_count, 1);
+    assert_eq!(processed.data[0].delivery_id, "delivery-1");
+    assert_eq!(processed.data[0].redeliveries, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn forged_delivery_does_not_prevent_processing() -> anyhow::Result<()> {
+    let service: Service = common::init_repo().await?.into();
+    let push_payload = std::fs::read_to_string("test_files/webhook_push_payload.json")?;
+    service
+        .repo
+        .insert_unauthenticated_webhook_delivery("delivery-1", "push", "Invalid signature")
+        .await?;
+
+    let outcome = service
+        .on_github_delivery("delivery-1", "push", &push_payload)
+        .await?;
+
+    assert_eq!(outcome, WebhookDeliveryOutcome::Processed);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "tests-with-docker"), ignore)]
+async fn delivery_left_received_is_claimed_again_after_timeout() -> anyhow::Result<()> {
+    let repo = common::init_repo().await?;
+
+    let first = repo
+        .claim_webhook_delivery("delivery-1", "push", 60)
+        .await?;
+    let while_processing = repo
+        .claim_webhook_delivery("delivery-1", "push", 60)
+        .await?;
+    let after_timeout = repo.claim_webhook_delivery("delivery-1", "push", 0).await?;
+
+    assert_eq!(
+        (first, while_processing, after_timeout),
+        (true, false, true)
+    );
+
+    Ok(())
+}